@@ -0,0 +1,920 @@
+use log::info;
+use teloxide::prelude::*;
+use teloxide::types::{
+    ChatFullInfo, ChatMember, ChatPermissions, InlineKeyboardMarkup, MessageId, ReplyParameters,
+};
+
+use crate::outbound::Priority;
+use crate::permissions::{self, Right};
+use crate::retry::send_with_priority;
+use crate::{delete_message_later, Result, ThrottledBot};
+
+// Действия бота в чатах: повторы, приоритет в очереди исходящих, автоудаление
+// ответов, проверка прав бота и запись в журнал `audit` собраны здесь, чтобы
+// обработчики не повторяли сетевой код. `action_name` попадает в логи повторов.
+
+pub async fn send(
+    bot: &ThrottledBot,
+    chat_id: ChatId,
+    text: String,
+    action_name: &str,
+) -> Result<Message> {
+    send_as(bot, Priority::Notification, chat_id, text, action_name).await
+}
+
+// Ответ, который удаляется через telegram.auto_delete_secs
+pub async fn reply_ephemeral(
+    bot: &ThrottledBot,
+    chat_id: ChatId,
+    text: String,
+    action_name: &str,
+) -> Result<Message> {
+    let message = send_as(bot, Priority::Notification, chat_id, text, action_name).await?;
+    delete_message_later(chat_id, message.id);
+    Ok(message)
+}
+
+// Подсказка или предупреждение участнику; при перегрузке очереди не отправляется
+pub async fn prompt_ephemeral(
+    bot: &ThrottledBot,
+    chat_id: ChatId,
+    text: String,
+    action_name: &str,
+) -> Result<Message> {
+    let message = send_as(bot, Priority::Prompt, chat_id, text, action_name).await?;
+    delete_message_later(chat_id, message.id);
+    Ok(message)
+}
+
+// Подсказка с кнопками; как и prompt_ephemeral, при перегрузке не отправляется
+pub async fn prompt_ephemeral_with_keyboard(
+    bot: &ThrottledBot,
+    chat_id: ChatId,
+    text: String,
+    keyboard: InlineKeyboardMarkup,
+    action_name: &str,
+) -> Result<Message> {
+    let message = send_message_as(
+        bot,
+        Priority::Prompt,
+        chat_id,
+        text,
+        Some(keyboard),
+        None,
+        action_name,
+    )
+    .await?;
+    delete_message_later(chat_id, message.id);
+    Ok(message)
+}
+
+pub async fn send_with_keyboard(
+    bot: &ThrottledBot,
+    chat_id: ChatId,
+    text: String,
+    keyboard: InlineKeyboardMarkup,
+    action_name: &str,
+) -> Result<Message> {
+    send_message_as(
+        bot,
+        Priority::Notification,
+        chat_id,
+        text,
+        Some(keyboard),
+        None,
+        action_name,
+    )
+    .await
+}
+
+pub async fn reply(
+    bot: &ThrottledBot,
+    chat_id: ChatId,
+    reply_to: MessageId,
+    text: String,
+    action_name: &str,
+) -> Result<Message> {
+    send_message_as(
+        bot,
+        Priority::Notification,
+        chat_id,
+        text,
+        None,
+        Some(reply_to),
+        action_name,
+    )
+    .await
+}
+
+pub async fn reply_with_keyboard(
+    bot: &ThrottledBot,
+    chat_id: ChatId,
+    reply_to: MessageId,
+    text: String,
+    keyboard: InlineKeyboardMarkup,
+    action_name: &str,
+) -> Result<Message> {
+    send_message_as(
+        bot,
+        Priority::Notification,
+        chat_id,
+        text,
+        Some(keyboard),
+        Some(reply_to),
+        action_name,
+    )
+    .await
+}
+
+async fn send_as(
+    bot: &ThrottledBot,
+    priority: Priority,
+    chat_id: ChatId,
+    text: String,
+    action_name: &str,
+) -> Result<Message> {
+    send_message_as(bot, priority, chat_id, text, None, None, action_name).await
+}
+
+async fn send_message_as(
+    bot: &ThrottledBot,
+    priority: Priority,
+    chat_id: ChatId,
+    text: String,
+    keyboard: Option<InlineKeyboardMarkup>,
+    reply_to: Option<MessageId>,
+    action_name: &str,
+) -> Result<Message> {
+    let bot = bot.clone();
+    send_with_priority(
+        priority,
+        move || {
+            let mut request = bot.send_message(chat_id, text.clone());
+            if let Some(keyboard) = keyboard.clone() {
+                request = request.reply_markup(keyboard);
+            }
+            if let Some(reply_to) = reply_to {
+                request = request.reply_parameters(ReplyParameters::new(reply_to));
+            }
+            Box::pin(async move { request.await.map_err(|e| e.into()) })
+        },
+        action_name,
+    )
+    .await
+}
+
+// Новый текст сообщения бота; кнопки под ним при этом убираются
+pub async fn edit_text(
+    bot: &ThrottledBot,
+    chat_id: ChatId,
+    message_id: MessageId,
+    text: String,
+    action_name: &str,
+) -> Result<()> {
+    edit_message(bot, chat_id, message_id, text, None, action_name).await
+}
+
+pub async fn edit_text_with_keyboard(
+    bot: &ThrottledBot,
+    chat_id: ChatId,
+    message_id: MessageId,
+    text: String,
+    keyboard: InlineKeyboardMarkup,
+    action_name: &str,
+) -> Result<()> {
+    edit_message(bot, chat_id, message_id, text, Some(keyboard), action_name).await
+}
+
+async fn edit_message(
+    bot: &ThrottledBot,
+    chat_id: ChatId,
+    message_id: MessageId,
+    text: String,
+    keyboard: Option<InlineKeyboardMarkup>,
+    action_name: &str,
+) -> Result<()> {
+    let bot = bot.clone();
+    send_with_priority(
+        Priority::Notification,
+        move || {
+            let mut request = bot.edit_message_text(chat_id, message_id, text.clone());
+            if let Some(keyboard) = keyboard.clone() {
+                request = request.reply_markup(keyboard);
+            }
+            Box::pin(async move {
+                request.await?;
+                Ok(())
+            })
+        },
+        action_name,
+    )
+    .await
+}
+
+pub async fn answer_callback(
+    bot: &ThrottledBot,
+    query_id: String,
+    text: String,
+    action_name: &str,
+) -> Result<()> {
+    let bot = bot.clone();
+    send_with_priority(
+        Priority::Notification,
+        move || {
+            let request = bot
+                .answer_callback_query(query_id.clone())
+                .text(text.clone());
+            Box::pin(async move {
+                request.await?;
+                Ok(())
+            })
+        },
+        action_name,
+    )
+    .await
+}
+
+// Пересылка с подписью «переслано от», по ней видно автора и исходный чат
+pub async fn forward(
+    bot: &ThrottledBot,
+    chat_id: ChatId,
+    from_chat_id: ChatId,
+    message_id: MessageId,
+    action_name: &str,
+) -> Result<Message> {
+    let bot = bot.clone();
+    send_with_priority(
+        Priority::Notification,
+        move || {
+            let request = bot.forward_message(chat_id, from_chat_id, message_id);
+            Box::pin(async move { request.await.map_err(|e| e.into()) })
+        },
+        action_name,
+    )
+    .await
+}
+
+// Копия без подписи «переслано от» в ответ на сообщение `reply_to`
+pub async fn copy_as_reply(
+    bot: &ThrottledBot,
+    chat_id: ChatId,
+    reply_to: MessageId,
+    from_chat_id: ChatId,
+    message_id: MessageId,
+    action_name: &str,
+) -> Result<MessageId> {
+    let bot = bot.clone();
+    send_with_priority(
+        Priority::Notification,
+        move || {
+            let request = bot
+                .copy_message(chat_id, from_chat_id, message_id)
+                .reply_parameters(ReplyParameters::new(reply_to));
+            Box::pin(async move { request.await.map_err(|e| e.into()) })
+        },
+        action_name,
+    )
+    .await
+}
+
+pub async fn delete(
+    bot: &ThrottledBot,
+    chat_id: ChatId,
+    message_id: MessageId,
+    action_name: &str,
+) -> Result<()> {
+    delete_as(bot, Priority::Moderation, chat_id, message_id, action_name).await
+}
+
+// Уборка команд из чата: не обгоняет удаление спама и баны в очереди исходящих
+pub async fn delete_command(
+    bot: &ThrottledBot,
+    chat_id: ChatId,
+    message_id: MessageId,
+    action_name: &str,
+) -> Result<()> {
+    delete_as(
+        bot,
+        Priority::Notification,
+        chat_id,
+        message_id,
+        action_name,
+    )
+    .await
+}
+
+async fn delete_as(
+    bot: &ThrottledBot,
+    priority: Priority,
+    chat_id: ChatId,
+    message_id: MessageId,
+    action_name: &str,
+) -> Result<()> {
+    permissions::require(chat_id, Right::Delete)?;
+    let bot = bot.clone();
+    send_with_priority(
+        priority,
+        move || {
+            let bot = bot.clone();
+            Box::pin(async move {
+                bot.delete_message(chat_id, message_id).await?;
+                Ok(())
+            })
+        },
+        action_name,
+    )
+    .await?;
+    info!(
+        target: "audit",
+        chat_id = chat_id.0,
+        action = action_name;
+        "Deleted message {} in chat {} ({})", message_id, chat_id, action_name
+    );
+    Ok(())
+}
+
+// Пачка удалений из очереди: в основном собственные сообщения бота, которым
+// право удаления не нужно, поэтому оно не проверяется
+pub async fn delete_messages(
+    bot: &ThrottledBot,
+    chat_id: ChatId,
+    message_ids: Vec<MessageId>,
+    action_name: &str,
+) -> Result<()> {
+    let count = message_ids.len();
+    let bot = bot.clone();
+    send_with_priority(
+        Priority::Notification,
+        move || {
+            let request = bot.delete_messages(chat_id, message_ids.clone());
+            Box::pin(async move {
+                request.await?;
+                Ok(())
+            })
+        },
+        action_name,
+    )
+    .await?;
+    info!(
+        target: "audit",
+        chat_id = chat_id.0,
+        action = action_name;
+        "Deleted {} messages in chat {} ({})", count, chat_id, action_name
+    );
+    Ok(())
+}
+
+pub async fn restrict(
+    bot: &ThrottledBot,
+    chat_id: ChatId,
+    user_id: UserId,
+    permissions: ChatPermissions,
+    action_name: &str,
+) -> Result<()> {
+    restrict_as(
+        bot,
+        Priority::Moderation,
+        chat_id,
+        user_id,
+        permissions,
+        action_name,
+    )
+    .await
+}
+
+// Возврат прав группы подтверждённому участнику: не срочнее уведомлений
+pub async fn unrestrict(
+    bot: &ThrottledBot,
+    chat_id: ChatId,
+    user_id: UserId,
+    permissions: ChatPermissions,
+    action_name: &str,
+) -> Result<()> {
+    restrict_as(
+        bot,
+        Priority::Notification,
+        chat_id,
+        user_id,
+        permissions,
+        action_name,
+    )
+    .await
+}
+
+async fn restrict_as(
+    bot: &ThrottledBot,
+    priority: Priority,
+    chat_id: ChatId,
+    user_id: UserId,
+    permissions: ChatPermissions,
+    action_name: &str,
+) -> Result<()> {
+    permissions::require(chat_id, Right::Restrict)?;
+    let bot = bot.clone();
+    let requested = permissions.clone();
+    send_with_priority(
+        priority,
+        move || {
+            let bot = bot.clone();
+            let permissions = permissions.clone();
+            Box::pin(async move {
+                bot.restrict_chat_member(chat_id, user_id, permissions)
+                    .await?;
+                Ok(())
+            })
+        },
+        action_name,
+    )
+    .await?;
+    info!(
+        target: "audit",
+        chat_id = chat_id.0,
+        user_id = user_id.0,
+        action = action_name;
+        "Set permissions of user {} in chat {} to {:?} ({})",
+        user_id,
+        chat_id,
+        requested,
+        action_name
+    );
+    Ok(())
+}
+
+pub async fn ban(
+    bot: &ThrottledBot,
+    chat_id: ChatId,
+    user_id: UserId,
+    action_name: &str,
+) -> Result<()> {
+    permissions::require(chat_id, Right::Ban)?;
+    let bot = bot.clone();
+    send_with_priority(
+        Priority::Moderation,
+        move || {
+            let bot = bot.clone();
+            Box::pin(async move {
+                bot.ban_chat_member(chat_id, user_id).await?;
+                Ok(())
+            })
+        },
+        action_name,
+    )
+    .await?;
+    info!(
+        target: "audit",
+        chat_id = chat_id.0,
+        user_id = user_id.0,
+        action = action_name;
+        "Banned user {} in chat {} ({})", user_id, chat_id, action_name
+    );
+    Ok(())
+}
+
+// Пользователь исключается баном и разбаном. Разбан — вторая половина того же
+// действия, поэтому идёт с приоритетом модерации
+pub async fn unban_kicked(
+    bot: &ThrottledBot,
+    chat_id: ChatId,
+    user_id: UserId,
+    action_name: &str,
+) -> Result<()> {
+    unban_as(bot, Priority::Moderation, chat_id, user_id, action_name).await
+}
+
+pub async fn unban(
+    bot: &ThrottledBot,
+    chat_id: ChatId,
+    user_id: UserId,
+    action_name: &str,
+) -> Result<()> {
+    unban_as(bot, Priority::Notification, chat_id, user_id, action_name).await
+}
+
+async fn unban_as(
+    bot: &ThrottledBot,
+    priority: Priority,
+    chat_id: ChatId,
+    user_id: UserId,
+    action_name: &str,
+) -> Result<()> {
+    let bot = bot.clone();
+    send_with_priority(
+        priority,
+        move || {
+            let bot = bot.clone();
+            Box::pin(async move {
+                bot.unban_chat_member(chat_id, user_id)
+                    .only_if_banned(true)
+                    .await?;
+                Ok(())
+            })
+        },
+        action_name,
+    )
+    .await?;
+    info!(
+        target: "audit",
+        chat_id = chat_id.0,
+        user_id = user_id.0,
+        action = action_name;
+        "Unbanned user {} in chat {} ({})", user_id, chat_id, action_name
+    );
+    Ok(())
+}
+
+pub async fn ban_sender_chat(
+    bot: &ThrottledBot,
+    chat_id: ChatId,
+    sender_chat_id: ChatId,
+    action_name: &str,
+) -> Result<()> {
+    permissions::require(chat_id, Right::Ban)?;
+    let bot = bot.clone();
+    send_with_priority(
+        Priority::Moderation,
+        move || {
+            let bot = bot.clone();
+            Box::pin(async move {
+                bot.ban_chat_sender_chat(chat_id, sender_chat_id).await?;
+                Ok(())
+            })
+        },
+        action_name,
+    )
+    .await?;
+    info!(
+        target: "audit",
+        chat_id = chat_id.0,
+        action = action_name;
+        "Banned sender chat {} in chat {} ({})", sender_chat_id, chat_id, action_name
+    );
+    Ok(())
+}
+
+pub async fn unban_sender_chat(
+    bot: &ThrottledBot,
+    chat_id: ChatId,
+    sender_chat_id: ChatId,
+    action_name: &str,
+) -> Result<()> {
+    let bot = bot.clone();
+    send_with_priority(
+        Priority::Notification,
+        move || {
+            let bot = bot.clone();
+            Box::pin(async move {
+                bot.unban_chat_sender_chat(chat_id, sender_chat_id).await?;
+                Ok(())
+            })
+        },
+        action_name,
+    )
+    .await?;
+    info!(
+        target: "audit",
+        chat_id = chat_id.0,
+        action = action_name;
+        "Unbanned sender chat {} in chat {} ({})", sender_chat_id, chat_id, action_name
+    );
+    Ok(())
+}
+
+// Цель глобального бана: отрицательный ID — канал, положительный — пользователь
+pub async fn ban_in_chat(
+    bot: &ThrottledBot,
+    chat_id: ChatId,
+    id: i64,
+    action_name: &str,
+) -> Result<()> {
+    if id < 0 {
+        ban_sender_chat(bot, chat_id, ChatId(id), action_name).await
+    } else {
+        ban(bot, chat_id, UserId(id as u64), action_name).await
+    }
+}
+
+pub async fn unban_in_chat(
+    bot: &ThrottledBot,
+    chat_id: ChatId,
+    id: i64,
+    action_name: &str,
+) -> Result<()> {
+    if id < 0 {
+        unban_sender_chat(bot, chat_id, ChatId(id), action_name).await
+    } else {
+        unban(bot, chat_id, UserId(id as u64), action_name).await
+    }
+}
+
+// Права группы для всех участников: закрытие группы при рейде
+pub async fn lock_chat(bot: &ThrottledBot, chat_id: ChatId, action_name: &str) -> Result<()> {
+    set_chat_permissions_as(
+        bot,
+        Priority::Moderation,
+        chat_id,
+        ChatPermissions::empty(),
+        action_name,
+    )
+    .await
+}
+
+// Возврат прав группы после рейда: не срочнее уведомлений
+pub async fn set_chat_permissions(
+    bot: &ThrottledBot,
+    chat_id: ChatId,
+    permissions: ChatPermissions,
+    action_name: &str,
+) -> Result<()> {
+    set_chat_permissions_as(
+        bot,
+        Priority::Notification,
+        chat_id,
+        permissions,
+        action_name,
+    )
+    .await
+}
+
+async fn set_chat_permissions_as(
+    bot: &ThrottledBot,
+    priority: Priority,
+    chat_id: ChatId,
+    permissions: ChatPermissions,
+    action_name: &str,
+) -> Result<()> {
+    permissions::require(chat_id, Right::Restrict)?;
+    let bot = bot.clone();
+    let requested = permissions.clone();
+    send_with_priority(
+        priority,
+        move || {
+            let request = bot.set_chat_permissions(chat_id, permissions.clone());
+            Box::pin(async move {
+                request.await?;
+                Ok(())
+            })
+        },
+        action_name,
+    )
+    .await?;
+    info!(
+        target: "audit",
+        chat_id = chat_id.0,
+        action = action_name;
+        "Set default permissions in chat {} to {:?} ({})", chat_id, requested, action_name
+    );
+    Ok(())
+}
+
+pub async fn leave_chat(bot: &ThrottledBot, chat_id: ChatId, action_name: &str) -> Result<()> {
+    let bot = bot.clone();
+    send_with_priority(
+        Priority::Notification,
+        move || {
+            let request = bot.leave_chat(chat_id);
+            Box::pin(async move {
+                request.await?;
+                Ok(())
+            })
+        },
+        action_name,
+    )
+    .await?;
+    info!(
+        target: "audit",
+        chat_id = chat_id.0,
+        action = action_name;
+        "Left chat {} ({})", chat_id, action_name
+    );
+    Ok(())
+}
+
+pub async fn get_chat(
+    bot: &ThrottledBot,
+    chat_id: ChatId,
+    action_name: &str,
+) -> Result<ChatFullInfo> {
+    let bot = bot.clone();
+    send_with_priority(
+        Priority::Notification,
+        move || {
+            let request = bot.get_chat(chat_id);
+            Box::pin(async move { request.await.map_err(|e| e.into()) })
+        },
+        action_name,
+    )
+    .await
+}
+
+pub async fn get_chat_member(
+    bot: &ThrottledBot,
+    chat_id: ChatId,
+    user_id: UserId,
+    action_name: &str,
+) -> Result<ChatMember> {
+    let bot = bot.clone();
+    send_with_priority(
+        Priority::Notification,
+        move || {
+            let bot = bot.clone();
+            Box::pin(async move {
+                bot.get_chat_member(chat_id, user_id)
+                    .await
+                    .map_err(|e| e.into())
+            })
+        },
+        action_name,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use teloxide::types::{InlineKeyboardButton, MessageId};
+
+    use super::*;
+    use crate::deletions::DeletionQueue;
+    use crate::test_api::{sent_message, FakeBotApi};
+    use crate::DELETION_QUEUE;
+
+    const CHAT: ChatId = ChatId(-100123);
+
+    fn deletion_queue() -> &'static DeletionQueue {
+        DELETION_QUEUE.get_or_init(|| {
+            let path = std::env::temp_dir()
+                .join(format!("deletion_queue_test_{}.txt", std::process::id()));
+            let _ = std::fs::remove_file(&path);
+            DeletionQueue::load(path.to_str().unwrap())
+        })
+    }
+
+    #[tokio::test]
+    async fn reply_ephemeral_sends_and_schedules_deletion() {
+        let api = FakeBotApi::start().await;
+        api.respond("sendMessage", sent_message(CHAT.0, 77, "hello"))
+            .await;
+        let queue = deletion_queue();
+
+        let sent = reply_ephemeral(&api.bot(), CHAT, "hello".to_string(), "test reply")
+            .await
+            .unwrap();
+
+        assert_eq!(sent.id, MessageId(77));
+        let requests = api.requests("sendMessage").await;
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0]["chat_id"], json!(CHAT.0));
+        assert_eq!(requests[0]["text"], json!("hello"));
+        let scheduled = queue.due(i64::MAX);
+        assert!(scheduled
+            .iter()
+            .any(|(chat, ids)| *chat == CHAT && ids.contains(&MessageId(77))));
+    }
+
+    #[tokio::test]
+    async fn keyboards_and_replies_are_attached() {
+        let api = FakeBotApi::start().await;
+        api.respond("sendMessage", sent_message(CHAT.0, 78, "record"))
+            .await;
+        api.respond("editMessageText", sent_message(CHAT.0, 78, "done"))
+            .await;
+        api.respond("answerCallbackQuery", json!(true)).await;
+        let bot = api.bot();
+        let keyboard =
+            InlineKeyboardMarkup::new([[InlineKeyboardButton::callback("Undo", "undo:1")]]);
+
+        reply_with_keyboard(
+            &bot,
+            CHAT,
+            MessageId(5),
+            "record".to_string(),
+            keyboard.clone(),
+            "test reply",
+        )
+        .await
+        .unwrap();
+        edit_text(&bot, CHAT, MessageId(78), "done".to_string(), "test edit")
+            .await
+            .unwrap();
+        answer_callback(&bot, "42".to_string(), "ok".to_string(), "test answer")
+            .await
+            .unwrap();
+
+        let sent = api.requests("sendMessage").await;
+        assert_eq!(
+            sent[0]["reply_markup"]["inline_keyboard"][0][0]["callback_data"],
+            json!("undo:1")
+        );
+        assert_eq!(sent[0]["reply_parameters"]["message_id"], json!(5));
+        let edited = api.requests("editMessageText").await;
+        assert_eq!(edited[0]["message_id"], json!(78));
+        // Правка без клавиатуры убирает кнопки
+        assert!(edited[0].get("reply_markup").is_none());
+        let answered = api.requests("answerCallbackQuery").await;
+        assert_eq!(answered[0]["callback_query_id"], json!("42"));
+        assert_eq!(answered[0]["text"], json!("ok"));
+    }
+
+    #[tokio::test]
+    async fn delete_sends_delete_message() {
+        let api = FakeBotApi::start().await;
+        api.respond("deleteMessage", json!(true)).await;
+
+        delete(&api.bot(), CHAT, MessageId(5), "test delete")
+            .await
+            .unwrap();
+
+        let requests = api.requests("deleteMessage").await;
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0]["chat_id"], json!(CHAT.0));
+        assert_eq!(requests[0]["message_id"], json!(5));
+    }
+
+    #[tokio::test]
+    async fn permanent_error_is_reported_without_retries() {
+        let api = FakeBotApi::start().await;
+        api.fail(
+            "deleteMessage",
+            400,
+            "Bad Request: message to delete not found",
+        )
+        .await;
+
+        let result = delete(&api.bot(), CHAT, MessageId(5), "test delete").await;
+
+        assert!(result.is_err());
+        assert_eq!(api.requests("deleteMessage").await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn restrict_and_ban_target_the_user() {
+        let api = FakeBotApi::start().await;
+        api.respond("restrictChatMember", json!(true)).await;
+        api.respond("banChatMember", json!(true)).await;
+        let bot = api.bot();
+
+        restrict(
+            &bot,
+            CHAT,
+            UserId(42),
+            ChatPermissions::SEND_MESSAGES,
+            "test restrict",
+        )
+        .await
+        .unwrap();
+        ban(&bot, CHAT, UserId(42), "test ban").await.unwrap();
+
+        let restricted = api.requests("restrictChatMember").await;
+        assert_eq!(restricted.len(), 1);
+        assert_eq!(restricted[0]["user_id"], json!(42));
+        assert_eq!(
+            restricted[0]["permissions"]["can_send_messages"],
+            json!(true)
+        );
+        let banned = api.requests("banChatMember").await;
+        assert_eq!(banned.len(), 1);
+        assert_eq!(banned[0]["chat_id"], json!(CHAT.0));
+        assert_eq!(banned[0]["user_id"], json!(42));
+    }
+
+    #[tokio::test]
+    async fn global_ban_targets_users_and_channels() {
+        let api = FakeBotApi::start().await;
+        api.respond("banChatMember", json!(true)).await;
+        api.respond("banChatSenderChat", json!(true)).await;
+        api.respond("unbanChatMember", json!(true)).await;
+        let bot = api.bot();
+
+        ban_in_chat(&bot, CHAT, 42, "test ban").await.unwrap();
+        ban_in_chat(&bot, CHAT, -1005550000, "test ban")
+            .await
+            .unwrap();
+        unban_in_chat(&bot, CHAT, 42, "test unban").await.unwrap();
+
+        assert_eq!(api.requests("banChatMember").await[0]["user_id"], json!(42));
+        assert_eq!(
+            api.requests("banChatSenderChat").await[0]["sender_chat_id"],
+            json!(-1005550000)
+        );
+        let unbanned = api.requests("unbanChatMember").await;
+        assert_eq!(unbanned.len(), 1);
+        assert_eq!(unbanned[0]["only_if_banned"], json!(true));
+    }
+
+    #[tokio::test]
+    async fn actions_without_rights_are_not_sent() {
+        let api = FakeBotApi::start().await;
+        // Своя группа, чтобы не отключить права в остальных тестах
+        let group = ChatId(-1009999);
+        permissions::record(group, vec![Right::Delete, Right::Ban]);
+        let bot = api.bot();
+
+        let deleted = delete(&bot, group, MessageId(5), "test delete").await;
+        let banned = ban(&bot, group, UserId(42), "test ban").await;
+
+        assert!(deleted.unwrap_err().is::<permissions::MissingRight>());
+        assert!(banned.is_err());
+        assert!(api.requests("deleteMessage").await.is_empty());
+        assert!(api.requests("banChatMember").await.is_empty());
+    }
+}
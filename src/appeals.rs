@@ -0,0 +1,114 @@
+use std::collections::{HashMap, HashSet};
+
+use log::info;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, UserId};
+use tokio::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AppealDecision {
+    Approve,
+    Deny,
+}
+
+impl AppealDecision {
+    fn as_str(&self) -> &'static str {
+        match self {
+            AppealDecision::Approve => "approve",
+            AppealDecision::Deny => "deny",
+        }
+    }
+
+    fn parse(text: &str) -> Option<Self> {
+        match text {
+            "approve" => Some(AppealDecision::Approve),
+            "deny" => Some(AppealDecision::Deny),
+            _ => None,
+        }
+    }
+}
+
+// Данные кнопки под апелляцией: `appeal:<решение>:<id записи>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AppealCallback {
+    pub decision: AppealDecision,
+    pub record_id: u64,
+}
+
+impl AppealCallback {
+    pub fn encode(&self) -> String {
+        format!("appeal:{}:{}", self.decision.as_str(), self.record_id)
+    }
+
+    pub fn parse(data: &str) -> Option<Self> {
+        let mut parts = data.split(':');
+        if parts.next()? != "appeal" {
+            return None;
+        }
+        let decision = AppealDecision::parse(parts.next()?)?;
+        let record_id = parts.next()?.parse().ok()?;
+        Some(Self {
+            decision,
+            record_id,
+        })
+    }
+}
+
+pub fn appeal_keyboard(record_id: u64) -> InlineKeyboardMarkup {
+    let button = |label: &str, decision| {
+        InlineKeyboardButton::callback(
+            label,
+            AppealCallback {
+                decision,
+                record_id,
+            }
+            .encode(),
+        )
+    };
+    InlineKeyboardMarkup::new([[
+        button("✅ Одобрить", AppealDecision::Approve),
+        button("❌ Отклонить", AppealDecision::Deny),
+    ]])
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Appeal {
+    pub user_id: UserId,
+    pub record_id: u64,
+}
+
+#[derive(Default)]
+pub struct Appeals {
+    // Пользователи, от которых ждём текст апелляции, и запись, которую они обжалуют
+    awaiting: Mutex<HashMap<UserId, u64>>,
+    // Записи с поданной и ещё не рассмотренной апелляцией
+    submitted: Mutex<HashSet<u64>>,
+}
+
+impl Appeals {
+    pub async fn is_submitted(&self, record_id: u64) -> bool {
+        self.submitted.lock().await.contains(&record_id)
+    }
+
+    pub async fn is_awaiting(&self, user_id: UserId) -> bool {
+        self.awaiting.lock().await.contains_key(&user_id)
+    }
+
+    pub async fn start(&self, user_id: UserId, record_id: u64) {
+        info!(
+            "User {} started an appeal of record #{}",
+            user_id, record_id
+        );
+        self.awaiting.lock().await.insert(user_id, record_id);
+    }
+
+    // Текст от пользователя завершает сбор апелляции
+    pub async fn take_awaiting(&self, user_id: UserId) -> Option<Appeal> {
+        let record_id = self.awaiting.lock().await.remove(&user_id)?;
+        self.submitted.lock().await.insert(record_id);
+        Some(Appeal { user_id, record_id })
+    }
+
+    pub async fn resolve(&self, record_id: u64) {
+        self.submitted.lock().await.remove(&record_id);
+    }
+}
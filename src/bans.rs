@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use log::{error, info, warn};
+use teloxide::types::ChatId;
+use tokio::sync::Mutex;
+
+use crate::Result;
+
+// Общий список забаненных для всех групп процесса. Строки файла: `<id> <причина>`,
+// положительные ID — пользователи, отрицательные — каналы.
+pub struct GlobalBans {
+    banned: Mutex<HashMap<i64, String>>,
+    path: String,
+    // Группы, на которые распространяется бан
+    chats: Vec<ChatId>,
+}
+
+impl GlobalBans {
+    pub fn load(path: &str, chats: Vec<ChatId>) -> Self {
+        info!("Loading global ban list from {}", path);
+        let mut banned = HashMap::new();
+
+        if Path::new(path).exists() {
+            match File::open(path) {
+                Ok(file) => {
+                    for line in BufReader::new(file).lines().map_while(|l| l.ok()) {
+                        let line = line.trim();
+                        let (id, reason) = line.split_once(' ').unwrap_or((line, ""));
+                        if let Ok(id) = id.parse::<i64>() {
+                            banned.insert(id, reason.trim().to_string());
+                        }
+                    }
+                }
+                Err(e) => error!("Failed to load global ban list: {}", e),
+            }
+        } else {
+            warn!("Global ban list {} does not exist, starting empty", path);
+        }
+
+        info!("Loaded {} globally banned ids", banned.len());
+        Self {
+            banned: Mutex::new(banned),
+            path: path.to_string(),
+            chats,
+        }
+    }
+
+    pub fn chats(&self) -> &[ChatId] {
+        &self.chats
+    }
+
+    pub async fn contains(&self, id: i64) -> bool {
+        self.banned.lock().await.contains_key(&id)
+    }
+
+    // Возвращает false, если ID уже был в списке
+    pub async fn add(&self, id: i64, reason: &str) -> Result<bool> {
+        let mut banned = self.banned.lock().await;
+        if banned.contains_key(&id) {
+            return Ok(false);
+        }
+        banned.insert(id, reason.to_string());
+        self.save(&banned)?;
+        info!("Added {} to global ban list: {}", id, reason);
+        Ok(true)
+    }
+
+    pub async fn remove(&self, id: i64) -> Result<bool> {
+        let mut banned = self.banned.lock().await;
+        if banned.remove(&id).is_none() {
+            return Ok(false);
+        }
+        self.save(&banned)?;
+        info!("Removed {} from global ban list", id);
+        Ok(true)
+    }
+
+    fn save(&self, banned: &HashMap<i64, String>) -> Result<()> {
+        let tmp_path = format!("{}.tmp", self.path);
+        {
+            let mut file = File::create(&tmp_path)?;
+            for (id, reason) in banned.iter() {
+                writeln!(file, "{} {}", id, reason)?;
+            }
+            file.sync_all()?;
+        }
+        std::fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,1072 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Display;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::Deserialize;
+use url::Url;
+
+use crate::evidence::EvidenceMode;
+use crate::Result;
+
+// ID группы, который раньше подставлялся по умолчанию: запуск с ним почти наверняка ошибка
+const LEGACY_GROUP_CHAT_ID: i64 = -1001380105834;
+const LEGACY_SECRET_CODE: &str = "default_code";
+const MIN_SECRET_CODE_LEN: usize = 6;
+
+// Все настройки бота. Значения берутся из TOML-файла (`CONFIG_FILE`, по умолчанию
+// `config.toml`), затем любое из них можно переопределить переменной окружения.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub bot: BotConfig,
+    pub files: FilesConfig,
+    pub log: LogConfig,
+    pub telegram: TelegramConfig,
+    pub features: FeaturesConfig,
+    pub verification: VerificationConfig,
+    pub sender_chats: SenderChatsConfig,
+    pub trust: TrustConfig,
+    pub raid: RaidConfig,
+    pub evidence: EvidenceConfig,
+    pub reports: ReportsConfig,
+    pub messages: MessagesConfig,
+    pub global_bans: GlobalBansConfig,
+    pub webhook: WebhookConfig,
+    pub metrics: MetricsConfig,
+    pub health: HealthConfig,
+    // Несколько групп в одном процессе; пусто — одна группа из [bot] и общих секций
+    pub groups: Vec<GroupConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BotConfig {
+    pub token: String,
+    pub group_chat_id: i64,
+    // Пустой код — подтверждение секретным кодом отключено
+    pub secret_code: Option<String>,
+    pub admin_log_chat_id: Option<i64>,
+    // Владелец бота: получает в личку уведомления о добавлении в чужие чаты
+    pub owner_id: Option<i64>,
+    // Чаты, где бот может находиться, не модерируя их (кроме групп и их служебных чатов)
+    pub allowed_chats: Vec<i64>,
+    // Выходить из чатов не из списка; false — только игнорировать их
+    pub leave_unknown_chats: bool,
+    pub unknown_chat_leave_secs: u64,
+    // Как часто бот перепроверяет свои права администратора в группах
+    pub rights_check_interval_secs: u64,
+}
+
+impl Default for BotConfig {
+    fn default() -> Self {
+        Self {
+            token: String::new(),
+            group_chat_id: 0,
+            secret_code: None,
+            admin_log_chat_id: None,
+            owner_id: None,
+            allowed_chats: Vec::new(),
+            leave_unknown_chats: true,
+            unknown_chat_leave_secs: 60,
+            rights_check_interval_secs: 600,
+        }
+    }
+}
+
+// Группа в `[[groups]]`. Незаданные ключи берутся из [bot], а секция группы
+// заменяет общую секцию целиком.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GroupConfig {
+    pub chat_id: i64,
+    pub secret_code: Option<String>,
+    pub admin_log_chat_id: Option<i64>,
+    pub files: Option<FilesConfig>,
+    pub features: Option<FeaturesConfig>,
+    pub verification: Option<VerificationConfig>,
+    pub sender_chats: Option<SenderChatsConfig>,
+    pub trust: Option<TrustConfig>,
+    pub raid: Option<RaidConfig>,
+    pub evidence: Option<EvidenceConfig>,
+    pub reports: Option<ReportsConfig>,
+    pub messages: Option<MessagesConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilesConfig {
+    pub whitelist: String,
+    pub forbidden_patterns: String,
+    pub pending_users: String,
+    pub modlog: String,
+    pub pattern_stats: String,
+}
+
+impl Default for FilesConfig {
+    fn default() -> Self {
+        Self {
+            whitelist: "whitelist.txt".to_string(),
+            forbidden_patterns: "forbidden_patterns.txt".to_string(),
+            pending_users: "pending_users.txt".to_string(),
+            modlog: "modlog.jsonl".to_string(),
+            pattern_stats: "pattern_stats.json".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    // Пусто — лог не пишется в файл
+    pub file: String,
+    pub level: String,
+    // Уровни отдельных модулей: nstgbr::retry, teloxide, audit и т.п.
+    pub modules: BTreeMap<String, String>,
+    // text или json
+    pub format: String,
+    pub stderr: bool,
+    // Ротация: по размеру (0 — без ограничения) и/или по времени (never, hourly, daily)
+    pub rotate_size_mb: u64,
+    pub rotate_every: String,
+    // Сколько старых файлов хранить после ротации
+    pub keep_files: usize,
+    // Не писать в лог тексты сообщений участников
+    pub redact_messages: bool,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            file: "bot.log".to_string(),
+            level: "info".to_string(),
+            modules: BTreeMap::new(),
+            format: "text".to_string(),
+            stderr: false,
+            rotate_size_mb: 0,
+            rotate_every: "never".to_string(),
+            keep_files: 7,
+            redact_messages: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelegramConfig {
+    // Таймаут HTTP-запроса к Bot API; должен быть больше таймаута long polling (10 с)
+    pub request_timeout_secs: u64,
+    // Всего попыток запроса, включая первую
+    pub retry_attempts: u32,
+    // Пауза перед повтором при сетевых ошибках удваивается с каждой попыткой до retry_max_delay_ms
+    pub retry_base_delay_ms: u64,
+    pub retry_max_delay_ms: u64,
+    // Предел ожидания по RetryAfter от Telegram; дольше — запрос считается неудавшимся
+    pub retry_max_wait_secs: u64,
+    // Через сколько удаляются служебные ответы бота в группе
+    pub auto_delete_secs: u64,
+    // Очередь автоудаления переживает перезапуск; общая для всех групп
+    pub deletion_queue_file: String,
+    // Сколько запросов к Bot API выполняется одновременно; остальные ждут по приоритету
+    pub max_concurrent_requests: usize,
+    // При таком числе ожидающих запросов подсказки участникам отбрасываются
+    pub max_queued_requests: usize,
+}
+
+impl Default for TelegramConfig {
+    fn default() -> Self {
+        Self {
+            request_timeout_secs: 17,
+            retry_attempts: 3,
+            retry_base_delay_ms: 500,
+            retry_max_delay_ms: 10_000,
+            retry_max_wait_secs: 60,
+            auto_delete_secs: 30,
+            deletion_queue_file: "deletion_queue.txt".to_string(),
+            max_concurrent_requests: 8,
+            max_queued_requests: 50,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeaturesConfig {
+    pub reports: bool,
+    pub appeals: bool,
+    pub trust_levels: bool,
+}
+
+impl Default for FeaturesConfig {
+    fn default() -> Self {
+        Self {
+            reports: true,
+            appeals: true,
+            trust_levels: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VerificationConfig {
+    // delete или restrict
+    pub mode: String,
+    pub window_secs: u64,
+    pub kick_after_secs: Option<u64>,
+    pub kick_check_interval_secs: u64,
+}
+
+impl Default for VerificationConfig {
+    fn default() -> Self {
+        Self {
+            mode: "delete".to_string(),
+            window_secs: 300,
+            kick_after_secs: None,
+            kick_check_interval_secs: 300,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SenderChatsConfig {
+    pub ban_unknown: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrustConfig {
+    pub regular_min_messages: u32,
+    pub regular_min_days: i64,
+    pub flood_window_secs: u64,
+    pub new_flood_limit: usize,
+    pub regular_flood_limit: usize,
+}
+
+impl Default for TrustConfig {
+    fn default() -> Self {
+        Self {
+            regular_min_messages: 10,
+            regular_min_days: 3,
+            flood_window_secs: 60,
+            new_flood_limit: 5,
+            regular_flood_limit: 20,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RaidConfig {
+    pub enabled: bool,
+    pub window_secs: u64,
+    pub join_threshold: usize,
+    pub message_threshold: usize,
+    pub cooldown_secs: u64,
+    pub lock_group_permissions: bool,
+}
+
+impl Default for RaidConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            window_secs: 60,
+            join_threshold: 10,
+            message_threshold: 15,
+            cooldown_secs: 600,
+            lock_group_permissions: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EvidenceConfig {
+    // off, chat, archive или both
+    pub mode: String,
+    pub chat_id: Option<i64>,
+    pub dir: String,
+    pub retention_days: u64,
+}
+
+impl Default for EvidenceConfig {
+    fn default() -> Self {
+        Self {
+            mode: "off".to_string(),
+            chat_id: None,
+            dir: "evidence".to_string(),
+            retention_days: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReportsConfig {
+    pub auto_delete_threshold: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MessagesConfig {
+    pub language: String,
+    pub per_user_language: bool,
+    pub overrides_file: Option<String>,
+}
+
+impl Default for MessagesConfig {
+    fn default() -> Self {
+        Self {
+            language: "ru".to_string(),
+            per_user_language: false,
+            overrides_file: None,
+        }
+    }
+}
+
+// Общий для всех групп список забаненных
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GlobalBansConfig {
+    pub enabled: bool,
+    pub file: String,
+}
+
+impl Default for GlobalBansConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            file: "global_bans.txt".to_string(),
+        }
+    }
+}
+
+// Доставка обновлений через вебхук вместо long polling
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub enabled: bool,
+    // Адрес встроенного HTTP-сервера
+    pub listen: String,
+    // Публичный https-адрес, на который Telegram шлёт обновления; его путь — путь вебхука
+    pub url: Option<String>,
+    // Сверяется с заголовком X-Telegram-Bot-Api-Secret-Token; не задан — новый при каждом запуске
+    pub secret_token: Option<String>,
+    // Сертификат и ключ в PEM: сервер сам принимает HTTPS, без них — HTTP за обратным прокси
+    pub tls_cert: Option<String>,
+    pub tls_key: Option<String>,
+    // Самоподписанный tls_cert передаётся Telegram при регистрации вебхука
+    pub upload_certificate: bool,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: "0.0.0.0:8443".to_string(),
+            url: None,
+            secret_token: None,
+            tls_cert: None,
+            tls_key: None,
+            upload_certificate: false,
+        }
+    }
+}
+
+// HTTP-эндпоинт /metrics для Prometheus
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    pub listen: String,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: "127.0.0.1:9898".to_string(),
+        }
+    }
+}
+
+// HTTP-эндпоинты /healthz и /readyz для супервизора; адрес может совпадать с [metrics]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthConfig {
+    pub enabled: bool,
+    pub listen: String,
+    // Как часто проверять права бота в группах и запись в хранилище
+    pub check_interval_secs: u64,
+    // Без успешного getUpdates дольше этого процесс считается зависшим
+    pub poll_stall_secs: u64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: "127.0.0.1:9898".to_string(),
+            check_interval_secs: 60,
+            poll_stall_secs: 120,
+        }
+    }
+}
+
+impl Config {
+    // Файл, затем переменные окружения, затем проверка
+    pub fn load() -> Result<Self> {
+        let path = std::env::var("CONFIG_FILE").unwrap_or_else(|_| "config.toml".to_string());
+        let mut config = if Path::new(&path).exists() {
+            let content = std::fs::read_to_string(&path)?;
+            toml::from_str(&content).map_err(|e| format!("invalid config {}: {}", path, e))?
+        } else {
+            Config::default()
+        };
+        config.apply_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    // Полные настройки каждой обслуживаемой группы
+    pub fn groups(&self) -> Vec<Config> {
+        if self.groups.is_empty() {
+            return vec![self.clone()];
+        }
+        self.groups
+            .iter()
+            .map(|group| {
+                let mut config = self.clone();
+                config.groups.clear();
+                config.bot.group_chat_id = group.chat_id;
+                if group.secret_code.is_some() {
+                    config.bot.secret_code = group.secret_code.clone();
+                }
+                if group.admin_log_chat_id.is_some() {
+                    config.bot.admin_log_chat_id = group.admin_log_chat_id;
+                }
+                override_section(&mut config.files, &group.files);
+                override_section(&mut config.features, &group.features);
+                override_section(&mut config.verification, &group.verification);
+                override_section(&mut config.sender_chats, &group.sender_chats);
+                override_section(&mut config.trust, &group.trust);
+                override_section(&mut config.raid, &group.raid);
+                override_section(&mut config.evidence, &group.evidence);
+                override_section(&mut config.reports, &group.reports);
+                override_section(&mut config.messages, &group.messages);
+                config
+            })
+            .collect()
+    }
+
+    fn apply_env(&mut self) -> Result<()> {
+        env("VERIFICATION_BOT_TOKEN", &mut self.bot.token)?;
+        env("GROUP_CHAT_ID", &mut self.bot.group_chat_id)?;
+        env_opt("SECRET_CODE", &mut self.bot.secret_code)?;
+        env_opt("ADMIN_LOG_CHAT_ID", &mut self.bot.admin_log_chat_id)?;
+        env_opt("OWNER_ID", &mut self.bot.owner_id)?;
+        env_list("ALLOWED_CHATS", &mut self.bot.allowed_chats)?;
+        env_bool("LEAVE_UNKNOWN_CHATS", &mut self.bot.leave_unknown_chats)?;
+        env(
+            "UNKNOWN_CHAT_LEAVE_SECS",
+            &mut self.bot.unknown_chat_leave_secs,
+        )?;
+        env(
+            "RIGHTS_CHECK_INTERVAL_SECS",
+            &mut self.bot.rights_check_interval_secs,
+        )?;
+
+        env("WHITELIST_FILE", &mut self.files.whitelist)?;
+        env(
+            "FORBIDDEN_PATTERNS_FILE",
+            &mut self.files.forbidden_patterns,
+        )?;
+        env("PENDING_USERS_FILE", &mut self.files.pending_users)?;
+        env("MODLOG_FILE", &mut self.files.modlog)?;
+        env("PATTERN_STATS_FILE", &mut self.files.pattern_stats)?;
+
+        env("LOG_FILE", &mut self.log.file)?;
+        env("LOG_LEVEL", &mut self.log.level)?;
+        if let Ok(value) = std::env::var("LOG_MODULES") {
+            self.log.modules = parse_module_levels(&value)
+                .map_err(|e| format!("invalid LOG_MODULES='{}': {}", value, e))?;
+        }
+        env("LOG_FORMAT", &mut self.log.format)?;
+        env_bool("LOG_STDERR", &mut self.log.stderr)?;
+        env("LOG_ROTATE_SIZE_MB", &mut self.log.rotate_size_mb)?;
+        env("LOG_ROTATE_EVERY", &mut self.log.rotate_every)?;
+        env("LOG_KEEP_FILES", &mut self.log.keep_files)?;
+        env_bool("LOG_REDACT_MESSAGES", &mut self.log.redact_messages)?;
+
+        let telegram = &mut self.telegram;
+        env("REQUEST_TIMEOUT_SECS", &mut telegram.request_timeout_secs)?;
+        env("RETRY_ATTEMPTS", &mut telegram.retry_attempts)?;
+        env("RETRY_BASE_DELAY_MS", &mut telegram.retry_base_delay_ms)?;
+        env("RETRY_MAX_DELAY_MS", &mut telegram.retry_max_delay_ms)?;
+        env("RETRY_MAX_WAIT_SECS", &mut telegram.retry_max_wait_secs)?;
+        env("AUTO_DELETE_SECS", &mut telegram.auto_delete_secs)?;
+        env("DELETION_QUEUE_FILE", &mut telegram.deletion_queue_file)?;
+        env(
+            "MAX_CONCURRENT_REQUESTS",
+            &mut telegram.max_concurrent_requests,
+        )?;
+        env("MAX_QUEUED_REQUESTS", &mut telegram.max_queued_requests)?;
+
+        env_bool("FEATURE_REPORTS", &mut self.features.reports)?;
+        env_bool("FEATURE_APPEALS", &mut self.features.appeals)?;
+        env_bool("FEATURE_TRUST_LEVELS", &mut self.features.trust_levels)?;
+
+        let verification = &mut self.verification;
+        env("UNVERIFIED_MODE", &mut verification.mode)?;
+        env("VERIFICATION_WINDOW_SECS", &mut verification.window_secs)?;
+        env_opt(
+            "KICK_UNVERIFIED_AFTER_SECS",
+            &mut verification.kick_after_secs,
+        )?;
+        env(
+            "KICK_CHECK_INTERVAL_SECS",
+            &mut verification.kick_check_interval_secs,
+        )?;
+
+        env_bool(
+            "BAN_UNKNOWN_SENDER_CHATS",
+            &mut self.sender_chats.ban_unknown,
+        )?;
+
+        let trust = &mut self.trust;
+        env("REGULAR_MIN_MESSAGES", &mut trust.regular_min_messages)?;
+        env("REGULAR_MIN_DAYS", &mut trust.regular_min_days)?;
+        env("FLOOD_WINDOW_SECS", &mut trust.flood_window_secs)?;
+        env("NEW_FLOOD_LIMIT", &mut trust.new_flood_limit)?;
+        env("REGULAR_FLOOD_LIMIT", &mut trust.regular_flood_limit)?;
+
+        let raid = &mut self.raid;
+        env_bool("RAID_DETECTION", &mut raid.enabled)?;
+        env("RAID_WINDOW_SECS", &mut raid.window_secs)?;
+        env("RAID_JOIN_THRESHOLD", &mut raid.join_threshold)?;
+        env("RAID_MESSAGE_THRESHOLD", &mut raid.message_threshold)?;
+        env("RAID_COOLDOWN_SECS", &mut raid.cooldown_secs)?;
+        env_bool(
+            "RAID_LOCK_GROUP_PERMISSIONS",
+            &mut raid.lock_group_permissions,
+        )?;
+
+        let evidence = &mut self.evidence;
+        env("EVIDENCE_MODE", &mut evidence.mode)?;
+        env_opt("EVIDENCE_CHAT_ID", &mut evidence.chat_id)?;
+        env("EVIDENCE_DIR", &mut evidence.dir)?;
+        env("EVIDENCE_RETENTION_DAYS", &mut evidence.retention_days)?;
+
+        env(
+            "REPORT_AUTO_DELETE_THRESHOLD",
+            &mut self.reports.auto_delete_threshold,
+        )?;
+
+        env("BOT_LANGUAGE", &mut self.messages.language)?;
+        env_bool("PER_USER_LANGUAGE", &mut self.messages.per_user_language)?;
+        env_opt("MESSAGES_FILE", &mut self.messages.overrides_file)?;
+
+        env_bool("GLOBAL_BANS", &mut self.global_bans.enabled)?;
+        env("GLOBAL_BANS_FILE", &mut self.global_bans.file)?;
+
+        let webhook = &mut self.webhook;
+        env_bool("WEBHOOK", &mut webhook.enabled)?;
+        env("WEBHOOK_LISTEN", &mut webhook.listen)?;
+        env_opt("WEBHOOK_URL", &mut webhook.url)?;
+        env_opt("WEBHOOK_SECRET_TOKEN", &mut webhook.secret_token)?;
+        env_opt("WEBHOOK_TLS_CERT", &mut webhook.tls_cert)?;
+        env_opt("WEBHOOK_TLS_KEY", &mut webhook.tls_key)?;
+        env_bool(
+            "WEBHOOK_UPLOAD_CERTIFICATE",
+            &mut webhook.upload_certificate,
+        )?;
+
+        env_bool("METRICS", &mut self.metrics.enabled)?;
+        env("METRICS_LISTEN", &mut self.metrics.listen)?;
+
+        env_bool("HEALTH", &mut self.health.enabled)?;
+        env("HEALTH_LISTEN", &mut self.health.listen)?;
+        env(
+            "HEALTH_CHECK_INTERVAL_SECS",
+            &mut self.health.check_interval_secs,
+        )?;
+        env("HEALTH_POLL_STALL_SECS", &mut self.health.poll_stall_secs)?;
+        Ok(())
+    }
+
+    // Отказываемся запускаться с настройками, которые молча сломают модерацию
+    fn validate(&self) -> Result<()> {
+        let mut errors = Vec::new();
+
+        if self.bot.token.trim().is_empty() {
+            errors.push("bot.token (VERIFICATION_BOT_TOKEN) is not set".to_string());
+        }
+        if self.log.level.parse::<log::LevelFilter>().is_err() {
+            errors.push(format!("log.level '{}' is not a log level", self.log.level));
+        }
+        for (module, level) in &self.log.modules {
+            if level.parse::<log::LevelFilter>().is_err() {
+                errors.push(format!(
+                    "log.modules.{} '{}' is not a log level",
+                    module, level
+                ));
+            }
+        }
+        if !matches!(self.log.format.as_str(), "text" | "json") {
+            errors.push(format!(
+                "log.format '{}' must be text or json",
+                self.log.format
+            ));
+        }
+        if !matches!(self.log.rotate_every.as_str(), "never" | "hourly" | "daily") {
+            errors.push(format!(
+                "log.rotate_every '{}' must be never, hourly or daily",
+                self.log.rotate_every
+            ));
+        }
+        if self.log.keep_files == 0 {
+            errors.push("log.keep_files must be at least 1".to_string());
+        }
+        if self.log.file.trim().is_empty() && !self.log.stderr {
+            errors.push("log.file is empty and log.stderr is off, logs would be lost".to_string());
+        }
+        if self.telegram.request_timeout_secs <= 10 {
+            errors.push(
+                "telegram.request_timeout_secs must be greater than the 10 s polling timeout"
+                    .to_string(),
+            );
+        }
+        if self.telegram.retry_attempts == 0 {
+            errors.push("telegram.retry_attempts must be at least 1".to_string());
+        }
+        if self.telegram.retry_max_delay_ms < self.telegram.retry_base_delay_ms {
+            errors.push(
+                "telegram.retry_max_delay_ms must not be less than retry_base_delay_ms".to_string(),
+            );
+        }
+        if self.telegram.max_concurrent_requests == 0 {
+            errors.push("telegram.max_concurrent_requests must be at least 1".to_string());
+        }
+        if self.bot.rights_check_interval_secs == 0 {
+            errors.push("bot.rights_check_interval_secs must be positive".to_string());
+        }
+        if self.telegram.auto_delete_secs == 0 {
+            errors.push("telegram.auto_delete_secs must be positive".to_string());
+        }
+        if self.bot.owner_id.is_some_and(|id| id <= 0) {
+            errors.push("bot.owner_id must be a user id (positive)".to_string());
+        }
+        if self.global_bans.enabled && self.global_bans.file.trim().is_empty() {
+            errors.push("global_bans.file is not set".to_string());
+        }
+        if self.webhook.enabled {
+            validate_webhook(&self.webhook, &mut errors);
+        }
+        if self.metrics.enabled && self.metrics.listen.parse::<SocketAddr>().is_err() {
+            errors.push(format!(
+                "metrics.listen '{}' is not an address like 127.0.0.1:9898",
+                self.metrics.listen
+            ));
+        }
+        if self.health.enabled {
+            if self.health.listen.parse::<SocketAddr>().is_err() {
+                errors.push(format!(
+                    "health.listen '{}' is not an address like 127.0.0.1:9898",
+                    self.health.listen
+                ));
+            }
+            if self.health.check_interval_secs == 0 {
+                errors.push("health.check_interval_secs must be positive".to_string());
+            }
+            // Пустой long poll возвращается раз в 10 с, плюс время на сам запрос
+            if self.health.poll_stall_secs <= self.telegram.request_timeout_secs {
+                errors.push(
+                    "health.poll_stall_secs must be greater than telegram.request_timeout_secs"
+                        .to_string(),
+                );
+            }
+        }
+
+        if self.groups.is_empty() {
+            validate_group(self, "bot.group_chat_id (GROUP_CHAT_ID)", "", &mut errors);
+        } else {
+            if self.bot.group_chat_id != 0 {
+                errors
+                    .push("bot.group_chat_id must not be set together with [[groups]]".to_string());
+            }
+            let groups = self.groups();
+            for (i, group) in groups.iter().enumerate() {
+                let chat_field = format!("groups[{}].chat_id", i);
+                validate_group(group, &chat_field, &format!("groups[{}]: ", i), &mut errors);
+            }
+            validate_distinct(&groups, &mut errors);
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("invalid configuration:\n  {}", errors.join("\n  ")).into())
+        }
+    }
+}
+
+fn override_section<T: Clone>(section: &mut T, group: &Option<T>) {
+    if let Some(group) = group {
+        *section = group.clone();
+    }
+}
+
+// Проверки, которые относятся к отдельной группе
+fn validate_group(config: &Config, chat_field: &str, prefix: &str, errors: &mut Vec<String>) {
+    match config.bot.group_chat_id {
+        0 => errors.push(format!("{} is not set", chat_field)),
+        LEGACY_GROUP_CHAT_ID => errors.push(format!(
+            "{} is the old built-in default {}, set your own group",
+            chat_field, LEGACY_GROUP_CHAT_ID
+        )),
+        id if id > 0 => errors.push(format!(
+            "{} {} is not a group id (must be negative)",
+            chat_field, id
+        )),
+        _ => {}
+    }
+    if let Some(code) = &config.bot.secret_code {
+        if code == LEGACY_SECRET_CODE {
+            errors.push(format!(
+                "{}secret_code must not be \"default_code\"",
+                prefix
+            ));
+        } else if !code.is_empty() && code.chars().count() < MIN_SECRET_CODE_LEN {
+            errors.push(format!(
+                "{}secret_code must be at least {} characters",
+                prefix, MIN_SECRET_CODE_LEN
+            ));
+        }
+    }
+
+    if !matches!(config.verification.mode.as_str(), "delete" | "restrict") {
+        errors.push(format!(
+            "{}verification.mode '{}' must be delete or restrict",
+            prefix, config.verification.mode
+        ));
+    }
+    if config.verification.window_secs == 0 || config.verification.kick_check_interval_secs == 0 {
+        errors.push(format!("{}verification intervals must be positive", prefix));
+    }
+    if config.verification.kick_after_secs == Some(0) {
+        errors.push(format!(
+            "{}verification.kick_after_secs must be positive",
+            prefix
+        ));
+    }
+
+    if config.trust.flood_window_secs == 0 {
+        errors.push(format!(
+            "{}trust.flood_window_secs must be positive",
+            prefix
+        ));
+    }
+    if config.raid.enabled
+        && (config.raid.window_secs == 0
+            || config.raid.join_threshold == 0
+            || config.raid.message_threshold == 0)
+    {
+        errors.push(format!(
+            "{}raid window and thresholds must be positive",
+            prefix
+        ));
+    }
+
+    match EvidenceMode::parse(&config.evidence.mode) {
+        None => errors.push(format!(
+            "{}evidence.mode '{}' must be off, chat, archive or both",
+            prefix, config.evidence.mode
+        )),
+        Some(mode) if mode.uses_chat() && config.evidence.chat_id.is_none() => {
+            errors.push(format!(
+                "{}evidence.mode uses chat, but evidence.chat_id is not set",
+                prefix
+            ))
+        }
+        Some(_) => {}
+    }
+}
+
+// Telegram доставляет вебхуки только по HTTPS и только на эти порты
+const WEBHOOK_PORTS: [u16; 4] = [443, 80, 88, 8443];
+
+fn validate_webhook(webhook: &WebhookConfig, errors: &mut Vec<String>) {
+    if webhook.listen.parse::<SocketAddr>().is_err() {
+        errors.push(format!(
+            "webhook.listen '{}' is not an address like 0.0.0.0:8443",
+            webhook.listen
+        ));
+    }
+    match webhook.url.as_deref().map(Url::parse) {
+        None => errors.push("webhook.url (WEBHOOK_URL) is not set".to_string()),
+        Some(Err(e)) => errors.push(format!("webhook.url is invalid: {}", e)),
+        Some(Ok(url)) if url.scheme() != "https" => {
+            errors.push("webhook.url must be an https:// address".to_string())
+        }
+        Some(Ok(url))
+            if !url
+                .port_or_known_default()
+                .is_some_and(|p| WEBHOOK_PORTS.contains(&p)) =>
+        {
+            errors.push("webhook.url port must be 443, 80, 88 or 8443".to_string())
+        }
+        Some(Ok(_)) => {}
+    }
+    if let Some(token) = &webhook.secret_token {
+        let valid_chars = token
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || c == b'_' || c == b'-');
+        if token.is_empty() || token.len() > 256 || !valid_chars {
+            errors.push(
+                "webhook.secret_token must be 1-256 characters of A-Z, a-z, 0-9, _ and -"
+                    .to_string(),
+            );
+        }
+    }
+    if webhook.tls_cert.is_some() != webhook.tls_key.is_some() {
+        errors.push("webhook.tls_cert and webhook.tls_key must be set together".to_string());
+    }
+    if webhook.upload_certificate && webhook.tls_cert.is_none() {
+        errors.push("webhook.upload_certificate needs webhook.tls_cert".to_string());
+    }
+}
+
+// Группы не должны делить файлы состояния и чат администраторов: кнопки в журнале
+// привязаны к записям конкретной группы. Файл запрещённых шаблонов можно держать
+// общим: группы с одним файлом используют одни и те же загруженные шаблоны
+fn validate_distinct(groups: &[Config], errors: &mut Vec<String>) {
+    let mut chats = HashSet::new();
+    let mut admin_chats = HashSet::new();
+    let mut files = HashSet::new();
+    for group in groups {
+        if !chats.insert(group.bot.group_chat_id) {
+            errors.push(format!(
+                "group {} is configured more than once",
+                group.bot.group_chat_id
+            ));
+        }
+        if let Some(admin_chat) = group.bot.admin_log_chat_id {
+            if !admin_chats.insert(admin_chat) {
+                errors.push(format!(
+                    "admin_log_chat_id {} is shared by several groups",
+                    admin_chat
+                ));
+            }
+        }
+        let group_files = &group.files;
+        for file in [
+            &group_files.whitelist,
+            &group_files.pending_users,
+            &group_files.modlog,
+            &group_files.pattern_stats,
+        ] {
+            if !files.insert(file.as_str()) {
+                errors.push(format!(
+                    "file {} is used by several groups, set [groups.files] for each group",
+                    file
+                ));
+            }
+        }
+    }
+}
+
+fn env<T>(name: &str, target: &mut T) -> Result<()>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Ok(value) = std::env::var(name) {
+        *target = value
+            .trim()
+            .parse()
+            .map_err(|e| format!("invalid {}='{}': {}", name, value, e))?;
+    }
+    Ok(())
+}
+
+// Пустое значение переменной сбрасывает необязательную настройку
+fn env_opt<T>(name: &str, target: &mut Option<T>) -> Result<()>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Ok(value) = std::env::var(name) {
+        *target = if value.trim().is_empty() {
+            None
+        } else {
+            Some(
+                value
+                    .trim()
+                    .parse()
+                    .map_err(|e| format!("invalid {}='{}': {}", name, value, e))?,
+            )
+        };
+    }
+    Ok(())
+}
+
+// Список через запятую; пустое значение очищает список
+fn env_list<T>(name: &str, target: &mut Vec<T>) -> Result<()>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Ok(value) = std::env::var(name) {
+        *target = value
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| {
+                item.parse()
+                    .map_err(|e| format!("invalid {}='{}': {}", name, value, e))
+            })
+            .collect::<std::result::Result<_, _>>()?;
+    }
+    Ok(())
+}
+
+// "teloxide=warn,nstgbr::retry=debug"
+fn parse_module_levels(value: &str) -> std::result::Result<BTreeMap<String, String>, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| match item.split_once('=') {
+            Some((module, level)) => Ok((module.trim().to_string(), level.trim().to_string())),
+            None => Err(format!("expected module=level, got '{}'", item)),
+        })
+        .collect()
+}
+
+fn env_bool(name: &str, target: &mut bool) -> Result<()> {
+    if let Ok(value) = std::env::var(name) {
+        *target = match value.trim().to_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => true,
+            "0" | "false" | "no" | "off" => false,
+            _ => {
+                return Err(format!("invalid {}='{}': expected true or false", name, value).into())
+            }
+        };
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn valid() -> Config {
+        let mut config = Config::default();
+        config.bot.token = "123456:TEST".to_string();
+        config.bot.group_chat_id = -1001234567890;
+        config
+    }
+
+    fn validation_error(config: &Config) -> String {
+        config.validate().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn accepts_valid_config() {
+        assert!(valid().validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_unsafe_defaults() {
+        let mut config = valid();
+        config.bot.group_chat_id = LEGACY_GROUP_CHAT_ID;
+        assert!(validation_error(&config).contains("old built-in default"));
+
+        let mut config = valid();
+        config.bot.secret_code = Some(LEGACY_SECRET_CODE.to_string());
+        assert!(validation_error(&config).contains("must not be \"default_code\""));
+
+        let mut config = valid();
+        config.bot.secret_code = Some("12345".to_string());
+        assert!(validation_error(&config).contains("at least 6 characters"));
+        config.bot.secret_code = Some("123456".to_string());
+        assert!(config.validate().is_ok());
+
+        let mut config = valid();
+        config.telegram.request_timeout_secs = 10;
+        assert!(validation_error(&config).contains("request_timeout_secs"));
+        config.telegram.request_timeout_secs = 11;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn groups_share_only_patterns_file() {
+        let group = |chat_id: i64, name: &str| {
+            let files = FilesConfig {
+                whitelist: format!("{}/whitelist.txt", name),
+                forbidden_patterns: "forbidden_patterns.txt".to_string(),
+                pending_users: format!("{}/pending_users.txt", name),
+                modlog: format!("{}/modlog.jsonl", name),
+                pattern_stats: format!("{}/pattern_stats.json", name),
+            };
+            GroupConfig {
+                chat_id,
+                files: Some(files),
+                ..GroupConfig::default()
+            }
+        };
+        let mut config = valid();
+        config.bot.group_chat_id = 0;
+        config.groups = vec![group(-1001, "first"), group(-1002, "second")];
+        assert!(config.validate().is_ok());
+
+        config.groups[1].files.as_mut().unwrap().whitelist = "first/whitelist.txt".to_string();
+        assert!(validation_error(&config).contains("first/whitelist.txt is used by several groups"));
+    }
+
+    // Имена переменных не пересекаются между тестами: окружение общее для потоков
+    #[test]
+    fn env_overrides_config() {
+        std::env::set_var("GROUP_CHAT_ID", " -100777 ");
+        std::env::set_var("ALLOWED_CHATS", "-1001, ,-1002");
+        std::env::set_var("FEATURE_REPORTS", "off");
+        std::env::set_var("KICK_UNVERIFIED_AFTER_SECS", "600");
+        std::env::set_var("EVIDENCE_CHAT_ID", "");
+        let mut config = valid();
+        config.features.reports = true;
+        config.evidence.chat_id = Some(-1003);
+        config.apply_env().unwrap();
+
+        assert_eq!(config.bot.group_chat_id, -100777);
+        assert_eq!(config.bot.allowed_chats, vec![-1001, -1002]);
+        assert!(!config.features.reports);
+        assert_eq!(config.verification.kick_after_secs, Some(600));
+        assert_eq!(config.evidence.chat_id, None);
+    }
+
+    #[test]
+    fn parses_env_values() {
+        let mut flag = false;
+        for (value, expected) in [("1", true), ("Yes", true), (" on ", true), ("FALSE", false)] {
+            std::env::set_var("NSTGBR_TEST_BOOL", value);
+            env_bool("NSTGBR_TEST_BOOL", &mut flag).unwrap();
+            assert_eq!(flag, expected, "{}", value);
+        }
+        std::env::set_var("NSTGBR_TEST_BOOL", "maybe");
+        assert!(env_bool("NSTGBR_TEST_BOOL", &mut flag).is_err());
+        env_bool("NSTGBR_TEST_UNSET", &mut flag).unwrap();
+        assert!(!flag);
+
+        let mut list = vec![1];
+        std::env::set_var("NSTGBR_TEST_LIST", "");
+        env_list("NSTGBR_TEST_LIST", &mut list).unwrap();
+        assert!(list.is_empty());
+        std::env::set_var("NSTGBR_TEST_LIST", "3, 4,");
+        env_list("NSTGBR_TEST_LIST", &mut list).unwrap();
+        assert_eq!(list, vec![3, 4]);
+        std::env::set_var("NSTGBR_TEST_LIST", "3,x");
+        assert!(env_list("NSTGBR_TEST_LIST", &mut list).is_err());
+    }
+}
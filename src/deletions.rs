@@ -0,0 +1,164 @@
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use log::{debug, error, info, warn};
+use teloxide::types::{ChatId, MessageId};
+use tokio::sync::Notify;
+
+use crate::metrics;
+use crate::Result;
+
+// deleteMessages принимает не больше 100 сообщений за раз
+const MAX_BATCH: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ScheduledDeletion {
+    chat_id: ChatId,
+    message_id: MessageId,
+    // Unix-время, когда сообщение пора удалить
+    due: i64,
+}
+
+// Очередь отложенных удалений ответов бота. Хранится в файле строками
+// `<chat_id> <message_id> <due>`, поэтому после перезапуска удаления продолжаются.
+// Разбирает её одна задача-планировщик.
+pub struct DeletionQueue {
+    entries: Mutex<Vec<ScheduledDeletion>>,
+    path: String,
+    wakeup: Notify,
+}
+
+impl DeletionQueue {
+    pub fn load(path: &str) -> Self {
+        info!("Loading deletion queue from {}", path);
+        let mut entries = Vec::new();
+
+        if Path::new(path).exists() {
+            match File::open(path) {
+                Ok(file) => {
+                    for line in BufReader::new(file).lines().map_while(|l| l.ok()) {
+                        let mut fields = line.split_whitespace();
+                        let chat_id = fields.next().and_then(|f| f.parse::<i64>().ok());
+                        let message_id = fields.next().and_then(|f| f.parse::<i32>().ok());
+                        let due = fields.next().and_then(|f| f.parse::<i64>().ok());
+                        if let (Some(chat_id), Some(message_id), Some(due)) =
+                            (chat_id, message_id, due)
+                        {
+                            entries.push(ScheduledDeletion {
+                                chat_id: ChatId(chat_id),
+                                message_id: MessageId(message_id),
+                                due,
+                            });
+                        }
+                    }
+                }
+                Err(e) => error!("Failed to load deletion queue: {}", e),
+            }
+        } else {
+            warn!(
+                "Deletion queue file {} does not exist, starting empty",
+                path
+            );
+        }
+
+        info!("Loaded {} scheduled deletions", entries.len());
+        metrics::set_deletion_queue_depth(entries.len());
+        Self {
+            entries: Mutex::new(entries),
+            path: path.to_string(),
+            wakeup: Notify::new(),
+        }
+    }
+
+    pub fn schedule(&self, chat_id: ChatId, message_id: MessageId, due: i64) -> Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{} {} {}", chat_id.0, message_id.0, due)?;
+        entries.push(ScheduledDeletion {
+            chat_id,
+            message_id,
+            due,
+        });
+        debug!(
+            "Scheduled deletion of message {} in chat {} at {} ({} queued)",
+            message_id,
+            chat_id,
+            due,
+            entries.len()
+        );
+        metrics::set_deletion_queue_depth(entries.len());
+        drop(entries);
+        self.wakeup.notify_one();
+        Ok(())
+    }
+
+    // Сколько сообщений ждут удаления
+    pub fn depth(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    // Паника с захваченной блокировкой: очередь больше не разбирается
+    pub fn is_poisoned(&self) -> bool {
+        self.entries.is_poisoned()
+    }
+
+    pub fn next_due(&self) -> Option<i64> {
+        self.entries.lock().unwrap().iter().map(|e| e.due).min()
+    }
+
+    // Наступившие удаления, сгруппированные по чатам пачками не больше MAX_BATCH
+    pub fn due(&self, now: i64) -> Vec<(ChatId, Vec<MessageId>)> {
+        let entries = self.entries.lock().unwrap();
+        let mut by_chat: BTreeMap<i64, Vec<MessageId>> = BTreeMap::new();
+        for entry in entries.iter().filter(|e| e.due <= now) {
+            by_chat
+                .entry(entry.chat_id.0)
+                .or_default()
+                .push(entry.message_id);
+        }
+        by_chat
+            .into_iter()
+            .flat_map(|(chat_id, ids)| {
+                ids.chunks(MAX_BATCH)
+                    .map(|chunk| (ChatId(chat_id), chunk.to_vec()))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    // Убирает обработанные сообщения из очереди и файла
+    pub fn complete(&self, chat_id: ChatId, message_ids: &[MessageId]) -> Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|e| !(e.chat_id == chat_id && message_ids.contains(&e.message_id)));
+        metrics::set_deletion_queue_depth(entries.len());
+        self.save(&entries)
+    }
+
+    // Ждёт новой записи в очереди
+    pub async fn wait_for_new(&self) {
+        self.wakeup.notified().await;
+    }
+
+    fn save(&self, entries: &[ScheduledDeletion]) -> Result<()> {
+        let tmp_path = format!("{}.tmp", self.path);
+        {
+            let mut file = File::create(&tmp_path)?;
+            for entry in entries {
+                writeln!(
+                    file,
+                    "{} {} {}",
+                    entry.chat_id.0, entry.message_id.0, entry.due
+                )?;
+            }
+            file.sync_all()?;
+        }
+        std::fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
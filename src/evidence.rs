@@ -0,0 +1,192 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use teloxide::types::{ChatId, Message};
+
+use crate::config::EvidenceConfig;
+use crate::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvidenceMode {
+    Off,
+    // Пересылка удаляемого сообщения в отдельный чат
+    Chat,
+    // JSON сообщения в локальном каталоге
+    Archive,
+    Both,
+}
+
+impl EvidenceMode {
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "off" => Some(EvidenceMode::Off),
+            "chat" => Some(EvidenceMode::Chat),
+            "archive" => Some(EvidenceMode::Archive),
+            "both" => Some(EvidenceMode::Both),
+            _ => None,
+        }
+    }
+
+    pub fn uses_chat(&self) -> bool {
+        matches!(self, EvidenceMode::Chat | EvidenceMode::Both)
+    }
+
+    pub fn uses_archive(&self) -> bool {
+        matches!(self, EvidenceMode::Archive | EvidenceMode::Both)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EvidenceSettings {
+    pub mode: EvidenceMode,
+    pub chat_id: Option<ChatId>,
+    pub dir: PathBuf,
+    pub retention: Duration,
+}
+
+impl EvidenceSettings {
+    // Режим уже проверен при загрузке конфигурации
+    pub fn from_config(config: &EvidenceConfig) -> Self {
+        Self {
+            mode: EvidenceMode::parse(&config.mode).unwrap_or(EvidenceMode::Off),
+            chat_id: config.chat_id.map(ChatId),
+            dir: PathBuf::from(&config.dir),
+            retention: Duration::from_secs(config.retention_days * 24 * 60 * 60),
+        }
+    }
+}
+
+// Где сохранена копия удалённого сообщения
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Evidence {
+    // Пересланное сообщение в чате доказательств
+    pub chat_id: Option<i64>,
+    pub message_id: Option<i32>,
+    // Файл в локальном архиве
+    pub archive_file: Option<String>,
+}
+
+impl Evidence {
+    pub fn is_empty(&self) -> bool {
+        self.message_id.is_none() && self.archive_file.is_none()
+    }
+
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let (Some(chat_id), Some(message_id)) = (self.chat_id, self.message_id) {
+            parts.push(format!("копия {} в чате {}", message_id, chat_id));
+        }
+        if let Some(file) = &self.archive_file {
+            parts.push(format!("архив {}", file));
+        }
+        parts.join(", ")
+    }
+}
+
+#[derive(Serialize)]
+struct ArchivedMessage<'a> {
+    saved_at: i64,
+    rule: &'a str,
+    file_ids: Vec<String>,
+    message: &'a Message,
+}
+
+pub fn archive_message(dir: &Path, msg: &Message, rule: &str) -> Result<String> {
+    fs::create_dir_all(dir)?;
+    let saved_at = chrono::Utc::now().timestamp();
+    let path = dir.join(format!("{}_{}_{}.json", msg.chat.id.0, msg.id.0, saved_at));
+    let archived = ArchivedMessage {
+        saved_at,
+        rule,
+        file_ids: media_file_ids(msg),
+        message: msg,
+    };
+
+    let mut file = File::create(&path)?;
+    file.write_all(serde_json::to_string_pretty(&archived)?.as_bytes())?;
+    debug!("Archived message {} to {}", msg.id, path.display());
+    Ok(path.display().to_string())
+}
+
+// file_id всех вложений: по ним администратор сможет достать медиа через Bot API
+pub fn media_file_ids(msg: &Message) -> Vec<String> {
+    let mut ids = Vec::new();
+    if let Some(photo) = msg.photo().and_then(|sizes| sizes.last()) {
+        ids.push(photo.file.id.to_string());
+    }
+    if let Some(video) = msg.video() {
+        ids.push(video.file.id.to_string());
+    }
+    if let Some(document) = msg.document() {
+        ids.push(document.file.id.to_string());
+    }
+    if let Some(animation) = msg.animation() {
+        ids.push(animation.file.id.to_string());
+    }
+    if let Some(sticker) = msg.sticker() {
+        ids.push(sticker.file.id.to_string());
+    }
+    if let Some(audio) = msg.audio() {
+        ids.push(audio.file.id.to_string());
+    }
+    if let Some(voice) = msg.voice() {
+        ids.push(voice.file.id.to_string());
+    }
+    if let Some(video_note) = msg.video_note() {
+        ids.push(video_note.file.id.to_string());
+    }
+    ids
+}
+
+// Текст или подпись сообщения из файла архива
+pub fn archived_text(path: &str) -> Option<String> {
+    let content = fs::read_to_string(path).ok()?;
+    let value: serde_json::Value = serde_json::from_str(&content).ok()?;
+    let message = value.get("message")?;
+    message
+        .get("text")
+        .or_else(|| message.get("caption"))
+        .and_then(|t| t.as_str())
+        .map(str::to_owned)
+}
+
+// Удаляет файлы архива старше срока хранения. Ошибка с одним файлом не мешает
+// остальным: такие файлы попадут в лог и будут удалены при следующем проходе
+pub fn prune_archive(dir: &Path, retention: Duration) -> Result<usize> {
+    if !dir.exists() {
+        return Ok(0);
+    }
+    let now = SystemTime::now();
+    let mut removed = 0;
+    for entry in fs::read_dir(dir)? {
+        let result = entry.and_then(|entry| {
+            let modified = entry.metadata()?.modified()?;
+            if now.duration_since(modified).unwrap_or_default() > retention {
+                fs::remove_file(entry.path())?;
+                return Ok(true);
+            }
+            Ok(false)
+        });
+        match result {
+            Ok(true) => removed += 1,
+            Ok(false) => {}
+            Err(e) => warn!(
+                "Failed to prune archived message in {}: {}",
+                dir.display(),
+                e
+            ),
+        }
+    }
+    if removed > 0 {
+        info!(
+            "Pruned {} archived messages from {}",
+            removed,
+            dir.display()
+        );
+    }
+    Ok(removed)
+}
@@ -0,0 +1,337 @@
+use std::collections::BTreeSet;
+use std::fs::OpenOptions;
+use std::path::Path;
+use std::sync::{LazyLock, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::Router;
+use log::{info, warn};
+use teloxide::types::ChatId;
+
+use crate::config::Config;
+use crate::permissions::{self, Right};
+
+// Состояние процесса для /healthz и /readyz. Живость — процесс не завис
+// (очереди не отравлены паникой, getUpdates не стоит); готовность — бот
+// ещё и может модерировать: знает себя, получает обновления, имеет нужные права в группах,
+// может писать файлы.
+static HEALTH: LazyLock<Mutex<State>> = LazyLock::new(|| Mutex::new(State::default()));
+
+#[derive(Default)]
+struct State {
+    bot_info: bool,
+    polling: Option<Polling>,
+    // Группы, в которых проверяются права бота, и права, без которых
+    // включённые функции не работают
+    groups: Vec<(ChatId, Vec<Right>)>,
+    // None — запись ещё не проверялась
+    storage: Option<Vec<(String, std::result::Result<(), String>)>>,
+}
+
+struct Polling {
+    started: Instant,
+    stall_after: Duration,
+    last_success: Option<Instant>,
+    // Ошибка последнего getUpdates; None — последний запрос удался
+    last_error: Option<String>,
+}
+
+// Мьютекс держится только на время присваиваний, паника внутри невозможна;
+// отравление здесь не говорит о зависании, поэтому просто забираем значение
+fn state() -> MutexGuard<'static, State> {
+    HEALTH.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn bot_info_loaded() {
+    state().bot_info = true;
+}
+
+pub fn polling_started(stall_after: Duration) {
+    state().polling = Some(Polling {
+        started: Instant::now(),
+        stall_after,
+        last_success: None,
+        last_error: None,
+    });
+}
+
+pub fn poll_succeeded() {
+    if let Some(polling) = state().polling.as_mut() {
+        polling.last_success = Some(Instant::now());
+        polling.last_error = None;
+    }
+}
+
+pub fn poll_failed(error: &str) {
+    if let Some(polling) = state().polling.as_mut() {
+        polling.last_error = Some(error.to_string());
+    }
+}
+
+// Строка проверки: имя и None, если всё в порядке, или причина сбоя;
+// пояснение выводится в ответе, но на готовность не влияет
+struct Check {
+    name: String,
+    problem: Option<String>,
+    note: Option<String>,
+}
+
+impl Check {
+    fn new(name: impl Into<String>, problem: Option<String>) -> Self {
+        Self {
+            name: name.into(),
+            problem,
+            note: None,
+        }
+    }
+}
+
+fn liveness(now: Instant) -> Vec<Check> {
+    let mut checks = Vec::new();
+    let poisoned = |name: &str, poisoned: bool| {
+        Check::new(
+            name,
+            poisoned.then(|| "lock poisoned by a panic".to_string()),
+        )
+    };
+    checks.push(poisoned(
+        "deletion_queue",
+        crate::DELETION_QUEUE.get().is_some_and(|q| q.is_poisoned()),
+    ));
+    checks.push(poisoned(
+        "outbound_queue",
+        crate::OUTBOUND_QUEUE.get().is_some_and(|q| q.is_poisoned()),
+    ));
+    if let Some(polling) = &state().polling {
+        let last = polling.last_success.unwrap_or(polling.started);
+        let silent = now.saturating_duration_since(last);
+        let stalled = silent > polling.stall_after;
+        checks.push(Check::new(
+            "polling",
+            stalled.then(|| format!("no successful getUpdates for {} s", silent.as_secs())),
+        ));
+    }
+    checks
+}
+
+fn readiness(now: Instant) -> Vec<Check> {
+    let mut checks = liveness(now);
+    let state = state();
+    checks.push(Check::new(
+        "get_me",
+        (!state.bot_info).then(|| "bot info is not loaded".to_string()),
+    ));
+    if let Some(polling) = &state.polling {
+        let problem = match (&polling.last_error, polling.last_success) {
+            (Some(error), _) => Some(format!("last getUpdates failed: {}", error)),
+            (None, None) => Some("no successful getUpdates yet".to_string()),
+            (None, Some(_)) => None,
+        };
+        // Зависание уже попало в проверку живости
+        if let Some(check) = checks.iter_mut().find(|c| c.name == "polling") {
+            if check.problem.is_none() {
+                check.problem = problem;
+            }
+        }
+    }
+    for (chat_id, required) in &state.groups {
+        checks.push(check_group(*chat_id, required));
+    }
+    match &state.storage {
+        Some(storage) => {
+            for (path, result) in storage {
+                checks.push(Check::new(
+                    format!("storage {}", path),
+                    result.clone().err(),
+                ));
+            }
+        }
+        None => checks.push(Check::new("storage", Some("not checked yet".to_string()))),
+    }
+    checks
+}
+
+fn respond(checks: Vec<Check>) -> (StatusCode, String) {
+    let healthy = checks.iter().all(|c| c.problem.is_none());
+    let mut body = String::from(if healthy { "ok\n" } else { "fail\n" });
+    for check in checks {
+        body.push_str(&format!(
+            "{}: {}",
+            check.name,
+            check.problem.as_deref().unwrap_or("ok")
+        ));
+        if let Some(note) = check.note {
+            body.push_str(&format!(" ({})", note));
+        }
+        body.push('\n');
+    }
+    let status = if healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, body)
+}
+
+pub fn router() -> Router {
+    Router::new()
+        .route(
+            "/healthz",
+            get(|| async { respond(liveness(Instant::now())) }),
+        )
+        .route(
+            "/readyz",
+            get(|| async { respond(readiness(Instant::now())) }),
+        )
+}
+
+// Файлы, которые бот дописывает или перезаписывает во время работы
+pub fn storage_files(config: &Config) -> Vec<String> {
+    let mut files = BTreeSet::new();
+    files.insert(config.log.file.clone());
+    files.insert(config.telegram.deletion_queue_file.clone());
+    if config.global_bans.enabled {
+        files.insert(config.global_bans.file.clone());
+    }
+    for group in config.groups() {
+        files.insert(group.files.whitelist);
+        files.insert(group.files.forbidden_patterns);
+        files.insert(group.files.pending_users);
+        files.insert(group.files.modlog);
+        files.insert(group.files.pattern_stats);
+    }
+    files.into_iter().collect()
+}
+
+// Существующий файл должен открываться на запись, для нового — в каталоге
+// должно получаться создать файл
+fn check_writable(path: &str) -> std::result::Result<(), String> {
+    let path = Path::new(path);
+    if path.exists() {
+        return OpenOptions::new()
+            .append(true)
+            .open(path)
+            .map(|_| ())
+            .map_err(|e| e.to_string());
+    }
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let probe = dir.join(format!(".nstgbr-write-check-{}", std::process::id()));
+    std::fs::write(&probe, b"").map_err(|e| format!("{}: {}", dir.display(), e))?;
+    std::fs::remove_file(&probe).map_err(|e| format!("{}: {}", dir.display(), e))
+}
+
+// Права бота проверяет run_rights_check, здесь берётся её последний результат.
+// Готовность зависит только от прав, нужных включённым функциям
+fn check_group(chat_id: ChatId, required: &[Right]) -> Check {
+    let name = format!("group {}", chat_id);
+    let Some(missing) = permissions::missing(chat_id) else {
+        return Check::new(name, Some("bot rights are not checked yet".to_string()));
+    };
+    let (needed, optional): (Vec<Right>, Vec<Right>) =
+        missing.into_iter().partition(|r| required.contains(r));
+    let mut check = Check::new(
+        name,
+        (!needed.is_empty()).then(|| format!("bot lacks {}", permissions::api_names(&needed))),
+    );
+    if !optional.is_empty() {
+        check.note = Some(format!(
+            "not required: {}",
+            permissions::api_names(&optional)
+        ));
+    }
+    check
+}
+
+// Периодически проверяет запись в хранилище; новые сбои пишутся в лог
+// (о правах в группах сообщает сама проверка прав)
+pub async fn run_probe(groups: Vec<(ChatId, Vec<Right>)>, files: Vec<String>, interval: Duration) {
+    state().groups = groups;
+    let mut failing = BTreeSet::new();
+    loop {
+        let storage: Vec<_> = files
+            .iter()
+            .map(|file| (file.clone(), check_writable(file)))
+            .collect();
+        let problems: BTreeSet<String> = storage
+            .iter()
+            .filter_map(|(path, r)| r.as_ref().err().map(|e| format!("{}: {}", path, e)))
+            .collect();
+        for problem in problems.difference(&failing) {
+            warn!("Storage is not writable: {}", problem);
+        }
+        if problems.is_empty() && !failing.is_empty() {
+            info!("Storage is writable again");
+        }
+        failing = problems;
+        state().storage = Some(storage);
+        tokio::time::sleep(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_writable_files_and_directories() {
+        let dir = std::env::temp_dir().join(format!("nstgbr-health-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let existing = dir.join("whitelist.txt");
+        std::fs::write(&existing, "").unwrap();
+
+        assert!(check_writable(existing.to_str().unwrap()).is_ok());
+        assert!(check_writable(dir.join("new.txt").to_str().unwrap()).is_ok());
+        assert!(check_writable(dir.join("missing/new.txt").to_str().unwrap()).is_err());
+        // Проверка не оставляет за собой файлов
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failing_check_makes_response_unavailable() {
+        let (status, body) = respond(vec![
+            Check::new("get_me", None),
+            Check::new(
+                "polling",
+                Some("no successful getUpdates for 300 s".to_string()),
+            ),
+        ]);
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            body,
+            "fail\nget_me: ok\npolling: no successful getUpdates for 300 s\n"
+        );
+        let (status, _) = respond(vec![Check::new("get_me", None)]);
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[test]
+    fn only_required_rights_affect_readiness() {
+        let group = ChatId(-100888);
+        permissions::record(group, vec![Right::Invite]);
+        let check = check_group(group, &[Right::Delete]);
+        assert_eq!(check.problem, None);
+        assert_eq!(
+            check.note.as_deref(),
+            Some("not required: can_invite_users")
+        );
+
+        permissions::record(group, vec![Right::Ban, Right::Invite]);
+        let check = check_group(group, &[Right::Delete, Right::Ban]);
+        assert_eq!(
+            check.problem.as_deref(),
+            Some("bot lacks can_restrict_members")
+        );
+        let (status, body) = respond(vec![check]);
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            body,
+            "fail\ngroup -100888: bot lacks can_restrict_members (not required: can_invite_users)\n"
+        );
+    }
+}
@@ -0,0 +1,37 @@
+use std::net::SocketAddr;
+
+use axum::Router;
+use log::{error, info};
+
+use crate::Result;
+
+// Служебные HTTP-эндпоинты (/metrics, /healthz, /readyz). Роутеры с одним адресом
+// обслуживает один сервер, так что метрики и проверки могут делить порт.
+pub async fn serve(apps: Vec<(&str, Router)>) -> Result<()> {
+    let mut servers: Vec<(SocketAddr, Router)> = Vec::new();
+    for (listen, app) in apps {
+        let address: SocketAddr = listen.parse()?;
+        match servers.iter_mut().find(|(a, _)| *a == address) {
+            Some((_, router)) => *router = std::mem::take(router).merge(app),
+            None => servers.push((address, app)),
+        }
+    }
+
+    // Сначала занимаем все адреса, чтобы ошибка остановила запуск целиком
+    let mut listeners = Vec::new();
+    for (address, app) in servers {
+        let listener = tokio::net::TcpListener::bind(address)
+            .await
+            .map_err(|e| format!("failed to listen on {}: {}", address, e))?;
+        listeners.push((address, listener, app));
+    }
+    for (address, listener, app) in listeners {
+        info!("Serving HTTP endpoints on http://{}", address);
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                error!("HTTP server on {} failed: {}", address, e);
+            }
+        });
+    }
+    Ok(())
+}
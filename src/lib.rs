@@ -0,0 +1,3649 @@
+mod actions;
+mod appeals;
+mod bans;
+pub mod config;
+mod deletions;
+mod evidence;
+mod health;
+mod http;
+mod logging;
+mod messages;
+mod metrics;
+mod modlog;
+mod outbound;
+mod patterns;
+mod pending;
+mod permissions;
+mod polling;
+mod raid;
+mod reports;
+mod retry;
+#[cfg(test)]
+mod test_api;
+mod trust;
+mod webhook;
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use dotenv::dotenv;
+use log::{debug, error, info, warn};
+use teloxide::adaptors::throttle::Limits;
+use teloxide::adaptors::Throttle;
+use teloxide::dispatching::{Dispatcher, UpdateHandler};
+use teloxide::error_handlers::LoggingErrorHandler;
+use teloxide::prelude::*;
+use teloxide::types::{
+    CallbackQuery, Chat, ChatId, ChatMemberStatus, ChatMemberUpdated, ChatPermissions,
+    InlineKeyboardButton, InlineKeyboardMarkup, Message, MessageId, User, UserId,
+};
+use tokio::sync::Mutex;
+
+use crate::appeals::{Appeal, AppealCallback, AppealDecision, Appeals};
+use crate::bans::GlobalBans;
+use crate::config::{Config, FeaturesConfig, TelegramConfig};
+use crate::deletions::DeletionQueue;
+use crate::evidence::{Evidence, EvidenceSettings};
+use crate::messages::{MessageSettings, Messages};
+use crate::modlog::{
+    CallbackAction, DeletionDigest, ModAction, ModLog, ModRecord, RecordStatus, Target, UndoKind,
+};
+use crate::outbound::{OutboundQueue, QueueSaturated};
+use crate::patterns::{ForbiddenPatterns, PatternStats};
+use crate::pending::PendingUsers;
+use crate::permissions::Right;
+use crate::raid::{RaidGuard, RaidSettings, RaidTrigger};
+use crate::reports::{Report, ReportAction, ReportCallback, ReportSettings, Reports};
+use crate::retry::ErrorClass;
+use crate::trust::{TrustLevel, TrustSettings, Violation, WhitelistEntry};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+// Повторы запросов и автоудаление ответов используются повсюду, поэтому эти
+// настройки задаются один раз при запуске, а не передаются через BotState
+static TELEGRAM_CONFIG: OnceLock<TelegramConfig> = OnceLock::new();
+
+fn telegram_config() -> &'static TelegramConfig {
+    TELEGRAM_CONFIG.get_or_init(TelegramConfig::default)
+}
+
+// Очередь автоудаления ответов бота; её разбирает run_deletion_scheduler
+static DELETION_QUEUE: OnceLock<DeletionQueue> = OnceLock::new();
+
+// Все запросы к Bot API проходят через приоритетную очередь и Throttle
+static OUTBOUND_QUEUE: OnceLock<OutboundQueue> = OnceLock::new();
+
+// Как часто сохранять счётчики чистых сообщений новичков
+const WHITELIST_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+// Как часто отправлять сводку удалений у неподтверждённых
+const DELETION_DIGEST_INTERVAL: Duration = Duration::from_secs(60);
+// Пауза перед повтором отложенного удаления после сбоя сети или Telegram
+const DELETION_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+pub type ThrottledBot = Throttle<Bot>;
+
+#[derive(Debug, Clone)]
+enum Command {
+    Start,
+    Confirm,
+    Whois,
+    Trust,
+    Lockdown,
+    Patterns,
+    Fp,
+    Report,
+    Appeal,
+}
+
+impl Command {
+    fn parse(text: &str) -> Option<Self> {
+        let cmd_text = text.split_whitespace().next().unwrap_or("");
+        let cmd_text = cmd_text.split('@').next().unwrap_or(cmd_text);
+        match cmd_text {
+            "/start" => Some(Command::Start),
+            "/confirm" => Some(Command::Confirm),
+            "/whois" => Some(Command::Whois),
+            "/trust" => Some(Command::Trust),
+            "/lockdown" => Some(Command::Lockdown),
+            "/patterns" => Some(Command::Patterns),
+            "/fp" => Some(Command::Fp),
+            "/report" => Some(Command::Report),
+            "/appeal" => Some(Command::Appeal),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UnverifiedMode {
+    // Удалять сообщения неподтверждённых пользователей
+    Delete,
+    // Удалять сообщение и переводить пользователя в режим только чтения до /confirm
+    Restrict,
+}
+
+impl UnverifiedMode {
+    // Значение уже проверено при загрузке конфигурации
+    fn from_config(mode: &str) -> Self {
+        match mode {
+            "restrict" => UnverifiedMode::Restrict,
+            _ => UnverifiedMode::Delete,
+        }
+    }
+}
+
+struct GroupSettings {
+    chat_id: ChatId,
+    whitelist_file: String,
+    patterns_file: String,
+    // Общий для групп с одним файлом шаблонов: исключение видно всем сразу
+    patterns: Arc<Mutex<ForbiddenPatterns>>,
+    messages: MessageSettings,
+}
+
+struct SenderChatSettings {
+    // Привязанный канал обсуждений группы
+    linked_chat_id: Option<ChatId>,
+    ban_unknown: bool,
+}
+
+struct VerificationSettings {
+    mode: UnverifiedMode,
+    window: Duration,
+    bot_username: String,
+    secret_code: Option<String>,
+    pending_file: String,
+    // Через сколько неподтверждённых выгоняют из группы; None — не выгонять
+    kick_after: Option<Duration>,
+    kick_check_interval: Duration,
+}
+
+struct ModerationSettings {
+    admin_log_chat_id: Option<ChatId>,
+    modlog_file: String,
+    pattern_stats_file: String,
+    evidence: EvidenceSettings,
+    raid: RaidSettings,
+    reports: ReportSettings,
+    features: FeaturesConfig,
+    global_bans: Option<Arc<GlobalBans>>,
+}
+
+struct BotState {
+    whitelist: Mutex<HashMap<UserId, WhitelistEntry>>,
+    // Каналы, от имени которых разрешено писать (отрицательные ID в whitelist)
+    whitelisted_chats: Mutex<HashMap<ChatId, String>>,
+    whitelist_file: String,
+    // Запись в файл белого списка: дописывание и полная перезапись не перемешиваются
+    whitelist_file_lock: Mutex<()>,
+    // Счётчики чистых сообщений изменились, а файл ещё не перезаписан
+    whitelist_dirty: AtomicBool,
+    group_chat_id: ChatId,
+    sender_chats: SenderChatSettings,
+    forbidden_patterns: Arc<Mutex<ForbiddenPatterns>>,
+    patterns_file: String,
+    pattern_stats: PatternStats,
+    // None — подтверждение секретным кодом отключено
+    secret_code: Option<String>,
+    trust: TrustSettings,
+    recent_messages: Mutex<HashMap<UserId, VecDeque<Instant>>>,
+    unverified_mode: UnverifiedMode,
+    verification_window: Duration,
+    // Когда пользователю последний раз отправлялся запрос на подтверждение
+    prompted: Mutex<HashMap<UserId, Instant>>,
+    bot_username: String,
+    pending: PendingUsers,
+    kick_after: Option<Duration>,
+    kick_check_interval: Duration,
+    admin_log_chat_id: Option<ChatId>,
+    modlog: ModLog,
+    deletion_digest: DeletionDigest,
+    evidence: EvidenceSettings,
+    raid: RaidGuard,
+    reports: Reports,
+    appeals: Appeals,
+    messages: Messages,
+    features: FeaturesConfig,
+    // Общий для всех групп список банов; None — баны действуют только в своей группе
+    global_bans: Option<Arc<GlobalBans>>,
+}
+
+impl BotState {
+    fn new(
+        group: GroupSettings,
+        sender_chats: SenderChatSettings,
+        trust: TrustSettings,
+        verification: VerificationSettings,
+        moderation: ModerationSettings,
+    ) -> Self {
+        info!("Initializing BotState for group {}", group.chat_id.0);
+        let (whitelist, whitelisted_chats) = Self::load_whitelist(&group.whitelist_file);
+        metrics::set_whitelist_size(group.chat_id, whitelist.len());
+        Self {
+            whitelist: Mutex::new(whitelist),
+            whitelisted_chats: Mutex::new(whitelisted_chats),
+            whitelist_file: group.whitelist_file,
+            whitelist_file_lock: Mutex::new(()),
+            whitelist_dirty: AtomicBool::new(false),
+            group_chat_id: group.chat_id,
+            sender_chats,
+            forbidden_patterns: group.patterns,
+            patterns_file: group.patterns_file,
+            pattern_stats: PatternStats::load(&moderation.pattern_stats_file),
+            secret_code: verification.secret_code,
+            trust,
+            recent_messages: Mutex::new(HashMap::new()),
+            unverified_mode: verification.mode,
+            verification_window: verification.window,
+            prompted: Mutex::new(HashMap::new()),
+            bot_username: verification.bot_username,
+            pending: PendingUsers::load(&verification.pending_file),
+            kick_after: verification.kick_after,
+            kick_check_interval: verification.kick_check_interval,
+            admin_log_chat_id: moderation.admin_log_chat_id,
+            modlog: ModLog::load(&moderation.modlog_file),
+            deletion_digest: DeletionDigest::default(),
+            evidence: moderation.evidence,
+            raid: RaidGuard::new(moderation.raid),
+            reports: Reports::new(moderation.reports),
+            appeals: Appeals::default(),
+            messages: Messages::load(&group.messages),
+            features: moderation.features,
+            global_bans: moderation.global_bans,
+        }
+    }
+
+    fn load_whitelist(path: &str) -> (HashMap<UserId, WhitelistEntry>, HashMap<ChatId, String>) {
+        info!("Loading whitelist from {}", path);
+        let mut whitelist = HashMap::new();
+        let mut chats = HashMap::new();
+
+        if !Path::new(path).exists() {
+            warn!(
+                "Whitelist file {} does not exist, creating empty whitelist",
+                path
+            );
+            return (whitelist, chats);
+        }
+
+        match File::open(path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines().map_while(|l| l.ok()) {
+                    let line = line.trim();
+                    let (id, rest) = line.split_once(' ').unwrap_or((line, ""));
+                    match id.parse::<i64>() {
+                        Ok(id) if id < 0 => {
+                            chats.insert(ChatId(id), rest.trim().to_string());
+                        }
+                        Ok(id) => {
+                            whitelist.insert(UserId(id as u64), WhitelistEntry::parse(rest));
+                        }
+                        Err(_) => {}
+                    }
+                }
+                info!(
+                    "Loaded {} whitelisted users and {} whitelisted chats",
+                    whitelist.len(),
+                    chats.len()
+                );
+            }
+            Err(e) => error!("Failed to load whitelist: {}", e),
+        }
+        (whitelist, chats)
+    }
+
+    // Полная перезапись файла: нужна, когда меняется уровень или счётчик сообщений.
+    // Списки копируются по очереди и пишутся по возрастанию ID, чтобы строки
+    // не переставлялись от записи к записи
+    async fn save_whitelist(&self) -> Result<()> {
+        let _file = self.whitelist_file_lock.lock().await;
+        self.whitelist_dirty.store(false, Ordering::Relaxed);
+        let chats: BTreeMap<i64, String> = self
+            .whitelisted_chats
+            .lock()
+            .await
+            .iter()
+            .map(|(chat_id, title)| (chat_id.0, title.clone()))
+            .collect();
+        let users: BTreeMap<u64, String> = self
+            .whitelist
+            .lock()
+            .await
+            .iter()
+            .map(|(user_id, entry)| (user_id.0, entry.format()))
+            .collect();
+
+        let tmp_path = format!("{}.tmp", self.whitelist_file);
+        {
+            let mut file = File::create(&tmp_path)?;
+            for (chat_id, title) in &chats {
+                writeln!(file, "{} {}", chat_id, title)?;
+            }
+            for (user_id, entry) in &users {
+                writeln!(file, "{} {}", user_id, entry)?;
+            }
+            file.sync_all()?;
+        }
+        std::fs::rename(&tmp_path, &self.whitelist_file)?;
+        debug!("Saved whitelist with {} users", users.len());
+        Ok(())
+    }
+
+    // Сохраняет накопленные счётчики чистых сообщений
+    async fn flush_whitelist(&self) -> Result<()> {
+        if self.whitelist_dirty.load(Ordering::Relaxed) {
+            self.save_whitelist().await?;
+        }
+        Ok(())
+    }
+
+    async fn add_to_whitelist(&self, user_id: UserId, username: &str) -> Result<()> {
+        info!("Adding user {} ({}) to whitelist", user_id.0, username);
+        let _file = self.whitelist_file_lock.lock().await;
+        let mut whitelist = self.whitelist.lock().await;
+        if whitelist.contains_key(&user_id) {
+            warn!("User {} was already in whitelist", user_id.0);
+            return Ok(());
+        }
+
+        let entry = WhitelistEntry::new(username, chrono::Utc::now().timestamp());
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.whitelist_file)?;
+        writeln!(file, "{} {}", user_id.0, entry.format())?;
+        whitelist.insert(user_id, entry);
+        metrics::set_whitelist_size(self.group_chat_id, whitelist.len());
+        info!("Successfully added user {} to whitelist file", user_id.0);
+        drop(whitelist);
+
+        if let Err(e) = self.pending.remove(user_id).await {
+            error!("Failed to remove user {} from pending list: {}", user_id, e);
+        }
+        Ok(())
+    }
+
+    async fn remove_from_whitelist(&self, user_id: UserId) -> Result<bool> {
+        let mut whitelist = self.whitelist.lock().await;
+        if whitelist.remove(&user_id).is_none() {
+            warn!("User {} was not in whitelist", user_id);
+            return Ok(false);
+        }
+        info!("Removing user {} from whitelist", user_id);
+        metrics::set_whitelist_size(self.group_chat_id, whitelist.len());
+        drop(whitelist);
+        self.save_whitelist().await?;
+        Ok(true)
+    }
+
+    async fn add_chat_to_whitelist(&self, chat_id: ChatId, title: &str) -> Result<()> {
+        let _file = self.whitelist_file_lock.lock().await;
+        let mut chats = self.whitelisted_chats.lock().await;
+        if chats.contains_key(&chat_id) {
+            warn!("Sender chat {} was already in whitelist", chat_id);
+            return Ok(());
+        }
+        info!("Adding sender chat {} ({}) to whitelist", chat_id, title);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.whitelist_file)?;
+        writeln!(file, "{} {}", chat_id.0, title)?;
+        chats.insert(chat_id, title.to_string());
+        Ok(())
+    }
+
+    async fn remove_chat_from_whitelist(&self, chat_id: ChatId) -> Result<bool> {
+        if self
+            .whitelisted_chats
+            .lock()
+            .await
+            .remove(&chat_id)
+            .is_none()
+        {
+            warn!("Sender chat {} was not in whitelist", chat_id);
+            return Ok(false);
+        }
+        info!("Removing sender chat {} from whitelist", chat_id);
+        self.save_whitelist().await?;
+        Ok(true)
+    }
+
+    async fn whitelist_entry(&self, user_id: UserId) -> Option<WhitelistEntry> {
+        self.whitelist.lock().await.get(&user_id).cloned()
+    }
+
+    async fn set_trust_level(&self, user_id: UserId, level: TrustLevel) -> Result<bool> {
+        let mut whitelist = self.whitelist.lock().await;
+        match whitelist.get_mut(&user_id) {
+            Some(entry) => {
+                info!(
+                    "Changing trust level of user {} from {} to {}",
+                    user_id, entry.level, level
+                );
+                entry.level = level;
+                drop(whitelist);
+                self.save_whitelist().await?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    // Засчитывает чистое сообщение новичку; возвращает новый уровень при повышении.
+    // Повышение сохраняется сразу, счётчик — периодически в run_whitelist_flusher
+    async fn record_clean_message(&self, user_id: UserId) -> Result<Option<TrustLevel>> {
+        let mut whitelist = self.whitelist.lock().await;
+        let Some(entry) = whitelist.get_mut(&user_id) else {
+            return Ok(None);
+        };
+        if entry.level != TrustLevel::New {
+            return Ok(None);
+        }
+
+        entry.clean_messages += 1;
+        if !self
+            .trust
+            .is_ready_for_regular(entry, chrono::Utc::now().timestamp())
+        {
+            self.whitelist_dirty.store(true, Ordering::Relaxed);
+            return Ok(None);
+        }
+        entry.level = TrustLevel::Regular;
+        info!(
+            "User {} graduated to {} after {} clean messages",
+            user_id, entry.level, entry.clean_messages
+        );
+        drop(whitelist);
+        self.save_whitelist().await?;
+        Ok(Some(TrustLevel::Regular))
+    }
+
+    // Скользящее окно сообщений пользователя для ограничения флуда
+    async fn check_flood(&self, user_id: UserId, limit: usize) -> bool {
+        let window = Duration::from_secs(self.trust.flood_window_secs);
+        let now = Instant::now();
+        let mut recent = self.recent_messages.lock().await;
+        let timestamps = recent.entry(user_id).or_default();
+        while timestamps
+            .front()
+            .is_some_and(|t| now.duration_since(*t) > window)
+        {
+            timestamps.pop_front();
+        }
+        timestamps.push_back(now);
+        timestamps.len() > limit
+    }
+
+    // Режим restrict работает, только пока у бота есть право ограничивать участников
+    fn restrict_mode(&self) -> bool {
+        self.unverified_mode == UnverifiedMode::Restrict
+            && permissions::allows(self.group_chat_id, Right::Restrict)
+    }
+
+    // Права, без которых не работают включённые в группе функции
+    fn required_rights(&self) -> Vec<Right> {
+        let mut rights = vec![Right::Delete];
+        if self.unverified_mode == UnverifiedMode::Restrict
+            || self.raid.settings.lock_group_permissions
+        {
+            rights.push(Right::Restrict);
+        }
+        if self.kick_after.is_some() || self.sender_chats.ban_unknown || self.global_bans.is_some()
+        {
+            rights.push(Right::Ban);
+        }
+        rights
+    }
+
+    async fn is_whitelisted(&self, user_id: UserId) -> bool {
+        let whitelist = self.whitelist.lock().await;
+        let is_whitelisted = whitelist.contains_key(&user_id);
+        debug!(
+            "Checking if user {} is whitelisted: {}",
+            user_id.0, is_whitelisted
+        );
+        is_whitelisted
+    }
+
+    async fn is_chat_whitelisted(&self, chat_id: ChatId) -> bool {
+        let chats = self.whitelisted_chats.lock().await;
+        let is_whitelisted = chats.contains_key(&chat_id);
+        debug!(
+            "Checking if sender chat {} is whitelisted: {}",
+            chat_id, is_whitelisted
+        );
+        is_whitelisted
+    }
+
+    async fn check_message(&self, text: &str) -> Option<String> {
+        let patterns = self.forbidden_patterns.lock().await;
+        let matched = patterns.find_match(text);
+        if let Some(pattern) = &matched {
+            warn!(
+                "Message matched forbidden pattern '{}': '{}'",
+                pattern,
+                logging::redact(Some(text))
+            );
+        }
+        matched
+    }
+
+    async fn add_pattern_exception(&self, text: &str) -> Result<()> {
+        // Блокировка общая для групп с этим файлом, записи в него не перемешиваются
+        let mut patterns = self.forbidden_patterns.lock().await;
+        ForbiddenPatterns::add_exception(&self.patterns_file, text)?;
+        *patterns = ForbiddenPatterns::load(&self.patterns_file);
+        Ok(())
+    }
+
+    // Не чаще одного запроса на подтверждение за окно верификации
+    async fn should_prompt(&self, user_id: UserId) -> bool {
+        let now = Instant::now();
+        let mut prompted = self.prompted.lock().await;
+        // Записи старше окна уже ничего не ограничивают
+        prompted.retain(|_, last| now.duration_since(*last) < self.verification_window);
+        match prompted.get(&user_id) {
+            Some(last) if now.duration_since(*last) < self.verification_window => {
+                debug!("User {} was already prompted recently", user_id);
+                false
+            }
+            _ => {
+                prompted.insert(user_id, now);
+                true
+            }
+        }
+    }
+
+    async fn clear_prompt(&self, user_id: UserId) {
+        self.prompted.lock().await.remove(&user_id);
+    }
+
+    async fn check_secret_code(&self, text: &str) -> bool {
+        self.secret_code
+            .as_deref()
+            .is_some_and(|code| text.trim() == code)
+    }
+}
+
+// Все обслуживаемые процессом группы. Каждое обновление направляется в состояние
+// своей группы, чужие чаты не обрабатываются.
+pub struct Groups {
+    states: Vec<Arc<BotState>>,
+    unknown: UnknownChatSettings,
+    // Незнакомые чаты, о которых уже сообщили владельцу и из которых ещё не вышли
+    unknown_chats: Arc<Mutex<HashSet<ChatId>>>,
+}
+
+struct UnknownChatSettings {
+    owner_id: Option<UserId>,
+    // Чаты, где бот может находиться, ничего в них не делая
+    allowed_chats: HashSet<ChatId>,
+    // None — не выходить из незнакомых чатов, только игнорировать их
+    leave_after: Option<Duration>,
+}
+
+impl Groups {
+    fn new(states: Vec<Arc<BotState>>, unknown: UnknownChatSettings) -> Self {
+        Self {
+            states,
+            unknown,
+            unknown_chats: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    // Чат без модерации, в котором боту разрешено оставаться
+    fn is_allowed(&self, chat_id: ChatId) -> bool {
+        self.unknown.allowed_chats.contains(&chat_id)
+            || self
+                .states
+                .iter()
+                .any(|s| s.sender_chats.linked_chat_id == Some(chat_id))
+    }
+
+    // Сама группа, затем её чат администраторов или чат доказательств
+    fn by_chat(&self, chat_id: ChatId) -> Option<Arc<BotState>> {
+        self.states
+            .iter()
+            .find(|s| s.group_chat_id == chat_id)
+            .or_else(|| {
+                self.states
+                    .iter()
+                    .find(|s| s.admin_log_chat_id == Some(chat_id))
+            })
+            .or_else(|| {
+                self.states
+                    .iter()
+                    .find(|s| s.evidence.chat_id == Some(chat_id))
+            })
+            .cloned()
+    }
+
+    async fn route_message(&self, bot: &ThrottledBot, msg: &Message) -> Option<Arc<BotState>> {
+        if msg.chat.is_private() {
+            metrics::message_processed(true);
+            return Some(self.for_private(bot, msg).await);
+        }
+        if let Some(state) = self.by_chat(msg.chat.id) {
+            metrics::message_processed(false);
+            return Some(state);
+        }
+        if !self.is_allowed(msg.chat.id) {
+            self.handle_unknown_chat(bot, &msg.chat, None).await;
+        }
+        None
+    }
+
+    // Кнопки бот присылает только в чаты администраторов
+    fn route_callback(&self, q: &CallbackQuery) -> Option<Arc<BotState>> {
+        let chat_id = q.message.as_ref()?.chat().id;
+        let state = self
+            .states
+            .iter()
+            .find(|s| s.admin_log_chat_id == Some(chat_id))
+            .cloned();
+        if state.is_none() {
+            warn!("Ignoring callback from unconfigured chat {}", chat_id);
+        }
+        state
+    }
+
+    // В личке группа не видна, поэтому выбираем ту, к которой относится сообщение:
+    // ожидаемая апелляция, последнее обжалуемое действие, секретный код группы,
+    // затем группа, где пользователь ещё не подтверждён (сначала по списку ожидающих)
+    async fn for_private(&self, bot: &ThrottledBot, msg: &Message) -> Arc<BotState> {
+        let first = self.states[0].clone();
+        let Some(user) = msg.from.as_ref().filter(|_| self.states.len() > 1) else {
+            return first;
+        };
+
+        for state in &self.states {
+            if state.appeals.is_awaiting(user.id).await {
+                return state.clone();
+            }
+        }
+
+        let text = msg.text().unwrap_or("");
+        if matches!(Command::parse(text), Some(Command::Appeal)) {
+            let mut latest: Option<(i64, &Arc<BotState>)> = None;
+            for state in &self.states {
+                if let Some(record) = state.modlog.latest_appealable(user.id.0).await {
+                    if latest.is_none_or(|(time, _)| record.time > time) {
+                        latest = Some((record.time, state));
+                    }
+                }
+            }
+            if let Some((_, state)) = latest {
+                return state.clone();
+            }
+        }
+
+        for state in &self.states {
+            if state.check_secret_code(text).await {
+                return state.clone();
+            }
+        }
+
+        // Запрос к Telegram — только когда локальных данных не хватает
+        let mut unverified = Vec::new();
+        for state in &self.states {
+            if !state.is_whitelisted(user.id).await {
+                if state.pending.contains(user.id).await {
+                    return state.clone();
+                }
+                unverified.push(state);
+            }
+        }
+        match unverified.as_slice() {
+            [] => first,
+            [state] => (*state).clone(),
+            states => {
+                for state in states {
+                    if is_group_member(bot, state, user.id).await {
+                        return (*state).clone();
+                    }
+                }
+                first
+            }
+        }
+    }
+
+    // Сообщает владельцу о чужом чате и выходит из него после паузы
+    async fn handle_unknown_chat(&self, bot: &ThrottledBot, chat: &Chat, added_by: Option<&User>) {
+        if !self.unknown_chats.lock().await.insert(chat.id) {
+            return;
+        }
+        warn!(
+            "Refusing to work in unconfigured chat {} ({})",
+            chat.id,
+            chat.title().unwrap_or("")
+        );
+
+        if let Some(owner_id) = self.unknown.owner_id {
+            let mut text = format!(
+                "⚠️ Бот находится в чате, которого нет в конфигурации: {} ({})",
+                chat.title().unwrap_or(""),
+                chat.id
+            );
+            if let Some(user) = added_by {
+                text.push_str(&format!("\nДобавил: {} ({})", user.full_name(), user.id));
+            }
+            match self.unknown.leave_after {
+                Some(delay) => text.push_str(&format!(
+                    "\nБот выйдет из него через {} с.",
+                    delay.as_secs()
+                )),
+                None => text.push_str("\nСообщения из него игнорируются."),
+            }
+            if let Err(e) = actions::send(
+                bot,
+                owner_id.into(),
+                text,
+                "notify owner about unknown chat",
+            )
+            .await
+            {
+                error!("Failed to notify owner about chat {}: {}", chat.id, e);
+            }
+        }
+
+        let Some(delay) = self.unknown.leave_after else {
+            return;
+        };
+        let chat_id = chat.id;
+        let bot = bot.clone();
+        let unknown_chats = self.unknown_chats.clone();
+        info!("Leaving chat {} in {} seconds", chat_id, delay.as_secs());
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            match actions::leave_chat(&bot, chat_id, "leave unconfigured chat").await {
+                Ok(_) => info!("Left unconfigured chat {}", chat_id),
+                Err(e) => error!("Failed to leave chat {}: {}", chat_id, e),
+            }
+            // Если бота добавят снова, владелец получит новое уведомление
+            unknown_chats.lock().await.remove(&chat_id);
+        });
+    }
+}
+
+// Бота добавили в чат: всё, что не входит в конфигурацию, сразу помечается чужим
+async fn handle_my_chat_member(
+    bot: ThrottledBot,
+    update: ChatMemberUpdated,
+    groups: Arc<Groups>,
+) -> Result<()> {
+    let chat_id = update.chat.id;
+    if update.chat.is_private() || !update.new_chat_member.is_present() {
+        return Ok(());
+    }
+    if groups.by_chat(chat_id).is_some() || groups.is_allowed(chat_id) {
+        info!("Bot membership updated in known chat {}", chat_id);
+        return Ok(());
+    }
+    info!(
+        "Bot was added to chat {} by user {}",
+        chat_id, update.from.id
+    );
+    groups
+        .handle_unknown_chat(&bot, &update.chat, Some(&update.from))
+        .await;
+    Ok(())
+}
+
+async fn handle_start(bot: ThrottledBot, msg: Message, state: Arc<BotState>) -> Result<()> {
+    let user = match msg.from.as_ref() {
+        Some(user) => {
+            info!(
+                "Received /start from user {} ({} @{}) in chat {}",
+                user.id,
+                user.full_name(),
+                user.username.as_deref().unwrap_or(""),
+                msg.chat.id
+            );
+            user
+        }
+        None => {
+            warn!("Received /start without user info");
+            return Ok(());
+        }
+    };
+
+    // Ссылка из запроса на подтверждение: t.me/<bot>?start=confirm
+    if msg.chat.is_private()
+        && msg.text().and_then(|t| t.split_whitespace().nth(1)) == Some("confirm")
+    {
+        info!("User {} opened confirmation deep link", user.id);
+        return handle_confirm(bot, msg, state).await;
+    }
+
+    let key = if state.is_whitelisted(user.id).await {
+        "start.already_confirmed"
+    } else {
+        "start.instructions"
+    };
+    let text = state.messages.get(Some(user), key, &[]);
+
+    let chat_id = msg.chat.id;
+    info!("Sending start response to chat {}", chat_id);
+    actions::reply_ephemeral(&bot, chat_id, text, "send start response").await?;
+    Ok(())
+}
+
+async fn handle_confirm(bot: ThrottledBot, msg: Message, state: Arc<BotState>) -> Result<()> {
+    let user = match msg.from.as_ref() {
+        Some(user) => {
+            info!(
+                "Received /confirm from user {} ({} @{}) in chat {}",
+                user.id,
+                user.full_name(),
+                user.username.as_deref().unwrap_or(""),
+                msg.chat.id
+            );
+            user
+        }
+        None => {
+            warn!("Received /confirm without user info");
+            return Ok(());
+        }
+    };
+
+    let chat_id = msg.chat.id;
+    let message_id = msg.id;
+
+    info!(
+        "Deleting /confirm command from user {} in chat {}",
+        user.id, chat_id
+    );
+    actions::delete_command(&bot, chat_id, message_id, "delete confirm command").await?;
+
+    if state.is_whitelisted(user.id).await {
+        info!("User {} is already whitelisted", user.id);
+        if state.restrict_mode() {
+            lift_restrictions(&bot, &state, user.id).await;
+        }
+        let text = state
+            .messages
+            .get(Some(user), "confirm.already_confirmed", &[]);
+        actions::reply_ephemeral(&bot, chat_id, text, "send already confirmed message").await?;
+        return Ok(());
+    }
+
+    let group_chat_id = state.group_chat_id;
+    let user_id = user.id;
+
+    info!(
+        "Checking group membership for user {} in group {}",
+        user_id, group_chat_id
+    );
+    match actions::get_chat_member(&bot, group_chat_id, user_id, "get chat member").await {
+        Ok(member) if is_member(&member) => {
+            let username = user
+                .username
+                .as_deref()
+                .unwrap_or(&user.first_name)
+                .to_owned();
+            info!(
+                "User {} is a member of group {}, adding to whitelist",
+                user_id, group_chat_id
+            );
+
+            if let Err(e) = state.add_to_whitelist(user.id, &username).await {
+                error!("Failed to add to whitelist: {}", e);
+                let text = state.messages.get(Some(user), "error.try_later", &[]);
+                actions::reply_ephemeral(&bot, chat_id, text, "send whitelist error").await?;
+                return Ok(());
+            }
+
+            record_action(
+                &bot,
+                &state,
+                ModRecord::new(
+                    ModAction::WhitelistAdd,
+                    group_chat_id.0,
+                    user_target(user),
+                    "confirm",
+                ),
+            )
+            .await;
+
+            state.clear_prompt(user_id).await;
+            if state.restrict_mode() {
+                lift_restrictions(&bot, &state, user_id).await;
+            }
+
+            let key = match member.status() {
+                ChatMemberStatus::Member | ChatMemberStatus::Restricted => "confirm.success",
+                _ => "confirm.admin",
+            };
+            let text = state.messages.get(Some(user), key, &[]);
+
+            info!("User {} successfully confirmed", user_id);
+            metrics::verification("confirm");
+            actions::reply_ephemeral(&bot, chat_id, text, "send confirmation message").await?;
+        }
+        Ok(_) => {
+            warn!(
+                "User {} is not a member of group {}",
+                user_id, group_chat_id
+            );
+            let text = state.messages.get(Some(user), "confirm.not_member", &[]);
+            actions::reply_ephemeral(&bot, chat_id, text, "send not member message").await?;
+        }
+        Err(e) => {
+            error!(
+                "Failed to check group membership for user {}: {}",
+                user.id, e
+            );
+            let text = state
+                .messages
+                .get(Some(user), "confirm.membership_error", &[]);
+            actions::reply_ephemeral(&bot, chat_id, text, "send membership check error").await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn is_group_member(bot: &ThrottledBot, state: &BotState, user_id: UserId) -> bool {
+    let group_chat_id = state.group_chat_id;
+    match actions::get_chat_member(bot, group_chat_id, user_id, "get chat member").await {
+        Ok(member) => is_member(&member),
+        Err(e) => {
+            error!(
+                "Failed to check membership of {} in {}: {}",
+                user_id, group_chat_id, e
+            );
+            false
+        }
+    }
+}
+
+fn is_member(member: &teloxide::types::ChatMember) -> bool {
+    // Ограниченные (в том числе нами же) участники тоже остаются в группе
+    member.is_present()
+}
+
+async fn restrict_unverified(bot: &ThrottledBot, state: &BotState, user: &User, rule: &str) {
+    let group_chat_id = state.group_chat_id;
+    let user_id = user.id;
+    if !permissions::allows(group_chat_id, Right::Restrict) {
+        debug!("Cannot restrict user {}: bot lacks the right", user_id);
+        return;
+    }
+    info!(
+        "Restricting unverified user {} in group {}",
+        user_id, group_chat_id
+    );
+    match actions::restrict(
+        bot,
+        group_chat_id,
+        user_id,
+        ChatPermissions::empty(),
+        "restrict unverified user",
+    )
+    .await
+    {
+        Ok(_) => {
+            if rule == "raid_lockdown" {
+                state.raid.record_restricted(user_id).await;
+            }
+            record_action(
+                bot,
+                state,
+                ModRecord::new(ModAction::Mute, group_chat_id.0, user_target(user), rule),
+            )
+            .await;
+        }
+        Err(e) => error!("Failed to restrict user {}: {}", user_id, e),
+    }
+}
+
+fn user_target(user: &User) -> Target {
+    Target::User {
+        id: user.id.0,
+        name: user.full_name(),
+    }
+}
+
+fn chat_target(chat: &Chat) -> Target {
+    Target::Chat {
+        id: chat.id.0,
+        title: chat.title().unwrap_or("").to_owned(),
+    }
+}
+
+fn message_text(msg: &Message) -> Option<&str> {
+    msg.text().or_else(|| msg.caption())
+}
+
+// Сохраняет копию сообщения перед удалением, чтобы админы могли разобрать ошибки
+async fn preserve_evidence(
+    bot: &ThrottledBot,
+    state: &BotState,
+    msg: &Message,
+    rule: &str,
+) -> Option<Evidence> {
+    let settings = &state.evidence;
+    if settings.mode == evidence::EvidenceMode::Off {
+        return None;
+    }
+    let mut evidence = Evidence::default();
+
+    if settings.mode.uses_archive() {
+        match evidence::archive_message(&settings.dir, msg, rule) {
+            Ok(file) => evidence.archive_file = Some(file),
+            Err(e) => error!("Failed to archive message {}: {}", msg.id, e),
+        }
+    }
+
+    if let (true, Some(evidence_chat_id)) = (settings.mode.uses_chat(), settings.chat_id) {
+        let from_chat_id = msg.chat.id;
+        let message_id = msg.id;
+        match actions::forward(
+            bot,
+            evidence_chat_id,
+            from_chat_id,
+            message_id,
+            "forward evidence",
+        )
+        .await
+        {
+            Ok(copy) => {
+                evidence.chat_id = Some(evidence_chat_id.0);
+                evidence.message_id = Some(copy.id.0);
+                // Срок хранения копии отсчитывает очередь удалений: она переживает
+                // перезапуск и не зависит от того, осталась ли запись в памяти журнала
+                let due = chrono::Utc::now().timestamp() + settings.retention.as_secs() as i64;
+                delete_message_at(evidence_chat_id, copy.id, due);
+            }
+            Err(e) => error!(
+                "Failed to forward message {} as evidence: {}",
+                message_id, e
+            ),
+        }
+    }
+
+    Some(evidence)
+}
+
+// Копия сообщения, которое удалить не удалось: доказательство без действия
+// только сбивает с толку, поэтому оно убирается
+async fn discard_evidence(bot: &ThrottledBot, evidence: Option<Evidence>) {
+    let Some(evidence) = evidence else {
+        return;
+    };
+    if let Some(file) = &evidence.archive_file {
+        if let Err(e) = std::fs::remove_file(file) {
+            error!("Failed to remove orphaned evidence {}: {}", file, e);
+        }
+    }
+    if let (Some(chat_id), Some(message_id)) = (evidence.chat_id, evidence.message_id) {
+        if let Err(e) = actions::delete(
+            bot,
+            ChatId(chat_id),
+            MessageId(message_id),
+            "delete orphaned evidence",
+        )
+        .await
+        {
+            error!(
+                "Failed to delete orphaned evidence {} in chat {}: {}",
+                message_id, chat_id, e
+            );
+        }
+    }
+}
+
+// Чистит архив доказательств старше срока хранения. Копии в чате удаляет
+// очередь удалений, здесь из записей журнала убираются ссылки на них
+async fn run_evidence_pruner(state: Arc<BotState>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+    loop {
+        interval.tick().await;
+        let settings = &state.evidence;
+
+        if settings.mode.uses_archive() {
+            if let Err(e) = evidence::prune_archive(&settings.dir, settings.retention) {
+                error!("Failed to prune evidence archive: {}", e);
+            }
+        }
+
+        let cutoff = chrono::Utc::now().timestamp() - settings.retention.as_secs() as i64;
+        let now = chrono::Utc::now().timestamp();
+        for record in state.modlog.with_expired_evidence(cutoff).await {
+            // Копии, сохранённые до того, как их стала удалять очередь; повторное
+            // удаление уже удалённой копии deleteMessages просто пропускает
+            if let Some((chat_id, message_id)) = record
+                .evidence
+                .as_ref()
+                .and_then(|e| Some((e.chat_id?, e.message_id?)))
+            {
+                delete_message_at(ChatId(chat_id), MessageId(message_id), now);
+            }
+            if let Err(e) = state
+                .modlog
+                .update(record.id, |r| {
+                    if let Some(evidence) = r.evidence.as_mut() {
+                        evidence.message_id = None;
+                    }
+                })
+                .await
+            {
+                error!("Failed to update moderation record #{}: {}", record.id, e);
+            }
+        }
+    }
+}
+
+// Сохраняет запись о модерации и публикует её в чате администраторов
+async fn record_action(bot: &ThrottledBot, state: &BotState, record: ModRecord) {
+    let Some(record) = store_record(bot, state, record).await else {
+        return;
+    };
+    post_record(bot, state, record).await;
+}
+
+// Удаления у неподтверждённых идут в журнал сразу, а в чат администраторов —
+// сводкой от run_deletion_digest
+async fn record_unverified_deletion(bot: &ThrottledBot, state: &BotState, record: ModRecord) {
+    if let Some(record) = store_record(bot, state, record).await {
+        if state.admin_log_chat_id.is_some() {
+            state.deletion_digest.add(record).await;
+        }
+    }
+}
+
+async fn store_record(
+    bot: &ThrottledBot,
+    state: &BotState,
+    record: ModRecord,
+) -> Option<ModRecord> {
+    let record = match state.modlog.add(record).await {
+        Ok(record) => record,
+        Err(e) => {
+            error!("Failed to store moderation record: {}", e);
+            return None;
+        }
+    };
+    info!(
+        chat_id = record.chat_id,
+        target_id = global_ban_id(&record.target),
+        action = record.action.as_str();
+        "Moderation record #{}: {:?} {} ({})",
+        record.id,
+        record.action,
+        record.target.describe(),
+        record.rule
+    );
+    if record.action == ModAction::Ban {
+        spread_global_ban(bot, state, &record).await;
+    }
+    Some(record)
+}
+
+async fn post_record(bot: &ThrottledBot, state: &BotState, record: ModRecord) {
+    let Some(admin_chat_id) = state.admin_log_chat_id else {
+        return;
+    };
+    let text = record.format();
+    let action_name = "send moderation record";
+    let sent = match record.undo_keyboard() {
+        Some(keyboard) => {
+            actions::send_with_keyboard(bot, admin_chat_id, text, keyboard, action_name).await
+        }
+        None => actions::send(bot, admin_chat_id, text, action_name).await,
+    };
+    match sent {
+        Ok(sent) => {
+            if let Err(e) = state
+                .modlog
+                .update(record.id, |r| r.log_message_id = Some(sent.id.0))
+                .await
+            {
+                error!("Failed to update moderation record #{}: {}", record.id, e);
+            }
+        }
+        Err(e) => error!("Failed to send moderation record #{}: {}", record.id, e),
+    }
+}
+
+fn global_ban_id(target: &Target) -> i64 {
+    match target {
+        Target::User { id, .. } => *id as i64,
+        Target::Chat { id, .. } => *id,
+    }
+}
+
+// Бан в одной группе попадает в общий список и применяется в остальных группах
+async fn spread_global_ban(bot: &ThrottledBot, state: &BotState, record: &ModRecord) {
+    let Some(bans) = &state.global_bans else {
+        return;
+    };
+    let id = global_ban_id(&record.target);
+    let reason = format!("{} in {}", record.rule, record.chat_id);
+    match bans.add(id, &reason).await {
+        Ok(true) => {}
+        Ok(false) => return,
+        Err(e) => {
+            error!("Failed to update global ban list: {}", e);
+            return;
+        }
+    }
+    for &chat_id in bans.chats().iter().filter(|c| c.0 != record.chat_id) {
+        match actions::ban_in_chat(bot, chat_id, id, "apply global ban").await {
+            Ok(()) => info!("Applied global ban of {} in group {}", id, chat_id),
+            Err(e) => error!("Failed to apply global ban of {} in {}: {}", id, chat_id, e),
+        }
+    }
+}
+
+// Разбан администратором снимает бан и в остальных группах
+async fn lift_global_ban(bot: &ThrottledBot, state: &BotState, record: &ModRecord) {
+    let Some(bans) = &state.global_bans else {
+        return;
+    };
+    let id = global_ban_id(&record.target);
+    match bans.remove(id).await {
+        Ok(true) => {}
+        Ok(false) => return,
+        Err(e) => {
+            error!("Failed to update global ban list: {}", e);
+            return;
+        }
+    }
+    for &chat_id in bans.chats().iter().filter(|c| c.0 != record.chat_id) {
+        match actions::unban_in_chat(bot, chat_id, id, "lift global ban").await {
+            Ok(()) => info!("Lifted global ban of {} in group {}", id, chat_id),
+            Err(e) => error!("Failed to lift global ban of {} in {}: {}", id, chat_id, e),
+        }
+    }
+}
+
+// Забаненный в другой группе банится здесь при первом появлении: вступлении или сообщении
+async fn enforce_global_ban(bot: &ThrottledBot, state: &BotState, msg: &Message) -> bool {
+    let Some(bans) = &state.global_bans else {
+        return false;
+    };
+    if msg.chat.id != state.group_chat_id {
+        return false;
+    }
+
+    let mut targets = Vec::new();
+    match (&msg.sender_chat, &msg.from) {
+        (Some(chat), _) => targets.push(chat_target(chat)),
+        (None, Some(user)) => targets.push(user_target(user)),
+        (None, None) => {}
+    }
+    if let Some(members) = msg.new_chat_members() {
+        targets.extend(members.iter().map(user_target));
+    }
+    let mut seen = HashSet::new();
+    targets.retain(|t| seen.insert(global_ban_id(t)));
+
+    let mut banned = false;
+    for target in targets {
+        let id = global_ban_id(&target);
+        if !bans.contains(id).await {
+            continue;
+        }
+        banned = true;
+        warn!(
+            "{} is on the global ban list, banning in group {}",
+            target.describe(),
+            msg.chat.id
+        );
+        match actions::ban_in_chat(bot, msg.chat.id, id, "apply global ban").await {
+            Ok(()) => {
+                record_action(
+                    bot,
+                    state,
+                    ModRecord::new(ModAction::Ban, msg.chat.id.0, target, "global_ban"),
+                )
+                .await
+            }
+            Err(e) => error!("Failed to apply global ban of {}: {}", id, e),
+        }
+    }
+
+    if banned {
+        let chat_id = msg.chat.id;
+        let message_id = msg.id;
+        match actions::delete(bot, chat_id, message_id, "delete globally banned message").await {
+            Ok(()) => metrics::deletion("global_ban"),
+            Err(e) => error!(
+                "Failed to delete message from globally banned sender: {}",
+                e
+            ),
+        }
+    }
+    banned
+}
+
+// Возвращает пользователю права группы по умолчанию
+async fn lift_restrictions(bot: &ThrottledBot, state: &BotState, user_id: UserId) {
+    let group_chat_id = state.group_chat_id;
+    let permissions = match actions::get_chat(bot, group_chat_id, "get group permissions").await {
+        Ok(chat) => chat.permissions().unwrap_or_else(ChatPermissions::all),
+        Err(e) => {
+            error!(
+                "Failed to get default permissions of {}: {}",
+                group_chat_id, e
+            );
+            ChatPermissions::all()
+        }
+    };
+
+    info!(
+        "Lifting restrictions from user {} in group {}",
+        user_id, group_chat_id
+    );
+    if let Err(e) = actions::unrestrict(
+        bot,
+        group_chat_id,
+        user_id,
+        permissions,
+        "lift user restrictions",
+    )
+    .await
+    {
+        error!("Failed to lift restrictions from user {}: {}", user_id, e);
+    }
+}
+
+async fn handle_sender_chat_message(
+    bot: ThrottledBot,
+    msg: Message,
+    sender_chat: Chat,
+    state: Arc<BotState>,
+) -> Result<()> {
+    let chat_id = msg.chat.id;
+    let message_id = msg.id;
+    let sender_chat_id = sender_chat.id;
+    let sender_title = sender_chat.title().unwrap_or("").to_owned();
+
+    info!(
+        chat_id = chat_id.0,
+        sender_chat_id = sender_chat_id.0;
+        "Processing message on behalf of chat {} ({}) in chat {}: {}",
+        sender_chat_id,
+        sender_title,
+        chat_id,
+        logging::redact(message_text(&msg))
+    );
+
+    // Анонимные администраторы пишут от имени самой группы
+    if sender_chat_id == chat_id {
+        debug!("Message from anonymous admin in chat {}, skipping", chat_id);
+        return Ok(());
+    }
+
+    // Автоматические пересылки из привязанного канала обсуждений
+    if msg.is_automatic_forward() || state.sender_chats.linked_chat_id == Some(sender_chat_id) {
+        debug!(
+            "Message from linked channel {} in chat {}, skipping",
+            sender_chat_id, chat_id
+        );
+        return Ok(());
+    }
+
+    if !state.is_chat_whitelisted(sender_chat_id).await {
+        warn!(
+            "Sender chat {} is not whitelisted, deleting message",
+            sender_chat_id
+        );
+        let evidence = preserve_evidence(&bot, &state, &msg, "sender_chat").await;
+        if let Err(e) = actions::delete(
+            &bot,
+            chat_id,
+            message_id,
+            "delete unwhitelisted sender chat message",
+        )
+        .await
+        {
+            error!(
+                "Failed to delete message from unwhitelisted sender chat {}: {}",
+                sender_chat_id, e
+            );
+            discard_evidence(&bot, evidence).await;
+        } else {
+            metrics::deletion("sender_chat");
+            record_action(
+                &bot,
+                &state,
+                ModRecord::new(
+                    ModAction::Delete,
+                    chat_id.0,
+                    chat_target(&sender_chat),
+                    "sender_chat",
+                )
+                .with_snippet(message_text(&msg))
+                .with_evidence(evidence),
+            )
+            .await;
+        }
+
+        if state.sender_chats.ban_unknown && permissions::allows(chat_id, Right::Ban) {
+            warn!("Banning sender chat {} in chat {}", sender_chat_id, chat_id);
+            if let Err(e) =
+                actions::ban_sender_chat(&bot, chat_id, sender_chat_id, "ban sender chat").await
+            {
+                error!("Failed to ban sender chat {}: {}", sender_chat_id, e);
+            } else {
+                record_action(
+                    &bot,
+                    &state,
+                    ModRecord::new(
+                        ModAction::Ban,
+                        chat_id.0,
+                        chat_target(&sender_chat),
+                        "sender_chat",
+                    ),
+                )
+                .await;
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(text) = message_text(&msg) {
+        if let Some(pattern) = state.check_message(text).await {
+            warn!(
+                "Message from sender chat {} contains forbidden pattern, deleting",
+                sender_chat_id
+            );
+            let evidence = preserve_evidence(&bot, &state, &msg, &pattern).await;
+            if let Err(e) = actions::delete(
+                &bot,
+                chat_id,
+                message_id,
+                "delete forbidden sender chat message",
+            )
+            .await
+            {
+                error!(
+                    "Failed to delete forbidden message from sender chat {}: {}",
+                    sender_chat_id, e
+                );
+                discard_evidence(&bot, evidence).await;
+            } else {
+                metrics::deletion("forbidden_pattern");
+                record_action(
+                    &bot,
+                    &state,
+                    ModRecord::new(
+                        ModAction::Delete,
+                        chat_id.0,
+                        chat_target(&sender_chat),
+                        &pattern,
+                    )
+                    .with_snippet(Some(text))
+                    .with_evidence(evidence),
+                )
+                .await;
+            }
+
+            let text = state
+                .messages
+                .get(None, "warning.forbidden", &[("name", &sender_title)]);
+            skip_if_saturated(
+                actions::prompt_ephemeral(&bot, chat_id, text, "send forbidden pattern warning")
+                    .await,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+async fn handle_group_message(bot: ThrottledBot, msg: Message, state: Arc<BotState>) -> Result<()> {
+    // Служебные чаты (журнал, доказательства) не модерируются
+    if msg.chat.id != state.group_chat_id && !msg.chat.is_private() {
+        debug!("Ignoring message in chat {}", msg.chat.id);
+        return Ok(());
+    }
+
+    if enforce_global_ban(&bot, &state, &msg).await {
+        return Ok(());
+    }
+
+    // Сообщения от имени каналов и анонимных админов: msg.from здесь служебный бот
+    if let Some(sender_chat) = msg.sender_chat.clone() {
+        return handle_sender_chat_message(bot, msg, sender_chat, state).await;
+    }
+
+    if let Some(user) = msg.from.clone() {
+        info!(
+            chat_id = msg.chat.id.0,
+            user_id = user.id.0;
+            "Processing message from user {} ({} @{}) in chat {}: {}",
+            user.id,
+            user.full_name(),
+            user.username.as_deref().unwrap_or(""),
+            msg.chat.id,
+            logging::redact(msg.text())
+        );
+
+        // Новые участники попадают в список ожидающих подтверждения
+        if let Some(members) = msg.new_chat_members() {
+            if msg.chat.id == state.group_chat_id {
+                for member in members.iter().filter(|m| !m.is_bot) {
+                    if let Some(trigger) = state.raid.record_join().await {
+                        start_lockdown(&bot, &state, trigger).await;
+                    }
+                    if !state.is_whitelisted(member.id).await {
+                        track_pending(&state, member.id).await;
+                        // Во время рейда новички сразу получают режим только чтения
+                        if state.raid.is_active().await {
+                            restrict_unverified(&bot, &state, member, "raid_lockdown").await;
+                        }
+                    }
+                }
+            }
+        }
+
+        // Сначала проверяем команды
+        if let Some(text) = msg.text() {
+            if let Some(cmd) = Command::parse(text) {
+                info!("Detected command {:?} from user {}", cmd, user.id);
+                return handle_command(bot.clone(), msg.clone(), state.clone(), cmd).await;
+            }
+        }
+
+        // Текст апелляции после /appeal в личке
+        if msg.chat.is_private() {
+            if let Some(text) = message_text(&msg) {
+                if let Some(appeal) = state.appeals.take_awaiting(user.id).await {
+                    return submit_appeal(&bot, &state, appeal, &user, text).await;
+                }
+            }
+        }
+
+        let chat_id = msg.chat.id;
+        let message_id = msg.id;
+        let user_first_name = user.first_name.clone();
+
+        // Проверяем секретный код перед другими проверками
+        if let Some(text) = msg.text() {
+            if state.check_secret_code(text).await {
+                info!("User {} entered correct secret code", user.id);
+                let username = user
+                    .username
+                    .as_deref()
+                    .unwrap_or(&user.first_name)
+                    .to_owned();
+
+                if let Err(e) = state.add_to_whitelist(user.id, &username).await {
+                    error!("Failed to add to whitelist: {}", e);
+                } else {
+                    info!("User {} added to whitelist via secret code", user.id);
+                    metrics::verification("secret_code");
+                    record_action(
+                        &bot,
+                        &state,
+                        ModRecord::new(
+                            ModAction::WhitelistAdd,
+                            state.group_chat_id.0,
+                            user_target(&user),
+                            "secret_code",
+                        ),
+                    )
+                    .await;
+                    state.clear_prompt(user.id).await;
+                    if state.restrict_mode() {
+                        lift_restrictions(&bot, &state, user.id).await;
+                    }
+                }
+
+                // Удаляем сообщение с кодом
+                if let Err(e) =
+                    actions::delete(&bot, chat_id, message_id, "delete secret code message").await
+                {
+                    error!("Failed to delete secret code message: {}", e);
+                }
+
+                return Ok(());
+            }
+        }
+
+        // Для неподтверждённых пользователей
+        if !state.is_whitelisted(user.id).await {
+            warn!("User {} is not whitelisted, deleting message", user.id);
+            let mut lockdown = false;
+            if chat_id == state.group_chat_id {
+                track_pending(&state, user.id).await;
+                if let Some(trigger) = state.raid.record_unverified_message().await {
+                    start_lockdown(&bot, &state, trigger).await;
+                }
+                lockdown = state.raid.is_active().await;
+            }
+            let evidence = if msg.chat.is_private() {
+                None
+            } else {
+                preserve_evidence(&bot, &state, &msg, "unverified").await
+            };
+            if let Err(e) =
+                actions::delete(&bot, chat_id, message_id, "delete unwhitelisted message").await
+            {
+                error!(
+                    "Failed to delete message from unwhitelisted user {}: {}",
+                    user.id, e
+                );
+                discard_evidence(&bot, evidence).await;
+            } else if !msg.chat.is_private() {
+                metrics::deletion("unverified");
+                record_unverified_deletion(
+                    &bot,
+                    &state,
+                    ModRecord::new(
+                        ModAction::Delete,
+                        chat_id.0,
+                        user_target(&user),
+                        "unverified",
+                    )
+                    .with_snippet(message_text(&msg))
+                    .with_evidence(evidence),
+                )
+                .await;
+            }
+
+            if (state.restrict_mode() || lockdown) && !msg.chat.is_private() {
+                let rule = if lockdown {
+                    "raid_lockdown"
+                } else {
+                    "unverified"
+                };
+                restrict_unverified(&bot, &state, &user, rule).await;
+            }
+
+            // Во время рейда запросы не отправляем, чтобы бот сам не флудил
+            if lockdown {
+                debug!("Lockdown is active, skipping prompt for user {}", user.id);
+                return Ok(());
+            }
+
+            // Запрос на подтверждение — на сообщения с текстом или медиа, но не на служебные
+            let has_content =
+                message_text(&msg).is_some() || !evidence::media_file_ids(&msg).is_empty();
+            if has_content && state.should_prompt(user.id).await {
+                info!(
+                    "Sending confirmation request to user {} in chat {}",
+                    user.id, chat_id
+                );
+                let restrict_mode = state.restrict_mode();
+                let bot_username = state.bot_username.clone();
+                let action_name = "send confirmation request";
+                let response = if restrict_mode {
+                    let text = state.messages.get(
+                        Some(&user),
+                        "prompt.confirm_bot",
+                        &[("name", &user_first_name), ("bot", &bot_username)],
+                    );
+                    let button = state
+                        .messages
+                        .get(Some(&user), "prompt.confirm_button", &[]);
+                    // Ограниченный пользователь не может писать в группу,
+                    // поэтому подтверждение проходит в личке с ботом
+                    let url = format!("https://t.me/{}?start=confirm", bot_username)
+                        .parse()
+                        .expect("valid deep link url");
+                    let keyboard =
+                        InlineKeyboardMarkup::new([[InlineKeyboardButton::url(button, url)]]);
+                    actions::prompt_ephemeral_with_keyboard(
+                        &bot,
+                        chat_id,
+                        text,
+                        keyboard,
+                        action_name,
+                    )
+                    .await
+                } else {
+                    let text = state.messages.get(
+                        Some(&user),
+                        "prompt.confirm",
+                        &[("name", &user_first_name)],
+                    );
+                    actions::prompt_ephemeral(&bot, chat_id, text, action_name).await
+                };
+                let response = skip_if_saturated(response);
+                if !matches!(response, Ok(Some(_))) {
+                    // Неотправленный запрос не должен блокировать следующий
+                    state.clear_prompt(user.id).await;
+                }
+                response?;
+            }
+            return Ok(());
+        }
+
+        // Для подтверждённых пользователей проверяем запрещённые паттерны
+        if let Some(text) = msg.text() {
+            if let Some(pattern) = state.check_message(text).await {
+                warn!(
+                    "Message from user {} contains forbidden pattern, deleting",
+                    user.id
+                );
+                let evidence = preserve_evidence(&bot, &state, &msg, &pattern).await;
+                if let Err(e) =
+                    actions::delete(&bot, chat_id, message_id, "delete forbidden message").await
+                {
+                    error!(
+                        "Failed to delete forbidden message from user {}: {}",
+                        user.id, e
+                    );
+                    discard_evidence(&bot, evidence).await;
+                } else {
+                    metrics::deletion("forbidden_pattern");
+                    record_action(
+                        &bot,
+                        &state,
+                        ModRecord::new(ModAction::Delete, chat_id.0, user_target(&user), &pattern)
+                            .with_snippet(Some(text))
+                            .with_evidence(evidence),
+                    )
+                    .await;
+                }
+
+                let text = state.messages.get(
+                    Some(&user),
+                    "warning.forbidden",
+                    &[("name", &user_first_name)],
+                );
+                skip_if_saturated(
+                    actions::prompt_ephemeral(
+                        &bot,
+                        chat_id,
+                        text,
+                        "send forbidden pattern warning",
+                    )
+                    .await,
+                )?;
+                return Ok(());
+            }
+        }
+
+        // Ограничения в зависимости от уровня доверия
+        let level = state
+            .whitelist_entry(user.id)
+            .await
+            .map(|entry| entry.level)
+            .unwrap_or(TrustLevel::New);
+        let mut violation = None;
+        if state.features.trust_levels {
+            violation = trust::content_violation(level, &msg);
+            if violation.is_none() {
+                if let Some(limit) = state.trust.flood_limit(level) {
+                    if state.check_flood(user.id, limit).await {
+                        violation = Some(Violation::Flood);
+                    }
+                }
+            }
+        }
+
+        if let Some(violation) = violation {
+            warn!(
+                "Message from user {} (level {}) violates level restrictions: {:?}",
+                user.id, level, violation
+            );
+            let evidence = preserve_evidence(&bot, &state, &msg, violation.rule()).await;
+            if let Err(e) =
+                actions::delete(&bot, chat_id, message_id, "delete restricted message").await
+            {
+                error!(
+                    "Failed to delete restricted message from user {}: {}",
+                    user.id, e
+                );
+                discard_evidence(&bot, evidence).await;
+            } else {
+                metrics::deletion("trust_level");
+                record_action(
+                    &bot,
+                    &state,
+                    ModRecord::new(
+                        ModAction::Delete,
+                        chat_id.0,
+                        user_target(&user),
+                        violation.rule(),
+                    )
+                    .with_snippet(message_text(&msg))
+                    .with_evidence(evidence),
+                )
+                .await;
+            }
+
+            let violation_text = state
+                .messages
+                .get(Some(&user), violation.message_key(), &[]);
+            let text = state.messages.get(
+                Some(&user),
+                "warning.trust_level",
+                &[("name", &user_first_name), ("violation", &violation_text)],
+            );
+            skip_if_saturated(
+                actions::prompt_ephemeral(&bot, chat_id, text, "send level restriction warning")
+                    .await,
+            )?;
+            return Ok(());
+        }
+
+        match state.record_clean_message(user.id).await {
+            Ok(Some(level)) => info!("User {} promoted to {}", user.id, level),
+            Ok(None) => {}
+            Err(e) => error!("Failed to update trust level of user {}: {}", user.id, e),
+        }
+    }
+    Ok(())
+}
+
+async fn track_pending(state: &BotState, user_id: UserId) {
+    if let Err(e) = state
+        .pending
+        .track(user_id, chrono::Utc::now().timestamp())
+        .await
+    {
+        error!("Failed to track pending user {}: {}", user_id, e);
+    }
+}
+
+// Счётчики чистых сообщений меняются с каждым сообщением новичка, поэтому
+// файл белого списка перезаписывается не чаще раза в WHITELIST_FLUSH_INTERVAL
+async fn run_whitelist_flusher(state: Arc<BotState>) {
+    let mut interval = tokio::time::interval(WHITELIST_FLUSH_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(e) = state.flush_whitelist().await {
+            error!("Failed to save whitelist: {}", e);
+        }
+    }
+}
+
+// Периодически выгоняет тех, кто так и не подтвердился
+async fn run_kick_sweeper(bot: ThrottledBot, state: Arc<BotState>) {
+    let Some(kick_after) = state.kick_after else {
+        return;
+    };
+    info!(
+        "Starting kick sweeper: deadline {:?}, interval {:?}",
+        kick_after, state.kick_check_interval
+    );
+
+    let mut interval = tokio::time::interval(state.kick_check_interval);
+    loop {
+        interval.tick().await;
+        sweep_unverified(&bot, &state, kick_after).await;
+    }
+}
+
+async fn sweep_unverified(bot: &ThrottledBot, state: &BotState, kick_after: Duration) {
+    let now = chrono::Utc::now().timestamp();
+    let overdue = state
+        .pending
+        .overdue(now, kick_after.as_secs() as i64)
+        .await;
+    if overdue.is_empty() {
+        debug!("Kick sweeper: no overdue users");
+        return;
+    }
+
+    let group_chat_id = state.group_chat_id;
+    // Без права банить пользователи остаются в списке до возвращения прав
+    if !permissions::allows(group_chat_id, Right::Ban) {
+        warn!(
+            "Kick sweeper: bot cannot ban in group {}, skipping {} overdue user(s)",
+            group_chat_id,
+            overdue.len()
+        );
+        return;
+    }
+    let mut done = Vec::new();
+    let mut kicked = 0;
+    let mut stuck = 0;
+    let mut failed = 0;
+
+    for user_id in overdue {
+        if state.is_whitelisted(user_id).await {
+            done.push(user_id);
+            continue;
+        }
+
+        // Кто вышел сам, тот уже не в группе: выгонять некого
+        match actions::get_chat_member(bot, group_chat_id, user_id, "get chat member").await {
+            Ok(member) if !is_member(&member) => {
+                debug!("Unverified user {} already left the group", user_id);
+                done.push(user_id);
+                continue;
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Failed to check membership of {}: {}", user_id, e),
+        }
+
+        info!(
+            "Kicking unverified user {} from group {}",
+            user_id, group_chat_id
+        );
+        // Кик — это бан с немедленным разбаном, чтобы пользователь мог вернуться.
+        // Бан отправляется один раз: повторяется только разбан
+        if let Err(e) = actions::ban(bot, group_chat_id, user_id, "kick unverified user").await {
+            failed += 1;
+            error!("Failed to kick unverified user {}: {}", user_id, e);
+            continue;
+        }
+        done.push(user_id);
+
+        let unbanned =
+            actions::unban_kicked(bot, group_chat_id, user_id, "unban kicked user").await;
+        let rule = match unbanned {
+            Ok(_) => {
+                kicked += 1;
+                "kick_deadline"
+            }
+            Err(e) => {
+                // Запись с кнопкой разбана, чтобы администратор мог снять бан вручную
+                stuck += 1;
+                error!(
+                    "Kicked user {} stays banned in group {}: failed to unban: {}",
+                    user_id, group_chat_id, e
+                );
+                "kick_deadline_unban_failed"
+            }
+        };
+        record_action(
+            bot,
+            state,
+            ModRecord::new(
+                ModAction::Kick,
+                group_chat_id.0,
+                Target::User {
+                    id: user_id.0,
+                    name: String::new(),
+                },
+                rule,
+            ),
+        )
+        .await;
+    }
+
+    if let Err(e) = state.pending.remove_many(&done).await {
+        error!("Failed to update pending users: {}", e);
+    }
+
+    info!(
+        "Kick sweeper: kicked {}, left banned {}, failed {}",
+        kicked, stuck, failed
+    );
+    if kicked + stuck + failed > 0 {
+        let mut text = format!(
+            "🧹 Удаление неподтверждённых: выгнано {}, ошибок {}",
+            kicked, failed
+        );
+        if stuck > 0 {
+            text.push_str(&format!(
+                "\n⚠️ Не удалось разбанить после выгона: {}, они остаются забаненными",
+                stuck
+            ));
+        }
+        send_admin_log(bot, state, text).await;
+    }
+}
+
+async fn start_lockdown(bot: &ThrottledBot, state: &BotState, trigger: RaidTrigger) {
+    let group_chat_id = state.group_chat_id;
+    warn!(
+        "Starting lockdown in group {}: {}",
+        group_chat_id,
+        trigger.description()
+    );
+
+    let lock_permissions = state.raid.settings.lock_group_permissions
+        && permissions::allows(group_chat_id, Right::Restrict);
+    if state.raid.settings.lock_group_permissions && !lock_permissions {
+        warn!(
+            "Not locking group {}: bot lacks can_restrict_members",
+            group_chat_id
+        );
+    }
+    if lock_permissions {
+        match actions::get_chat(bot, group_chat_id, "get group permissions").await {
+            Ok(chat) => {
+                if let Some(permissions) = chat.permissions() {
+                    state.raid.save_permissions(permissions).await;
+                }
+                if let Err(e) =
+                    actions::lock_chat(bot, group_chat_id, "lock group permissions").await
+                {
+                    error!("Failed to lock group permissions: {}", e);
+                }
+            }
+            Err(e) => error!("Failed to get group permissions before lockdown: {}", e),
+        }
+    }
+
+    let until = match trigger {
+        RaidTrigger::Manual => "до снятия командой /lockdown off".to_string(),
+        _ => format!(
+            "на {} мин. или до /lockdown off",
+            state.raid.settings.cooldown.as_secs() / 60
+        ),
+    };
+    send_admin_log(
+        bot,
+        state,
+        format!(
+            "🚨 Блокировка группы: {}\nНовые участники ограничены, запросы на подтверждение не отправляются {}",
+            trigger.description(),
+            until
+        ),
+    )
+    .await;
+}
+
+async fn end_lockdown(bot: &ThrottledBot, state: &BotState, reason: &str) -> bool {
+    if !state.raid.end().await {
+        return false;
+    }
+    let group_chat_id = state.group_chat_id;
+    info!("Ending lockdown in group {}: {}", group_chat_id, reason);
+
+    if let Some(permissions) = state.raid.take_saved_permissions().await {
+        if let Err(e) = actions::set_chat_permissions(
+            bot,
+            group_chat_id,
+            permissions,
+            "restore group permissions",
+        )
+        .await
+        {
+            error!("Failed to restore group permissions: {}", e);
+        }
+    }
+
+    // Ограниченные блокировкой не получили запроса на подтверждение и сами писать
+    // не могут: снимаем ограничения, и их следующее сообщение пройдёт обычную проверку
+    let restricted = state.raid.take_restricted().await;
+    for &user_id in &restricted {
+        lift_restrictions(bot, state, user_id).await;
+    }
+    let mut text = format!("✅ Блокировка снята: {}", reason);
+    if !restricted.is_empty() {
+        info!(
+            "Lifted lockdown restrictions from {} user(s) in group {}",
+            restricted.len(),
+            group_chat_id
+        );
+        text.push_str(&format!(
+            "\nСняты ограничения с участников, ограниченных во время блокировки: {}",
+            restricted.len()
+        ));
+    }
+    send_admin_log(bot, state, text).await;
+    true
+}
+
+async fn run_deletion_digest(bot: ThrottledBot, state: Arc<BotState>) {
+    let mut interval = tokio::time::interval(DELETION_DIGEST_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Some(text) = state.deletion_digest.take_summary().await {
+            send_admin_log(&bot, &state, text).await;
+        }
+    }
+}
+
+// Снимает автоматическую блокировку по истечении времени
+async fn run_raid_monitor(bot: ThrottledBot, state: Arc<BotState>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(10));
+    loop {
+        interval.tick().await;
+        if state.raid.is_expired().await {
+            end_lockdown(&bot, &state, "рейд закончился").await;
+        }
+    }
+}
+
+// Права бота в группе: при запуске и затем периодически. Нехватку прав бот
+// сообщает в лог и чат администраторов, а функции без нужных прав отключает.
+async fn run_rights_check(bot: ThrottledBot, state: Arc<BotState>, me: UserId, every: Duration) {
+    let mut interval = tokio::time::interval(every);
+    loop {
+        interval.tick().await;
+        check_rights(&bot, &state, me).await;
+    }
+}
+
+async fn check_rights(bot: &ThrottledBot, state: &BotState, me: UserId) {
+    let group_chat_id = state.group_chat_id;
+    let member = match actions::get_chat_member(bot, group_chat_id, me, "check bot rights").await {
+        Ok(member) => member,
+        Err(e) => {
+            // Прошлый результат остаётся в силе до следующей проверки
+            error!(
+                "Failed to check bot rights in group {}: {}",
+                group_chat_id, e
+            );
+            return;
+        }
+    };
+
+    let missing = permissions::missing_in(&member);
+    let previous = permissions::record(group_chat_id, missing.clone());
+    if previous.as_ref() == Some(&missing) {
+        return;
+    }
+    if missing.is_empty() {
+        info!("Bot has all required rights in group {}", group_chat_id);
+        if previous.is_some() {
+            send_admin_log(
+                bot,
+                state,
+                "✅ Права бота в группе восстановлены, все функции снова работают".to_string(),
+            )
+            .await;
+        }
+        return;
+    }
+    error!(
+        "Bot lacks rights in group {}: {}; dependent features are disabled",
+        group_chat_id,
+        permissions::api_names(&missing)
+    );
+    send_admin_log(bot, state, permissions::describe_missing(&missing)).await;
+}
+
+async fn send_admin_log(bot: &ThrottledBot, state: &BotState, text: String) {
+    let Some(admin_chat_id) = state.admin_log_chat_id else {
+        return;
+    };
+    if let Err(e) = actions::send(bot, admin_chat_id, text, "send admin log").await {
+        error!("Failed to send admin log message: {}", e);
+    }
+}
+
+async fn handle_callback(bot: ThrottledBot, q: CallbackQuery, state: Arc<BotState>) -> Result<()> {
+    let Some(action) = q.data.as_deref().and_then(CallbackAction::parse) else {
+        warn!(
+            "Unknown callback data from user {}: {:?}",
+            q.from.id, q.data
+        );
+        return Ok(());
+    };
+    info!("Received callback {:?} from user {}", action, q.from.id);
+
+    let answer = if !is_group_admin(&bot, &state, q.from.id).await {
+        warn!("User {} is not an admin, ignoring callback", q.from.id);
+        "⛔ Только для администраторов".to_string()
+    } else {
+        match action {
+            CallbackAction::Undo(request) => {
+                resolve_record(&bot, &state, request.record_id, request.kind, &q.from)
+                    .await
+                    .unwrap_or_else(|answer| answer)
+            }
+            CallbackAction::AddException(record_id) => {
+                add_exception_from_record(&bot, &state, &q, record_id).await
+            }
+            CallbackAction::Report(callback) => {
+                resolve_report(&bot, &state, callback, &q.from).await
+            }
+            CallbackAction::Appeal(callback) => resolve_appeal(&bot, &state, &q, callback).await,
+        }
+    };
+
+    if let Err(e) =
+        actions::answer_callback(&bot, q.id.clone(), answer, "answer callback query").await
+    {
+        error!("Failed to answer callback query: {}", e);
+    }
+    Ok(())
+}
+
+// Отменяет действие по записи журнала. Ответ администратору возвращается в обоих
+// случаях: `Ok` — действие отменено, `Err` — запись не изменилась
+async fn resolve_record(
+    bot: &ThrottledBot,
+    state: &BotState,
+    record_id: u64,
+    kind: UndoKind,
+    admin: &User,
+) -> std::result::Result<String, String> {
+    let record = match state.modlog.get(record_id).await {
+        None => return Err("❌ Запись не найдена".to_string()),
+        Some(record) if record.status != RecordStatus::Active => {
+            return Err("ℹ️ Действие уже отменено".to_string())
+        }
+        Some(record) => record,
+    };
+
+    match undo_action(bot, state, &record, kind, admin).await {
+        Ok(answer) => {
+            let status = match kind {
+                UndoKind::FalsePositive => RecordStatus::FalsePositive,
+                _ => RecordStatus::Undone,
+            };
+            let admin_name = admin.full_name();
+            match state
+                .modlog
+                .update(record.id, |r| {
+                    r.status = status;
+                    r.resolved_by = Some(admin_name);
+                })
+                .await
+            {
+                Ok(Some(updated)) => refresh_record_message(bot, state, &updated).await,
+                Ok(None) => {}
+                Err(e) => error!("Failed to update moderation record #{}: {}", record.id, e),
+            }
+            Ok(answer)
+        }
+        Err(e) => {
+            error!("Failed to undo moderation record #{}: {}", record.id, e);
+            Err("⚠️ Ошибка. Попробуйте позже".to_string())
+        }
+    }
+}
+
+// Возвращает удалённое сообщение в группу от имени бота с указанием автора
+async fn repost_deleted(bot: &ThrottledBot, state: &BotState, record: &ModRecord) -> Result<()> {
+    let chat_id = ChatId(record.chat_id);
+    let author = record.target.name().to_owned();
+    let evidence = record.evidence.clone().unwrap_or_default();
+
+    if let (Some(evidence_chat_id), Some(evidence_message_id)) =
+        (evidence.chat_id, evidence.message_id)
+    {
+        let text = state
+            .messages
+            .get(None, "repost.header", &[("name", &author)]);
+        let header = actions::send(bot, chat_id, text, "send repost header").await?;
+        actions::copy_as_reply(
+            bot,
+            chat_id,
+            header.id,
+            ChatId(evidence_chat_id),
+            MessageId(evidence_message_id),
+            "repost deleted message",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let text = evidence
+        .archive_file
+        .as_deref()
+        .and_then(evidence::archived_text)
+        .or_else(|| record.snippet.clone());
+    let Some(text) = text else {
+        warn!("Record #{} has no content to repost", record.id);
+        return Ok(());
+    };
+
+    let text = state
+        .messages
+        .get(None, "repost.text", &[("name", &author), ("text", &text)]);
+    actions::send(bot, chat_id, text, "repost deleted message").await?;
+    Ok(())
+}
+
+async fn mark_false_positive(
+    bot: &ThrottledBot,
+    state: &BotState,
+    record: &ModRecord,
+) -> Result<String> {
+    if record.action == ModAction::Delete {
+        repost_deleted(bot, state, record).await?;
+    }
+
+    let is_pattern = state.forbidden_patterns.lock().await.has_rule(&record.rule);
+    if !is_pattern {
+        return Ok("🟡 Отмечено как ложное срабатывание".to_string());
+    }
+
+    let count = state
+        .pattern_stats
+        .record_false_positive(&record.rule)
+        .await?;
+    info!("Pattern '{}' has {} false positives", record.rule, count);
+
+    // Предлагаем исключение для текста, на котором ошибся паттерн
+    if let Some(admin_chat_id) = state.admin_log_chat_id {
+        let text = format!(
+            "🟡 Паттерн «{}» ложно сработал уже {} раз(а).\nДобавить исключение для этого текста?",
+            record.rule, count
+        );
+        let keyboard = modlog::exception_keyboard(record.id);
+        let action_name = "send exception offer";
+        let sent = match record.log_message_id.map(MessageId) {
+            Some(reply_to) => {
+                actions::reply_with_keyboard(
+                    bot,
+                    admin_chat_id,
+                    reply_to,
+                    text,
+                    keyboard,
+                    action_name,
+                )
+                .await
+            }
+            None => {
+                actions::send_with_keyboard(bot, admin_chat_id, text, keyboard, action_name).await
+            }
+        };
+        if let Err(e) = sent {
+            error!("Failed to offer exception for record #{}: {}", record.id, e);
+        }
+    }
+    Ok(format!(
+        "🟡 Ложное срабатывание паттерна «{}» ({})",
+        record.rule, count
+    ))
+}
+
+async fn add_exception_from_record(
+    bot: &ThrottledBot,
+    state: &BotState,
+    q: &CallbackQuery,
+    record_id: u64,
+) -> String {
+    let Some(record) = state.modlog.get(record_id).await else {
+        return "❌ Запись не найдена".to_string();
+    };
+    let text = record
+        .evidence
+        .as_ref()
+        .and_then(|e| e.archive_file.as_deref())
+        .and_then(evidence::archived_text)
+        .or_else(|| record.snippet.clone());
+    let Some(text) = text else {
+        return "❌ Нет текста для исключения".to_string();
+    };
+
+    if let Err(e) = state.add_pattern_exception(&text).await {
+        error!("Failed to add pattern exception: {}", e);
+        return "⚠️ Ошибка. Попробуйте позже".to_string();
+    }
+    info!(
+        "User {} added exception for pattern '{}' from record #{}",
+        q.from.id, record.rule, record.id
+    );
+
+    if let Some(message) = q.message.as_ref() {
+        let chat_id = message.chat().id;
+        let message_id = message.id();
+        let text = format!(
+            "➕ Исключение для паттерна «{}» добавлено ({})",
+            record.rule,
+            q.from.full_name()
+        );
+        if let Err(e) =
+            actions::edit_text(bot, chat_id, message_id, text, "edit exception offer").await
+        {
+            error!("Failed to edit exception offer: {}", e);
+        }
+    }
+    "➕ Исключение добавлено".to_string()
+}
+
+async fn undo_action(
+    bot: &ThrottledBot,
+    state: &BotState,
+    record: &ModRecord,
+    kind: UndoKind,
+    admin: &User,
+) -> Result<String> {
+    let chat_id = ChatId(record.chat_id);
+    match (kind, &record.target) {
+        (UndoKind::FalsePositive, _) => {
+            info!(
+                "Record #{} marked as false positive by {}",
+                record.id, admin.id
+            );
+            mark_false_positive(bot, state, record).await
+        }
+        (UndoKind::Whitelist, Target::User { id, .. }) => {
+            let user_id = UserId(*id);
+            whitelist_by_admin(bot, state, record, admin).await?;
+            if state.restrict_mode() {
+                lift_restrictions(bot, state, user_id).await;
+            }
+            Ok("✅ Добавлен в белый список".to_string())
+        }
+        (UndoKind::Whitelist, Target::Chat { id, title }) => {
+            let sender_chat_id = ChatId(*id);
+            state.add_chat_to_whitelist(sender_chat_id, title).await?;
+            // Забаненный канал писать не сможет, даже если он в белом списке
+            if state.sender_chats.ban_unknown {
+                if let Err(e) =
+                    actions::unban_sender_chat(bot, chat_id, sender_chat_id, "unban sender chat")
+                        .await
+                {
+                    error!("Failed to unban sender chat {}: {}", sender_chat_id, e);
+                }
+            }
+            record_action(
+                bot,
+                state,
+                ModRecord::new(
+                    ModAction::WhitelistAdd,
+                    record.chat_id,
+                    record.target.clone(),
+                    &format!("admin: {}", admin.full_name()),
+                ),
+            )
+            .await;
+            Ok("✅ Канал добавлен в белый список".to_string())
+        }
+        (UndoKind::Unwhitelist, Target::Chat { id, .. }) => {
+            state.remove_chat_from_whitelist(ChatId(*id)).await?;
+            record_action(
+                bot,
+                state,
+                ModRecord::new(
+                    ModAction::WhitelistRemove,
+                    record.chat_id,
+                    record.target.clone(),
+                    &format!("admin: {}", admin.full_name()),
+                ),
+            )
+            .await;
+            Ok("➖ Канал удалён из белого списка".to_string())
+        }
+        (UndoKind::Unwhitelist, Target::User { id, .. }) => {
+            state.remove_from_whitelist(UserId(*id)).await?;
+            record_action(
+                bot,
+                state,
+                ModRecord::new(
+                    ModAction::WhitelistRemove,
+                    record.chat_id,
+                    record.target.clone(),
+                    &format!("admin: {}", admin.full_name()),
+                ),
+            )
+            .await;
+            Ok("➖ Удалён из белого списка".to_string())
+        }
+        (UndoKind::Unmute, Target::User { id, .. }) => {
+            // Иначе следующее сообщение неподтверждённого снова его ограничит
+            whitelist_by_admin(bot, state, record, admin).await?;
+            lift_restrictions(bot, state, UserId(*id)).await;
+            Ok("🔊 Ограничения сняты".to_string())
+        }
+        (UndoKind::Unban, Target::User { id, .. }) => {
+            actions::unban(bot, chat_id, UserId(*id), "unban user").await?;
+            lift_global_ban(bot, state, record).await;
+            // Исключённый за неподтверждение обычно уже не забанен: без белого
+            // списка после возвращения его снова исключат
+            if record.action == ModAction::Kick {
+                whitelist_by_admin(bot, state, record, admin).await?;
+            }
+            Ok("♻️ Пользователь разбанен".to_string())
+        }
+        (UndoKind::Unban, Target::Chat { id, .. }) => {
+            actions::unban_sender_chat(bot, chat_id, ChatId(*id), "unban sender chat").await?;
+            lift_global_ban(bot, state, record).await;
+            Ok("♻️ Канал разбанен".to_string())
+        }
+        (kind, target) => Err(format!("cannot apply {:?} to {:?}", kind, target).into()),
+    }
+}
+
+// Администратор ручается за пользователя из записи: он попадает в белый список
+// и больше не получает запросов на подтверждение
+async fn whitelist_by_admin(
+    bot: &ThrottledBot,
+    state: &BotState,
+    record: &ModRecord,
+    admin: &User,
+) -> Result<()> {
+    let Target::User { id, name } = &record.target else {
+        return Ok(());
+    };
+    let user_id = UserId(*id);
+    state.clear_prompt(user_id).await;
+    if state.is_whitelisted(user_id).await {
+        return Ok(());
+    }
+    let name = if name.is_empty() {
+        id.to_string()
+    } else {
+        name.clone()
+    };
+    state.add_to_whitelist(user_id, &name).await?;
+    record_action(
+        bot,
+        state,
+        ModRecord::new(
+            ModAction::WhitelistAdd,
+            record.chat_id,
+            record.target.clone(),
+            &format!("admin: {}", admin.full_name()),
+        ),
+    )
+    .await;
+    Ok(())
+}
+
+// Обновляет сообщение с записью: новый статус, без кнопок
+async fn refresh_record_message(bot: &ThrottledBot, state: &BotState, record: &ModRecord) {
+    let (Some(chat_id), Some(message_id)) = (state.admin_log_chat_id, record.log_message_id) else {
+        return;
+    };
+    let message_id = MessageId(message_id);
+    let text = record.format();
+    if let Err(e) =
+        actions::edit_text(bot, chat_id, message_id, text, "edit moderation record").await
+    {
+        error!("Failed to edit moderation record message: {}", e);
+    }
+}
+
+async fn handle_command(
+    bot: ThrottledBot,
+    msg: Message,
+    state: Arc<BotState>,
+    cmd: Command,
+) -> Result<()> {
+    match cmd {
+        Command::Start => handle_start(bot, msg, state).await,
+        Command::Confirm => handle_confirm(bot, msg, state).await,
+        Command::Whois => handle_whois(bot, msg, state).await,
+        Command::Trust => handle_trust(bot, msg, state).await,
+        Command::Lockdown => handle_lockdown(bot, msg, state).await,
+        Command::Patterns => handle_patterns(bot, msg, state).await,
+        Command::Fp => handle_fp(bot, msg, state).await,
+        Command::Report => handle_report(bot, msg, state).await,
+        Command::Appeal => handle_appeal(bot, msg, state).await,
+    }
+}
+
+async fn is_group_admin(bot: &ThrottledBot, state: &BotState, user_id: UserId) -> bool {
+    let group_chat_id = state.group_chat_id;
+    match actions::get_chat_member(bot, group_chat_id, user_id, "get admin chat member").await {
+        Ok(member) => member.is_privileged(),
+        Err(e) => {
+            error!("Failed to check admin rights of user {}: {}", user_id, e);
+            false
+        }
+    }
+}
+
+// Цель админской команды: ответ на сообщение или ID в аргументе
+fn command_target(msg: &Message) -> Option<UserId> {
+    if let Some(user) = msg.reply_to_message().and_then(|m| m.from.as_ref()) {
+        return Some(user.id);
+    }
+    msg.text()?
+        .split_whitespace()
+        .nth(1)?
+        .parse::<u64>()
+        .ok()
+        .map(UserId)
+}
+
+async fn handle_whois(bot: ThrottledBot, msg: Message, state: Arc<BotState>) -> Result<()> {
+    let Some(user) = msg.from.as_ref() else {
+        warn!("Received /whois without user info");
+        return Ok(());
+    };
+    info!(
+        "Received /whois from user {} in chat {}",
+        user.id, msg.chat.id
+    );
+
+    let chat_id = msg.chat.id;
+    let message_id = msg.id;
+    if let Err(e) = actions::delete_command(&bot, chat_id, message_id, "delete whois command").await
+    {
+        error!("Failed to delete /whois command: {}", e);
+    }
+
+    if !is_group_admin(&bot, &state, user.id).await {
+        warn!("User {} is not an admin, ignoring /whois", user.id);
+        return Ok(());
+    }
+
+    let text = match command_target(&msg) {
+        None => "ℹ️ Ответьте на сообщение пользователя или укажите его ID: /whois <id>".to_string(),
+        Some(target) => match state.whitelist_entry(target).await {
+            Some(entry) => format!(
+                "👤 {} ({})\nУровень: {}\nЧистых сообщений: {}\nПодтверждён: {}",
+                target,
+                entry.username,
+                entry.level,
+                entry.clean_messages,
+                chrono::DateTime::from_timestamp(entry.confirmed_at, 0)
+                    .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_else(|| "неизвестно".to_string())
+            ),
+            None => format!("👤 {}\nНе подтверждён", target),
+        },
+    };
+
+    actions::reply_ephemeral(&bot, chat_id, text, "send whois response").await?;
+    Ok(())
+}
+
+async fn handle_trust(bot: ThrottledBot, msg: Message, state: Arc<BotState>) -> Result<()> {
+    let Some(user) = msg.from.as_ref() else {
+        warn!("Received /trust without user info");
+        return Ok(());
+    };
+    info!(
+        "Received /trust from user {} in chat {}",
+        user.id, msg.chat.id
+    );
+
+    let chat_id = msg.chat.id;
+    let message_id = msg.id;
+    if let Err(e) = actions::delete_command(&bot, chat_id, message_id, "delete trust command").await
+    {
+        error!("Failed to delete /trust command: {}", e);
+    }
+
+    if !is_group_admin(&bot, &state, user.id).await {
+        warn!("User {} is not an admin, ignoring /trust", user.id);
+        return Ok(());
+    }
+
+    let text = match command_target(&msg) {
+        None => "ℹ️ Ответьте на сообщение пользователя или укажите его ID: /trust <id>".to_string(),
+        Some(target) => match state.set_trust_level(target, TrustLevel::Trusted).await {
+            Ok(true) => format!("⭐ Пользователь {} переведён в доверенные", target),
+            Ok(false) => format!("❌ Пользователь {} не подтверждён", target),
+            Err(e) => {
+                error!("Failed to promote user {}: {}", target, e);
+                "⚠️ Ошибка. Попробуйте позже".to_string()
+            }
+        },
+    };
+
+    actions::reply_ephemeral(&bot, chat_id, text, "send trust response").await?;
+    Ok(())
+}
+
+async fn handle_lockdown(bot: ThrottledBot, msg: Message, state: Arc<BotState>) -> Result<()> {
+    let Some(user) = msg.from.as_ref() else {
+        warn!("Received /lockdown without user info");
+        return Ok(());
+    };
+    info!(
+        "Received /lockdown from user {} in chat {}",
+        user.id, msg.chat.id
+    );
+
+    let chat_id = msg.chat.id;
+    let message_id = msg.id;
+    if let Err(e) =
+        actions::delete_command(&bot, chat_id, message_id, "delete lockdown command").await
+    {
+        error!("Failed to delete /lockdown command: {}", e);
+    }
+
+    if !is_group_admin(&bot, &state, user.id).await {
+        warn!("User {} is not an admin, ignoring /lockdown", user.id);
+        return Ok(());
+    }
+
+    let arg = msg.text().and_then(|t| t.split_whitespace().nth(1));
+    let text = match arg {
+        Some("on") => {
+            if state.raid.start_manual().await {
+                start_lockdown(&bot, &state, RaidTrigger::Manual).await;
+                "🔒 Блокировка включена"
+            } else {
+                "ℹ️ Блокировка уже включена"
+            }
+        }
+        Some("off") => {
+            if end_lockdown(&bot, &state, "снята администратором").await {
+                "🔓 Блокировка снята"
+            } else {
+                "ℹ️ Блокировка не активна"
+            }
+        }
+        _ => {
+            if state.raid.is_active().await {
+                "🔒 Блокировка активна. /lockdown off — снять"
+            } else {
+                "🔓 Блокировка не активна. /lockdown on — включить"
+            }
+        }
+    };
+
+    actions::reply_ephemeral(&bot, chat_id, text.to_string(), "send lockdown response").await?;
+    Ok(())
+}
+
+async fn handle_patterns(bot: ThrottledBot, msg: Message, state: Arc<BotState>) -> Result<()> {
+    let Some(user) = msg.from.as_ref() else {
+        warn!("Received /patterns without user info");
+        return Ok(());
+    };
+    info!(
+        "Received /patterns from user {} in chat {}",
+        user.id, msg.chat.id
+    );
+
+    let chat_id = msg.chat.id;
+    let message_id = msg.id;
+    if let Err(e) =
+        actions::delete_command(&bot, chat_id, message_id, "delete patterns command").await
+    {
+        error!("Failed to delete /patterns command: {}", e);
+    }
+
+    if !is_group_admin(&bot, &state, user.id).await {
+        warn!("User {} is not an admin, ignoring /patterns", user.id);
+        return Ok(());
+    }
+
+    let arg = msg.text().and_then(|t| t.split_whitespace().nth(1));
+    let text = match arg {
+        Some("list") => {
+            let rules = state.forbidden_patterns.lock().await.rules();
+            if rules.is_empty() {
+                "ℹ️ Запрещённых паттернов нет".to_string()
+            } else {
+                let mut lines = vec!["📋 Паттерны (ложных срабатываний):".to_string()];
+                for rule in rules {
+                    if rule.starts_with('!') {
+                        lines.push(format!("{} — исключение", rule));
+                    } else {
+                        let count = state.pattern_stats.false_positives(&rule).await;
+                        lines.push(format!("{} — {}", rule, count));
+                    }
+                }
+                lines.join("\n")
+            }
+        }
+        _ => "ℹ️ /patterns list — список паттернов со счётчиками ложных срабатываний".to_string(),
+    };
+
+    // Лимит Telegram — 4096 символов на сообщение
+    let chunks: Vec<String> = text
+        .chars()
+        .collect::<Vec<_>>()
+        .chunks(4000)
+        .map(|c| c.iter().collect())
+        .collect();
+    for chunk in chunks {
+        // В группе ответ убирается, в личке остаётся
+        if chat_id == state.group_chat_id {
+            actions::reply_ephemeral(&bot, chat_id, chunk, "send patterns response").await?;
+        } else {
+            actions::send(&bot, chat_id, chunk, "send patterns response").await?;
+        }
+    }
+    Ok(())
+}
+
+// `/fp` в ответ на запись журнала в чате администраторов
+async fn handle_fp(bot: ThrottledBot, msg: Message, state: Arc<BotState>) -> Result<()> {
+    let Some(user) = msg.from.as_ref() else {
+        warn!("Received /fp without user info");
+        return Ok(());
+    };
+    info!("Received /fp from user {} in chat {}", user.id, msg.chat.id);
+
+    if state.admin_log_chat_id != Some(msg.chat.id) {
+        warn!("Ignoring /fp outside of the admin log chat");
+        return Ok(());
+    }
+    if !is_group_admin(&bot, &state, user.id).await {
+        warn!("User {} is not an admin, ignoring /fp", user.id);
+        return Ok(());
+    }
+
+    let record = match msg.reply_to_message() {
+        Some(reply) => state.modlog.find_by_log_message(reply.id.0).await,
+        None => None,
+    };
+    let text = match record {
+        None => "ℹ️ Ответьте командой /fp на запись журнала модерации".to_string(),
+        Some(record) => resolve_record(&bot, &state, record.id, UndoKind::FalsePositive, user)
+            .await
+            .unwrap_or_else(|answer| answer),
+    };
+
+    actions::reply(&bot, msg.chat.id, msg.id, text, "send fp response").await?;
+    Ok(())
+}
+
+// `/report` в ответ на сообщение: жалоба участника администраторам
+async fn handle_report(bot: ThrottledBot, msg: Message, state: Arc<BotState>) -> Result<()> {
+    let Some(user) = msg.from.as_ref() else {
+        warn!("Received /report without user info");
+        return Ok(());
+    };
+    info!(
+        "Received /report from user {} in chat {}",
+        user.id, msg.chat.id
+    );
+
+    let chat_id = msg.chat.id;
+    let message_id = msg.id;
+    if let Err(e) =
+        actions::delete_command(&bot, chat_id, message_id, "delete report command").await
+    {
+        error!("Failed to delete /report command: {}", e);
+    }
+
+    if chat_id != state.group_chat_id || !state.features.reports {
+        return Ok(());
+    }
+    let Some(entry) = state.whitelist_entry(user.id).await else {
+        warn!("Unverified user {} tried to /report", user.id);
+        return Ok(());
+    };
+
+    let key = match msg.reply_to_message() {
+        None => "report.usage",
+        Some(reported) => {
+            // Свои сообщения, сообщения ботов и администраторов не обжалуются
+            let is_exempt = match reported.from.as_ref() {
+                Some(author) if reported.sender_chat.is_none() => {
+                    author.id == user.id
+                        || author.is_bot
+                        || is_group_admin(&bot, &state, author.id).await
+                }
+                _ => false,
+            };
+            if is_exempt {
+                info!(
+                    "Ignoring report of message {} from user {}",
+                    reported.id, user.id
+                );
+                return Ok(());
+            }
+
+            let outcome = state
+                .reports
+                .add(reported, user, entry.level == TrustLevel::Trusted)
+                .await;
+            if !outcome.is_new_reporter {
+                "report.duplicate"
+            } else {
+                send_report_log(&bot, &state, &outcome.report).await;
+                if outcome.threshold_reached {
+                    auto_delete_reported(&bot, &state, reported.id.0).await;
+                }
+                "report.accepted"
+            }
+        }
+    };
+
+    let text = state.messages.get(Some(user), key, &[]);
+    actions::reply_ephemeral(&bot, chat_id, text, "send report response").await?;
+    Ok(())
+}
+
+// Публикует жалобу в чате администраторов или обновляет счётчик под уже опубликованной
+async fn send_report_log(bot: &ThrottledBot, state: &BotState, report: &Report) {
+    let Some(admin_chat_id) = state.admin_log_chat_id else {
+        warn!("ADMIN_LOG_CHAT_ID is not set, report is not delivered to admins");
+        return;
+    };
+    let reported_id = report.message.id.0;
+    let text = report.format();
+    let keyboard = reports::report_keyboard(reported_id);
+
+    match report.log_message_id {
+        Some(log_message_id) => {
+            if let Err(e) = actions::edit_text_with_keyboard(
+                bot,
+                admin_chat_id,
+                MessageId(log_message_id),
+                text,
+                keyboard,
+                "update report",
+            )
+            .await
+            {
+                error!("Failed to update report on message {}: {}", reported_id, e);
+            }
+        }
+        None => {
+            match actions::send_with_keyboard(bot, admin_chat_id, text, keyboard, "send report")
+                .await
+            {
+                Ok(sent) => state.reports.set_log_message(reported_id, sent.id.0).await,
+                Err(e) => error!("Failed to send report on message {}: {}", reported_id, e),
+            }
+        }
+    }
+}
+
+// Удаляет сообщение по жалобе с сохранением копии и записью в журнал
+async fn delete_reported(bot: &ThrottledBot, state: &BotState, msg: &Message) -> Result<()> {
+    let evidence = preserve_evidence(bot, state, msg, "report").await;
+    let chat_id = msg.chat.id;
+    let message_id = msg.id;
+    if let Err(e) = actions::delete(bot, chat_id, message_id, "delete reported message").await {
+        discard_evidence(bot, evidence).await;
+        return Err(e);
+    }
+    metrics::deletion("report");
+
+    let target = match (&msg.sender_chat, &msg.from) {
+        (Some(chat), _) => chat_target(chat),
+        (None, Some(user)) => user_target(user),
+        (None, None) => return Ok(()),
+    };
+    record_action(
+        bot,
+        state,
+        ModRecord::new(ModAction::Delete, chat_id.0, target, "report")
+            .with_snippet(message_text(msg))
+            .with_evidence(evidence),
+    )
+    .await;
+    Ok(())
+}
+
+async fn auto_delete_reported(bot: &ThrottledBot, state: &BotState, message_id: i32) {
+    let Some(report) = state.reports.claim(message_id).await else {
+        return;
+    };
+    info!(
+        "Message {} reached {} trusted reports, deleting",
+        message_id,
+        report.trusted_count()
+    );
+    if let Err(e) = delete_reported(bot, state, &report.message).await {
+        // Жалоба остаётся открытой: администраторы решат по кнопкам
+        error!("Failed to delete reported message {}: {}", message_id, e);
+        state.reports.release(message_id).await;
+        return;
+    }
+    state.reports.take(message_id).await;
+    close_report(
+        bot,
+        state,
+        &report,
+        "🗑 Удалено автоматически по жалобам доверенных участников",
+    )
+    .await;
+}
+
+// Удаляет сообщение по жалобе, если это ещё не сделано прошлой попыткой
+async fn delete_report_message(
+    bot: &ThrottledBot,
+    state: &BotState,
+    report: &Report,
+) -> Result<()> {
+    if !report.deleted {
+        delete_reported(bot, state, &report.message).await?;
+        state.reports.mark_deleted(report.message.id.0).await;
+    }
+    Ok(())
+}
+
+async fn resolve_report(
+    bot: &ThrottledBot,
+    state: &BotState,
+    callback: ReportCallback,
+    admin: &User,
+) -> String {
+    let Some(report) = state.reports.claim(callback.message_id).await else {
+        return "ℹ️ Жалоба уже рассмотрена".to_string();
+    };
+    let msg = &report.message;
+    let author = match (&msg.sender_chat, &msg.from) {
+        (None, Some(user)) => Some(user.clone()),
+        _ => None,
+    };
+
+    let result: Result<&str> = async {
+        match callback.action {
+            ReportAction::Dismiss => Ok("✖️ Жалоба отклонена"),
+            ReportAction::Delete => {
+                delete_report_message(bot, state, &report).await?;
+                Ok("🗑 Сообщение удалено")
+            }
+            ReportAction::Mute => {
+                delete_report_message(bot, state, &report).await?;
+                match &author {
+                    Some(user) => {
+                        restrict_unverified(bot, state, user, "report").await;
+                        Ok("🔇 Сообщение удалено, автор ограничен")
+                    }
+                    None => Ok("🗑 Сообщение удалено"),
+                }
+            }
+            ReportAction::Ban => {
+                delete_report_message(bot, state, &report).await?;
+                let Some(user) = &author else {
+                    return Ok("🗑 Сообщение удалено");
+                };
+                let chat_id = msg.chat.id;
+                actions::ban(bot, chat_id, user.id, "ban reported user").await?;
+                record_action(
+                    bot,
+                    state,
+                    ModRecord::new(ModAction::Ban, chat_id.0, user_target(user), "report"),
+                )
+                .await;
+                Ok("⛔ Сообщение удалено, автор забанен")
+            }
+        }
+    }
+    .await;
+
+    let answer = match result {
+        Ok(answer) => answer.to_string(),
+        Err(e) => {
+            // Кнопки остаются: администратор может повторить или выбрать другое действие
+            error!(
+                "Failed to resolve report on message {}: {}",
+                callback.message_id, e
+            );
+            state.reports.release(callback.message_id).await;
+            return "⚠️ Ошибка. Попробуйте позже".to_string();
+        }
+    };
+    state.reports.take(callback.message_id).await;
+    info!(
+        "Report on message {} resolved by {}: {:?}",
+        callback.message_id, admin.id, callback.action
+    );
+    close_report(
+        bot,
+        state,
+        &report,
+        &format!("{} ({})", answer, admin.full_name()),
+    )
+    .await;
+    answer
+}
+
+// Убирает кнопки под жалобой и дописывает итог
+async fn close_report(bot: &ThrottledBot, state: &BotState, report: &Report, result: &str) {
+    let (Some(chat_id), Some(message_id)) = (state.admin_log_chat_id, report.log_message_id) else {
+        return;
+    };
+    let text = format!("{}\n\n{}", report.format(), result);
+    if let Err(e) =
+        actions::edit_text(bot, chat_id, MessageId(message_id), text, "close report").await
+    {
+        error!("Failed to update report message: {}", e);
+    }
+}
+
+// `/appeal` в личке: показывает последнее ограничение и ждёт объяснения
+async fn handle_appeal(bot: ThrottledBot, msg: Message, state: Arc<BotState>) -> Result<()> {
+    let Some(user) = msg.from.as_ref() else {
+        warn!("Received /appeal without user info");
+        return Ok(());
+    };
+    info!(
+        "Received /appeal from user {} in chat {}",
+        user.id, msg.chat.id
+    );
+    if !msg.chat.is_private() {
+        return Ok(());
+    }
+
+    let record = if state.features.appeals {
+        state.modlog.latest_appealable(user.id.0).await
+    } else {
+        None
+    };
+    let text = match record {
+        _ if !state.features.appeals => state.messages.get(Some(user), "appeal.unavailable", &[]),
+        None => state.messages.get(Some(user), "appeal.nothing", &[]),
+        Some(record) if state.appeals.is_submitted(record.id).await => {
+            state.messages.get(Some(user), "appeal.pending", &[])
+        }
+        Some(record) => {
+            state.appeals.start(user.id, record.id).await;
+            state.messages.get(
+                Some(user),
+                "appeal.prompt",
+                &[
+                    ("action", record.action.description()),
+                    ("rule", &record.rule),
+                    ("time", &record.formatted_time()),
+                ],
+            )
+        }
+    };
+
+    let chat_id = msg.chat.id;
+    actions::send(&bot, chat_id, text, "send appeal prompt").await?;
+    Ok(())
+}
+
+async fn submit_appeal(
+    bot: &ThrottledBot,
+    state: &BotState,
+    appeal: Appeal,
+    user: &User,
+    explanation: &str,
+) -> Result<()> {
+    let record = state.modlog.get(appeal.record_id).await;
+    let (reply, admin_text) = match (record, state.admin_log_chat_id) {
+        (Some(record), Some(_)) => (
+            "appeal.sent",
+            Some(format!(
+                "📨 Апелляция от {} ({})\n\n{}\n\nОбъяснение: {}",
+                user.full_name(),
+                user.id,
+                record.format(),
+                explanation
+            )),
+        ),
+        _ => {
+            state.appeals.resolve(appeal.record_id).await;
+            ("appeal.unavailable", None)
+        }
+    };
+
+    if let (Some(text), Some(admin_chat_id)) = (admin_text, state.admin_log_chat_id) {
+        let keyboard = appeals::appeal_keyboard(appeal.record_id);
+        actions::send_with_keyboard(bot, admin_chat_id, text, keyboard, "send appeal").await?;
+        info!(
+            "User {} submitted an appeal of record #{}",
+            appeal.user_id, appeal.record_id
+        );
+    }
+
+    let chat_id = ChatId(user.id.0 as i64);
+    let text = state.messages.get(Some(user), reply, &[]);
+    actions::send(bot, chat_id, text, "send appeal confirmation").await?;
+    Ok(())
+}
+
+async fn resolve_appeal(
+    bot: &ThrottledBot,
+    state: &BotState,
+    q: &CallbackQuery,
+    callback: AppealCallback,
+) -> String {
+    let Some(record) = state.modlog.get(callback.record_id).await else {
+        return "❌ Запись не найдена".to_string();
+    };
+    let Target::User { id, .. } = record.target else {
+        return "❌ Запись не относится к пользователю".to_string();
+    };
+    if record.status != RecordStatus::Active {
+        state.appeals.resolve(record.id).await;
+        return "ℹ️ Действие уже отменено".to_string();
+    }
+
+    let (answer, user_text) = match callback.decision {
+        AppealDecision::Approve => {
+            // Удаление — ложное срабатывание: сообщение возвращается в группу,
+            // паттерну засчитывается ошибка
+            let kind = match record.action {
+                ModAction::Delete => UndoKind::FalsePositive,
+                ModAction::Mute => UndoKind::Unmute,
+                _ => UndoKind::Unban,
+            };
+            let answer = match resolve_record(bot, state, record.id, kind, &q.from).await {
+                Ok(answer) => answer,
+                // Апелляция остаётся открытой, кнопки — под сообщением, чтобы повторить
+                Err(answer) => return answer,
+            };
+            // Иначе неподтверждённого автора удалят снова
+            if record.action == ModAction::Delete {
+                if let Err(e) = whitelist_by_admin(bot, state, &record, &q.from).await {
+                    error!("Failed to whitelist user {} after appeal: {}", id, e);
+                }
+            }
+            (answer, "appeal.approved")
+        }
+        AppealDecision::Deny => ("❌ Апелляция отклонена".to_string(), "appeal.denied"),
+    };
+    state.appeals.resolve(record.id).await;
+    info!(
+        "Appeal of record #{} resolved by {}: {:?}",
+        record.id, q.from.id, callback.decision
+    );
+
+    // Язык клиента пользователя здесь неизвестен, отвечаем на языке группы
+    let user_chat_id = ChatId(id as i64);
+    let user_text = state.messages.get(None, user_text, &[]);
+    if let Err(e) = actions::send(bot, user_chat_id, user_text, "send appeal decision").await {
+        warn!("Failed to notify user {} about appeal decision: {}", id, e);
+    }
+
+    if let Some(message) = q.message.as_ref() {
+        let chat_id = message.chat().id;
+        let message_id = message.id();
+        let original = message
+            .regular_message()
+            .and_then(|m| m.text())
+            .unwrap_or("📨 Апелляция")
+            .to_owned();
+        let text = format!("{}\n\n{} ({})", original, answer, q.from.full_name());
+        if let Err(e) = actions::edit_text(bot, chat_id, message_id, text, "edit appeal").await {
+            error!("Failed to edit appeal message: {}", e);
+        }
+    }
+    answer
+}
+
+// Подсказку отбросила переполненная очередь: это обычный пропуск, а не ошибка обработчика
+fn skip_if_saturated<T>(result: Result<T>) -> Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(e) if e.is::<QueueSaturated>() => {
+            debug!("Prompt not sent: {}", e);
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn delete_message_later(chat_id: ChatId, message_id: MessageId) {
+    let delay = telegram_config().auto_delete_secs;
+    info!(
+        "Scheduling deletion of message {} in chat {} in {} seconds",
+        message_id, chat_id, delay
+    );
+    delete_message_at(
+        chat_id,
+        message_id,
+        chrono::Utc::now().timestamp() + delay as i64,
+    );
+}
+
+// Ставит сообщение в очередь удалений на Unix-время `due`
+fn delete_message_at(chat_id: ChatId, message_id: MessageId, due: i64) {
+    let Some(queue) = DELETION_QUEUE.get() else {
+        error!(
+            "Deletion queue is not initialized, message {} stays",
+            message_id
+        );
+        return;
+    };
+    if let Err(e) = queue.schedule(chat_id, message_id, due) {
+        error!(
+            "Failed to schedule deletion of message {} in chat {}: {}",
+            message_id, chat_id, e
+        );
+    }
+}
+
+// Единственная задача, удаляющая сообщения из очереди: наступившие удаления
+// отправляются пачками через deleteMessages
+async fn run_deletion_scheduler(bot: ThrottledBot, queue: &'static DeletionQueue) {
+    info!(
+        "Deletion scheduler started with {} queued messages",
+        queue.depth()
+    );
+    loop {
+        let now = chrono::Utc::now().timestamp();
+        let mut postponed = false;
+        for (chat_id, message_ids) in queue.due(now) {
+            let count = message_ids.len();
+            match actions::delete_messages(
+                &bot,
+                chat_id,
+                message_ids.clone(),
+                "delete scheduled messages",
+            )
+            .await
+            {
+                Ok(_) => info!(
+                    "Deleted {} scheduled messages in chat {} ({} still queued)",
+                    count,
+                    chat_id,
+                    queue.depth().saturating_sub(count)
+                ),
+                // Сбой сети или Telegram: пачка остаётся в очереди до следующего прохода
+                Err(e) if retry::classify(e.as_ref()) != ErrorClass::Permanent => {
+                    warn!(
+                        "Failed to delete {} scheduled messages in chat {}, keeping them queued: {}",
+                        count, chat_id, e
+                    );
+                    postponed = true;
+                    continue;
+                }
+                // Сообщение могли удалить вручную или бот покинул чат
+                Err(e) => error!(
+                    "Failed to delete {} scheduled messages in chat {}, dropping them: {}",
+                    count, chat_id, e
+                ),
+            }
+            if let Err(e) = queue.complete(chat_id, &message_ids) {
+                error!("Failed to update deletion queue: {}", e);
+            }
+        }
+
+        debug!("Deletion queue depth: {}", queue.depth());
+        let wait = match queue.next_due() {
+            Some(_) if postponed => DELETION_RETRY_DELAY,
+            Some(due) => Duration::from_secs((due - now).max(1) as u64),
+            None => Duration::from_secs(3600),
+        };
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = queue.wait_for_new() => {}
+        }
+    }
+}
+
+async fn fetch_linked_chat_id(bot: &ThrottledBot, group_chat_id: ChatId) -> Option<ChatId> {
+    match actions::get_chat(bot, group_chat_id, "get group chat info").await {
+        Ok(chat) => {
+            let linked_chat_id = chat.linked_chat_id().map(ChatId);
+            info!("Linked discussion channel: {:?}", linked_chat_id);
+            linked_chat_id
+        }
+        Err(e) => {
+            error!(
+                "Failed to fetch linked channel for {}: {}",
+                group_chat_id, e
+            );
+            None
+        }
+    }
+}
+
+// Запуск бота: конфигурация, логирование, состояние групп и диспетчер
+pub async fn run() {
+    dotenv().ok();
+
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Refusing to start: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(e) = logging::init(&config.log) {
+        eprintln!("Failed to initialize logging: {}", e);
+        return;
+    }
+
+    info!("Starting verification bot...");
+    let deletion_queue = init_telegram(&config.telegram);
+
+    let client = match teloxide::net::default_reqwest_settings()
+        .timeout(Duration::from_secs(config.telegram.request_timeout_secs))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to build HTTP client: {}", e);
+            return;
+        }
+    };
+    let bot = Bot::with_client(config.bot.token.clone(), client).throttle(Limits::default());
+    tokio::spawn(run_deletion_scheduler(bot.clone(), deletion_queue));
+    let me = match bot.get_me().await {
+        Ok(me) => me,
+        Err(e) => {
+            error!("Failed to get bot info: {}", e);
+            return;
+        }
+    };
+    health::bot_info_loaded();
+
+    let mut endpoints = Vec::new();
+    if config.metrics.enabled {
+        endpoints.push((config.metrics.listen.as_str(), metrics::router()));
+    }
+    if config.health.enabled {
+        endpoints.push((config.health.listen.as_str(), health::router()));
+    }
+    if !endpoints.is_empty() {
+        if let Err(e) = http::serve(endpoints).await {
+            error!("Failed to start HTTP endpoints: {}", e);
+            return;
+        }
+    }
+
+    let groups = load_groups(&bot, &config, me.username().to_owned()).await;
+    if config.health.enabled {
+        let chats = groups
+            .states
+            .iter()
+            .map(|s| (s.group_chat_id, s.required_rights()))
+            .collect();
+        tokio::spawn(health::run_probe(
+            chats,
+            health::storage_files(&config),
+            Duration::from_secs(config.health.check_interval_secs),
+        ));
+    }
+    for state in &groups.states {
+        if state.kick_after.is_some() {
+            tokio::spawn(run_kick_sweeper(bot.clone(), state.clone()));
+        }
+        tokio::spawn(run_raid_monitor(bot.clone(), state.clone()));
+        tokio::spawn(run_whitelist_flusher(state.clone()));
+        if state.admin_log_chat_id.is_some() {
+            tokio::spawn(run_deletion_digest(bot.clone(), state.clone()));
+        }
+        tokio::spawn(run_rights_check(
+            bot.clone(),
+            state.clone(),
+            me.id,
+            Duration::from_secs(config.bot.rights_check_interval_secs),
+        ));
+        if state.evidence.mode != evidence::EvidenceMode::Off {
+            tokio::spawn(run_evidence_pruner(state.clone()));
+        }
+    }
+
+    let mut dispatcher = Dispatcher::builder(bot.clone(), schema())
+        .dependencies(dptree::deps![groups.clone()])
+        .enable_ctrlc_handler()
+        .build();
+    #[cfg(unix)]
+    shutdown_on_sigterm(dispatcher.shutdown_token());
+
+    if config.webhook.enabled {
+        info!("Starting dispatcher with webhook...");
+        if let Err(e) = webhook::dispatch(&mut dispatcher, bot, &config.webhook).await {
+            error!("Failed to run webhook: {}", e);
+        }
+    } else {
+        info!("Starting dispatcher with long polling...");
+        health::polling_started(Duration::from_secs(config.health.poll_stall_secs));
+        let listener = polling::Polling::new(bot).await;
+        dispatcher
+            .dispatch_with_listener(
+                listener,
+                LoggingErrorHandler::with_custom_text("An error from the update listener"),
+            )
+            .await;
+    }
+    for state in &groups.states {
+        if let Err(e) = state.flush_whitelist().await {
+            error!("Failed to save whitelist: {}", e);
+        }
+    }
+    info!("Bot stopped");
+}
+
+// systemd и Docker останавливают процесс через SIGTERM: завершаемся так же, как по Ctrl+C,
+// чтобы вебхук успел сняться
+#[cfg(unix)]
+fn shutdown_on_sigterm(token: teloxide::dispatching::ShutdownToken) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    tokio::spawn(async move {
+        let mut sigterm = match signal(SignalKind::terminate()) {
+            Ok(sigterm) => sigterm,
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                return;
+            }
+        };
+        sigterm.recv().await;
+        info!("Received SIGTERM, shutting down");
+        match token.shutdown() {
+            Ok(stopped) => stopped.await,
+            Err(e) => warn!("Failed to shut down dispatcher: {}", e),
+        }
+    });
+}
+
+// Настройки запросов к Bot API, очередь исходящих и очередь автоудаления.
+// Задаются один раз на процесс, повторные вызовы (в тестах) ничего не меняют.
+pub fn init_telegram(config: &TelegramConfig) -> &'static DeletionQueue {
+    TELEGRAM_CONFIG.get_or_init(|| config.clone());
+    OUTBOUND_QUEUE.get_or_init(|| {
+        OutboundQueue::new(config.max_concurrent_requests, config.max_queued_requests)
+    });
+    DELETION_QUEUE.get_or_init(|| DeletionQueue::load(&config.deletion_queue_file))
+}
+
+// Состояния всех групп из конфигурации; фоновые задачи групп запускает run
+pub async fn load_groups(bot: &ThrottledBot, config: &Config, bot_username: String) -> Arc<Groups> {
+    let group_configs = config.groups();
+    let global_bans = config.global_bans.enabled.then(|| {
+        let chats = group_configs
+            .iter()
+            .map(|group| ChatId(group.bot.group_chat_id))
+            .collect();
+        Arc::new(GlobalBans::load(&config.global_bans.file, chats))
+    });
+
+    let mut patterns: HashMap<String, Arc<Mutex<ForbiddenPatterns>>> = HashMap::new();
+    let mut states = Vec::new();
+    for group in &group_configs {
+        let file = &group.files.forbidden_patterns;
+        let group_patterns = patterns
+            .entry(file.clone())
+            .or_insert_with(|| Arc::new(Mutex::new(ForbiddenPatterns::load(file))))
+            .clone();
+        let state = load_group_state(
+            bot,
+            group,
+            bot_username.clone(),
+            group_patterns,
+            global_bans.clone(),
+        )
+        .await;
+        states.push(Arc::new(state));
+    }
+    info!("Serving {} group(s)", states.len());
+    let unknown = UnknownChatSettings {
+        owner_id: config.bot.owner_id.map(|id| UserId(id as u64)),
+        allowed_chats: config
+            .bot
+            .allowed_chats
+            .iter()
+            .copied()
+            .map(ChatId)
+            .collect(),
+        leave_after: config
+            .bot
+            .leave_unknown_chats
+            .then(|| Duration::from_secs(config.bot.unknown_chat_leave_secs)),
+    };
+    if unknown.owner_id.is_none() {
+        warn!("Owner is not set, additions to unknown chats are only logged");
+    }
+    Arc::new(Groups::new(states, unknown))
+}
+
+// Дерево обработчиков обновлений. Зависимости: бот, обновление и Arc<Groups>.
+pub fn schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync>> {
+    dptree::entry()
+        .branch(Update::filter_my_chat_member().endpoint(
+            |bot: ThrottledBot, update: ChatMemberUpdated, groups: Arc<Groups>| {
+                metrics::timed("my_chat_member", handle_my_chat_member(bot, update, groups))
+            },
+        ))
+        .branch(
+            Update::filter_callback_query()
+                .filter_map(|q: CallbackQuery, groups: Arc<Groups>| groups.route_callback(&q))
+                .endpoint(
+                    |bot: ThrottledBot, q: CallbackQuery, state: Arc<BotState>| {
+                        metrics::timed("callback", handle_callback(bot, q, state))
+                    },
+                ),
+        )
+        .branch(
+            Update::filter_message()
+                .filter_map_async(
+                    |bot: ThrottledBot, msg: Message, groups: Arc<Groups>| async move {
+                        groups.route_message(&bot, &msg).await
+                    },
+                )
+                .branch(
+                    dptree::entry()
+                        // Команды от имени каналов обрабатываются в handle_group_message
+                        .filter(|msg: Message| {
+                            msg.sender_chat.is_none()
+                                && msg.text().and_then(Command::parse).is_some()
+                        })
+                        .endpoint(
+                            |bot: ThrottledBot, msg: Message, state: Arc<BotState>| async move {
+                                let cmd = Command::parse(msg.text().unwrap()).unwrap();
+                                metrics::timed("command", handle_command(bot, msg, state, cmd))
+                                    .await
+                            },
+                        ),
+                )
+                .branch(dptree::entry().endpoint(
+                    |bot: ThrottledBot, msg: Message, state: Arc<BotState>| {
+                        metrics::timed("message", handle_group_message(bot, msg, state))
+                    },
+                )),
+        )
+}
+
+// Состояние одной группы по её полным настройкам
+async fn load_group_state(
+    bot: &ThrottledBot,
+    config: &Config,
+    bot_username: String,
+    patterns: Arc<Mutex<ForbiddenPatterns>>,
+    global_bans: Option<Arc<GlobalBans>>,
+) -> BotState {
+    let group_chat_id = ChatId(config.bot.group_chat_id);
+    info!("Group chat ID: {}", group_chat_id);
+    let secret_code = config.bot.secret_code.clone().filter(|c| !c.is_empty());
+    if secret_code.is_none() {
+        warn!(
+            "Secret code is not set for group {}, verification by code is disabled",
+            group_chat_id
+        );
+    }
+    let linked_chat_id = fetch_linked_chat_id(bot, group_chat_id).await;
+
+    let verification = VerificationSettings {
+        mode: UnverifiedMode::from_config(&config.verification.mode),
+        window: Duration::from_secs(config.verification.window_secs),
+        bot_username,
+        secret_code,
+        pending_file: config.files.pending_users.clone(),
+        kick_after: config.verification.kick_after_secs.map(Duration::from_secs),
+        kick_check_interval: Duration::from_secs(config.verification.kick_check_interval_secs),
+    };
+    info!("Unverified users mode: {:?}", verification.mode);
+
+    BotState::new(
+        GroupSettings {
+            chat_id: group_chat_id,
+            whitelist_file: config.files.whitelist.clone(),
+            patterns_file: config.files.forbidden_patterns.clone(),
+            patterns,
+            messages: MessageSettings::from_config(&config.messages),
+        },
+        SenderChatSettings {
+            linked_chat_id,
+            ban_unknown: config.sender_chats.ban_unknown,
+        },
+        TrustSettings::from_config(&config.trust),
+        verification,
+        ModerationSettings {
+            admin_log_chat_id: config.bot.admin_log_chat_id.map(ChatId),
+            modlog_file: config.files.modlog.clone(),
+            pattern_stats_file: config.files.pattern_stats.clone(),
+            evidence: EvidenceSettings::from_config(&config.evidence),
+            raid: RaidSettings::from_config(&config.raid),
+            reports: ReportSettings::from_config(&config.reports),
+            features: config.features.clone(),
+            global_bans,
+        },
+    )
+}
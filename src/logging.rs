@@ -0,0 +1,365 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Local};
+use log::kv::{Key, Value, VisitSource};
+use log::{LevelFilter, Record};
+use serde_json::{json, Map};
+
+use crate::config::LogConfig;
+use crate::Result;
+
+// Скрывать ли тексты сообщений участников; задаётся один раз при запуске
+static REDACT_MESSAGES: AtomicBool = AtomicBool::new(false);
+
+// Текст сообщения для лога: при log.redact_messages остаётся только длина
+pub fn redact(text: Option<&str>) -> String {
+    match text {
+        None => "[non-text message]".to_string(),
+        Some(text) if REDACT_MESSAGES.load(Ordering::Relaxed) => {
+            format!("[redacted, {} chars]", text.chars().count())
+        }
+        Some(text) => text.to_string(),
+    }
+}
+
+// Уровни, формат, файл с ротацией и stderr по настройкам [log]
+pub fn init(config: &LogConfig) -> Result<()> {
+    REDACT_MESSAGES.store(config.redact_messages, Ordering::Relaxed);
+
+    let json = config.format == "json";
+    let mut dispatch = fern::Dispatch::new()
+        .format(move |out, message, record| {
+            let line = if json {
+                json_line(Local::now(), message, record)
+            } else {
+                text_line(Local::now(), message, record)
+            };
+            out.finish(format_args!("{}", line))
+        })
+        .level(config.level.parse().unwrap_or(LevelFilter::Info));
+    for (module, level) in &config.modules {
+        dispatch = dispatch.level_for(module.clone(), level.parse().unwrap_or(LevelFilter::Info));
+    }
+    if !config.file.trim().is_empty() {
+        let file = Mutex::new(RotatingFile::open(
+            PathBuf::from(&config.file),
+            Rotation::from_config(config),
+        )?);
+        dispatch = dispatch.chain(fern::Output::call(move |record| {
+            let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+            if let Err(e) = file.write_line(&record.args().to_string(), Local::now()) {
+                eprintln!("Failed to write log: {}", e);
+            }
+        }));
+    }
+    if config.stderr {
+        dispatch = dispatch.chain(io::stderr());
+    }
+    dispatch.apply()?;
+    Ok(())
+}
+
+// Структурные поля записи: info!(chat_id = ..., user_id = ..., action = ...; "...")
+struct Fields(Map<String, serde_json::Value>);
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+    fn visit_pair(
+        &mut self,
+        key: Key<'kvs>,
+        value: Value<'kvs>,
+    ) -> std::result::Result<(), log::kv::Error> {
+        let value = if let Some(number) = value.to_i64() {
+            json!(number)
+        } else if let Some(number) = value.to_u64() {
+            json!(number)
+        } else if let Some(flag) = value.to_bool() {
+            json!(flag)
+        } else {
+            json!(value.to_string())
+        };
+        self.0.insert(key.to_string(), value);
+        Ok(())
+    }
+}
+
+fn fields(record: &Record) -> Map<String, serde_json::Value> {
+    let mut fields = Fields(Map::new());
+    let _ = record.key_values().visit(&mut fields);
+    fields.0
+}
+
+// Прежний формат; структурные поля есть только в JSON, в тексте они дублировали бы сообщение
+fn text_line(now: DateTime<Local>, message: &std::fmt::Arguments, record: &Record) -> String {
+    format!(
+        "[{}][{}][{}] {}",
+        now.format("%Y-%m-%d %H:%M:%S"),
+        record.target(),
+        record.level(),
+        message
+    )
+}
+
+fn json_line(now: DateTime<Local>, message: &std::fmt::Arguments, record: &Record) -> String {
+    let mut entry = Map::new();
+    entry.insert("time".to_string(), json!(now.to_rfc3339()));
+    entry.insert("level".to_string(), json!(record.level().as_str()));
+    entry.insert("target".to_string(), json!(record.target()));
+    entry.insert("message".to_string(), json!(message.to_string()));
+    entry.extend(fields(record));
+    serde_json::Value::Object(entry).to_string()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Period {
+    Hourly,
+    Daily,
+}
+
+impl Period {
+    fn key(self, time: DateTime<Local>) -> String {
+        match self {
+            Period::Hourly => time.format("%Y-%m-%d %H").to_string(),
+            Period::Daily => time.format("%Y-%m-%d").to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Rotation {
+    max_size: Option<u64>,
+    period: Option<Period>,
+    keep_files: usize,
+}
+
+impl Rotation {
+    // Значения уже проверены при загрузке конфигурации
+    fn from_config(config: &LogConfig) -> Self {
+        Self {
+            max_size: (config.rotate_size_mb > 0).then(|| config.rotate_size_mb * 1024 * 1024),
+            period: match config.rotate_every.as_str() {
+                "hourly" => Some(Period::Hourly),
+                "daily" => Some(Period::Daily),
+                _ => None,
+            },
+            keep_files: config.keep_files,
+        }
+    }
+}
+
+// Файл лога, который при превышении размера или смене часа/дня переименовывается
+// в <file>.<время ротации>; старше keep_files последних копий удаляются
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    // Период, к которому относятся записи в текущем файле
+    period_key: Option<String>,
+    rotation: Rotation,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, rotation: Rotation) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let metadata = file.metadata()?;
+        // После перезапуска период определяется по времени последней записи
+        let period_key = rotation.period.map(|period| {
+            let modified: DateTime<Local> = metadata
+                .modified()
+                .map(DateTime::from)
+                .unwrap_or_else(|_| Local::now());
+            period.key(modified)
+        });
+        Ok(Self {
+            path,
+            file,
+            size: metadata.len(),
+            period_key,
+            rotation,
+        })
+    }
+
+    fn write_line(&mut self, line: &str, now: DateTime<Local>) -> io::Result<()> {
+        let length = line.len() as u64 + 1;
+        let new_period = self
+            .rotation
+            .period
+            .map(|period| period.key(now))
+            .filter(|key| self.period_key.as_ref() != Some(key));
+        let too_big = self
+            .rotation
+            .max_size
+            .is_some_and(|max| self.size > 0 && self.size + length > max);
+        if new_period.is_some() || too_big {
+            self.rotate(now)?;
+        }
+        if let Some(key) = new_period {
+            self.period_key = Some(key);
+        }
+        self.file.write_all(format!("{}\n", line).as_bytes())?;
+        self.size += length;
+        Ok(())
+    }
+
+    fn rotate(&mut self, now: DateTime<Local>) -> io::Result<()> {
+        if self.size > 0 {
+            let stamp = now.format("%Y%m%d-%H%M%S").to_string();
+            let mut rotated = self.sibling(&stamp);
+            let mut counter = 1;
+            while rotated.exists() {
+                // С нулями имена сортируются по порядку: -002 раньше -010
+                rotated = self.sibling(&format!("{}-{:03}", stamp, counter));
+                counter += 1;
+            }
+            fs::rename(&self.path, &rotated)?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        self.prune()
+    }
+
+    fn sibling(&self, suffix: &str) -> PathBuf {
+        let mut name = self.path.file_name().unwrap_or_default().to_os_string();
+        name.push(".");
+        name.push(suffix);
+        self.path.with_file_name(name)
+    }
+
+    // Имена ротированных файлов сортируются по времени ротации
+    fn prune(&self) -> io::Result<()> {
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let prefix = format!(
+            "{}.",
+            self.path.file_name().unwrap_or_default().to_string_lossy()
+        );
+        let mut rotated: Vec<PathBuf> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                entry
+                    .file_name()
+                    .to_string_lossy()
+                    .strip_prefix(&prefix)
+                    .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+            })
+            .map(|entry| entry.path())
+            .collect();
+        rotated.sort();
+        let excess = rotated.len().saturating_sub(self.rotation.keep_files);
+        for old in &rotated[..excess] {
+            fs::remove_file(old)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nstgbr-log-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn rotated_files(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name != "bot.log")
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn rotates_by_size_and_keeps_newest_files() {
+        let dir = temp_dir("size");
+        let rotation = Rotation {
+            max_size: Some(10),
+            period: None,
+            keep_files: 2,
+        };
+        let mut file = RotatingFile::open(dir.join("bot.log"), rotation).unwrap();
+        let now = Local.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap();
+        for line in ["first", "second", "third", "fourth"] {
+            file.write_line(line, now).unwrap();
+        }
+
+        assert_eq!(
+            rotated_files(&dir),
+            vec!["bot.log.20261018-120000-001", "bot.log.20261018-120000-002"]
+        );
+        assert_eq!(fs::read_to_string(dir.join("bot.log")).unwrap(), "fourth\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rotates_when_the_day_changes() {
+        let dir = temp_dir("daily");
+        let rotation = Rotation {
+            max_size: None,
+            period: Some(Period::Daily),
+            keep_files: 7,
+        };
+        let mut file = RotatingFile::open(dir.join("bot.log"), rotation).unwrap();
+        let evening = Local.with_ymd_and_hms(2026, 10, 18, 23, 59, 0).unwrap();
+        let morning = Local.with_ymd_and_hms(2026, 10, 19, 0, 1, 0).unwrap();
+        file.period_key = Some(Period::Daily.key(evening));
+        file.write_line("evening", evening).unwrap();
+        file.write_line("still evening", evening).unwrap();
+        file.write_line("morning", morning).unwrap();
+
+        assert_eq!(rotated_files(&dir), vec!["bot.log.20261019-000100"]);
+        assert_eq!(
+            fs::read_to_string(dir.join("bot.log.20261019-000100")).unwrap(),
+            "evening\nstill evening\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("bot.log")).unwrap(),
+            "morning\n"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn json_lines_carry_structured_fields() {
+        let now = Local.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap();
+        let kvs: [(&str, Value); 3] = [
+            ("chat_id", Value::from(-100123i64)),
+            ("user_id", Value::from(42u64)),
+            ("action", Value::from("delete")),
+        ];
+        let args = format_args!("Deleted message 5");
+        let record = Record::builder()
+            .args(args)
+            .level(log::Level::Info)
+            .target("audit")
+            .key_values(&kvs)
+            .build();
+
+        let entry: serde_json::Value =
+            serde_json::from_str(&json_line(now, record.args(), &record)).unwrap();
+        assert_eq!(entry["message"], json!("Deleted message 5"));
+        assert_eq!(entry["target"], json!("audit"));
+        assert_eq!(entry["chat_id"], json!(-100123));
+        assert_eq!(entry["user_id"], json!(42));
+        assert_eq!(entry["action"], json!("delete"));
+        assert_eq!(
+            text_line(now, record.args(), &record),
+            "[2026-10-18 12:00:00][audit][INFO] Deleted message 5"
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+
+use log::{error, info, warn};
+use teloxide::types::User;
+
+use crate::config::MessagesConfig;
+use crate::Result;
+
+// Встроенные каталоги; оператор может переопределить любой шаблон своим файлом
+const BUILTIN: [(&str, &str); 2] = [
+    ("ru", include_str!("../locales/ru.json")),
+    ("en", include_str!("../locales/en.json")),
+];
+const FALLBACK_LANGUAGE: &str = "ru";
+
+type Catalog = HashMap<String, String>;
+
+#[derive(Debug, Clone)]
+pub struct MessageSettings {
+    // Язык группы
+    pub language: String,
+    // Отвечать пользователю на языке его клиента (`language_code`), если есть каталог
+    pub per_user_language: bool,
+    // JSON вида `{"ru": {"ключ": "шаблон"}, "en": {...}}`
+    pub overrides_file: Option<String>,
+}
+
+impl MessageSettings {
+    pub fn from_config(config: &MessagesConfig) -> Self {
+        Self {
+            language: config.language.clone(),
+            per_user_language: config.per_user_language,
+            overrides_file: config.overrides_file.clone(),
+        }
+    }
+}
+
+pub struct Messages {
+    catalogs: HashMap<String, Catalog>,
+    language: String,
+    per_user_language: bool,
+}
+
+impl Messages {
+    pub fn load(settings: &MessageSettings) -> Self {
+        let mut catalogs: HashMap<String, Catalog> = HashMap::new();
+        for (language, content) in BUILTIN {
+            match serde_json::from_str(content) {
+                Ok(catalog) => {
+                    catalogs.insert(language.to_string(), catalog);
+                }
+                Err(e) => error!("Built-in catalog '{}' is invalid: {}", language, e),
+            }
+        }
+
+        if let Some(path) = &settings.overrides_file {
+            match Self::load_overrides(path) {
+                Ok(overrides) => {
+                    for (language, templates) in overrides {
+                        info!(
+                            "Overriding {} '{}' templates from {}",
+                            templates.len(),
+                            language,
+                            path
+                        );
+                        catalogs.entry(language).or_default().extend(templates);
+                    }
+                }
+                Err(e) => error!("Failed to load message overrides from {}: {}", path, e),
+            }
+        }
+
+        if !catalogs.contains_key(&settings.language) {
+            warn!(
+                "No message catalog for language '{}', falling back to '{}'",
+                settings.language, FALLBACK_LANGUAGE
+            );
+        }
+        Self {
+            catalogs,
+            language: settings.language.clone(),
+            per_user_language: settings.per_user_language,
+        }
+    }
+
+    fn load_overrides(path: &str) -> Result<HashMap<String, Catalog>> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    // Язык ответа: язык клиента пользователя или язык группы
+    fn language_for(&self, user: Option<&User>) -> &str {
+        if self.per_user_language {
+            let code = user
+                .and_then(|u| u.language_code.as_deref())
+                .and_then(|code| code.split(['-', '_']).next());
+            if let Some(code) = code {
+                if let Some((language, _)) = self.catalogs.get_key_value(code) {
+                    return language;
+                }
+            }
+        }
+        &self.language
+    }
+
+    // Шаблон по ключу с подстановкой `{имя}`; при отсутствии перевода — русский шаблон
+    pub fn get(&self, user: Option<&User>, key: &str, args: &[(&str, &str)]) -> String {
+        let language = self.language_for(user);
+        let template = [language, self.language.as_str(), FALLBACK_LANGUAGE]
+            .iter()
+            .find_map(|language| self.catalogs.get(*language)?.get(key));
+        let Some(template) = template else {
+            error!("Missing message template '{}'", key);
+            return key.to_string();
+        };
+
+        substitute(template, args)
+    }
+}
+
+// Подстановка за один проход по шаблону: `{bot}` внутри имени пользователя
+// остаётся текстом, а не раскрывается следующим плейсхолдером
+fn substitute(template: &str, args: &[(&str, &str)]) -> String {
+    let mut text = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        text.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let value = after.find('}').and_then(|end| {
+            let name = &after[..end];
+            args.iter()
+                .find(|(arg, _)| *arg == name)
+                .map(|(_, value)| (*value, end))
+        });
+        match value {
+            Some((value, end)) => {
+                text.push_str(value);
+                rest = &after[end + 1..];
+            }
+            None => {
+                text.push('{');
+                rest = after;
+            }
+        }
+    }
+    text.push_str(rest);
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_in_values_are_not_expanded() {
+        let args = [("name", "{bot} {name}"), ("bot", "@test_bot")];
+        assert_eq!(
+            substitute("{name}, напишите {bot}", &args),
+            "{bot} {name}, напишите @test_bot"
+        );
+        assert_eq!(
+            substitute("{unknown} {{name}} {", &args),
+            "{unknown} {{bot} {name}} {"
+        );
+    }
+}
@@ -0,0 +1,181 @@
+use std::future::Future;
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+use axum::http::header::CONTENT_TYPE;
+use axum::routing::get;
+use axum::Router;
+use log::error;
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge, register_int_gauge_vec,
+    Encoder, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, TextEncoder, TEXT_FORMAT,
+};
+use teloxide::types::ChatId;
+
+// Метрики в формате Prometheus. Метки — короткие фиксированные строки из кода,
+// а не тексты сообщений или паттерны, чтобы число рядов не росло.
+
+static MESSAGES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "bot_messages_processed_total",
+        "Messages routed to a group, by chat kind (group, private)",
+        &["chat"]
+    )
+    .unwrap()
+});
+
+static DELETIONS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "bot_deletions_total",
+        "Messages deleted by moderation, by reason",
+        &["reason"]
+    )
+    .unwrap()
+});
+
+static VERIFICATIONS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "bot_verifications_total",
+        "Users added to the whitelist, by verification method",
+        &["method"]
+    )
+    .unwrap()
+});
+
+static WHITELIST_SIZE: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!(
+        "bot_whitelist_size",
+        "Whitelisted users per group",
+        &["group"]
+    )
+    .unwrap()
+});
+
+static DELETION_QUEUE_DEPTH: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!(
+        "bot_deletion_queue_depth",
+        "Messages waiting in the scheduled deletion queue"
+    )
+    .unwrap()
+});
+
+static RETRIES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "telegram_request_retries_total",
+        "Retried Telegram API requests, by action",
+        &["action"]
+    )
+    .unwrap()
+});
+
+static FAILURES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "telegram_request_failures_total",
+        "Telegram API requests that failed after all attempts, by action",
+        &["action"]
+    )
+    .unwrap()
+});
+
+static REQUEST_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "telegram_request_duration_seconds",
+        "Latency of a single Telegram API request attempt, by action",
+        &["action"]
+    )
+    .unwrap()
+});
+
+static HANDLER_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "bot_handler_duration_seconds",
+        "Time spent handling an update, by handler",
+        &["handler"]
+    )
+    .unwrap()
+});
+
+pub fn message_processed(private: bool) {
+    let chat = if private { "private" } else { "group" };
+    MESSAGES.with_label_values(&[chat]).inc();
+}
+
+pub fn deletion(reason: &str) {
+    DELETIONS.with_label_values(&[reason]).inc();
+}
+
+pub fn verification(method: &str) {
+    VERIFICATIONS.with_label_values(&[method]).inc();
+}
+
+pub fn set_whitelist_size(group: ChatId, size: usize) {
+    WHITELIST_SIZE
+        .with_label_values(&[&group.0.to_string()])
+        .set(size as i64);
+}
+
+pub fn set_deletion_queue_depth(depth: usize) {
+    DELETION_QUEUE_DEPTH.set(depth as i64);
+}
+
+pub fn retry(action: &str) {
+    RETRIES.with_label_values(&[action]).inc();
+}
+
+pub fn failure(action: &str) {
+    FAILURES.with_label_values(&[action]).inc();
+}
+
+pub fn observe_request(action: &str, elapsed: Duration) {
+    REQUEST_DURATION
+        .with_label_values(&[action])
+        .observe(elapsed.as_secs_f64());
+}
+
+// Выполняет обработчик и записывает, сколько он занял
+pub async fn timed<F: Future>(handler: &str, handling: F) -> F::Output {
+    let started = Instant::now();
+    let output = handling.await;
+    HANDLER_DURATION
+        .with_label_values(&[handler])
+        .observe(started.elapsed().as_secs_f64());
+    output
+}
+
+fn render() -> String {
+    let mut buffer = Vec::new();
+    if let Err(e) = TextEncoder::new().encode(&prometheus::gather(), &mut buffer) {
+        error!("Failed to encode metrics: {}", e);
+    }
+    String::from_utf8(buffer).unwrap_or_default()
+}
+
+pub fn router() -> Router {
+    Router::new().route(
+        "/metrics",
+        get(|| async { ([(CONTENT_TYPE, TEXT_FORMAT)], render()) }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_recorded_metrics() {
+        deletion("unverified");
+        verification("secret_code");
+        set_whitelist_size(ChatId(-100123), 42);
+        set_deletion_queue_depth(7);
+        observe_request("send start response", Duration::from_millis(120));
+
+        let text = render();
+        assert!(text.contains("bot_deletions_total{reason=\"unverified\"}"));
+        assert!(text.contains("bot_verifications_total{method=\"secret_code\"}"));
+        assert!(text.contains("bot_whitelist_size{group=\"-100123\"} 42"));
+        assert!(text.contains("bot_deletion_queue_depth 7"));
+        assert!(text.contains(
+            "telegram_request_duration_seconds_bucket{action=\"send start response\",le=\"0.25\"}"
+        ));
+    }
+}
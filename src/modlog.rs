@@ -0,0 +1,482 @@
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+use tokio::sync::Mutex;
+
+use crate::appeals::AppealCallback;
+use crate::evidence::Evidence;
+use crate::reports::ReportCallback;
+use crate::Result;
+
+// Сколько последних записей держать в памяти и в файле
+const MAX_RECORDS: usize = 5000;
+const SNIPPET_LEN: usize = 200;
+// Сколько участников перечислять в сводке удалений
+const DIGEST_USERS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModAction {
+    Delete,
+    WhitelistAdd,
+    WhitelistRemove,
+    Mute,
+    Ban,
+    Kick,
+}
+
+impl ModAction {
+    // Как в modlog.jsonl; для структурных полей лога
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ModAction::Delete => "delete",
+            ModAction::WhitelistAdd => "whitelist_add",
+            ModAction::WhitelistRemove => "whitelist_remove",
+            ModAction::Mute => "mute",
+            ModAction::Ban => "ban",
+            ModAction::Kick => "kick",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            ModAction::Delete => "🗑 Удаление сообщения",
+            ModAction::WhitelistAdd => "✅ Добавлен в белый список",
+            ModAction::WhitelistRemove => "➖ Удалён из белого списка",
+            ModAction::Mute => "🔇 Ограничение",
+            ModAction::Ban => "⛔ Бан",
+            ModAction::Kick => "👢 Исключение из группы",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Target {
+    User { id: u64, name: String },
+    Chat { id: i64, title: String },
+}
+
+impl Target {
+    pub fn name(&self) -> &str {
+        match self {
+            Target::User { name, .. } => name,
+            Target::Chat { title, .. } => title,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Target::User { id, name } => format!("{} ({})", name, id),
+            Target::Chat { id, title } => format!("канал {} ({})", title, id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordStatus {
+    Active,
+    Undone,
+    FalsePositive,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModRecord {
+    pub id: u64,
+    pub time: i64,
+    pub action: ModAction,
+    pub chat_id: i64,
+    pub target: Target,
+    // Правило, по которому сработал бот: паттерн, "unverified", "flood" и т.п.
+    pub rule: String,
+    pub snippet: Option<String>,
+    pub status: RecordStatus,
+    // Сообщение с этой записью в чате администраторов
+    pub log_message_id: Option<i32>,
+    // Кто из администраторов отменил действие
+    #[serde(default)]
+    pub resolved_by: Option<String>,
+    // Сохранённая копия удалённого сообщения
+    #[serde(default)]
+    pub evidence: Option<Evidence>,
+}
+
+impl ModRecord {
+    pub fn new(action: ModAction, chat_id: i64, target: Target, rule: &str) -> Self {
+        Self {
+            id: 0,
+            time: chrono::Utc::now().timestamp(),
+            action,
+            chat_id,
+            target,
+            rule: rule.to_string(),
+            snippet: None,
+            status: RecordStatus::Active,
+            log_message_id: None,
+            resolved_by: None,
+            evidence: None,
+        }
+    }
+
+    pub fn with_evidence(mut self, evidence: Option<Evidence>) -> Self {
+        self.evidence = evidence.filter(|e| !e.is_empty());
+        self
+    }
+
+    pub fn with_snippet(mut self, text: Option<&str>) -> Self {
+        self.snippet = text.map(|t| t.chars().take(SNIPPET_LEN).collect());
+        self
+    }
+
+    pub fn formatted_time(&self) -> String {
+        chrono::DateTime::from_timestamp(self.time, 0)
+            .map(|t| t.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+            .unwrap_or_default()
+    }
+
+    pub fn format(&self) -> String {
+        let mut text = format!(
+            "#{} {}\nКто: {}\nЧат: {}\nПравило: {}\nВремя: {}",
+            self.id,
+            self.action.description(),
+            self.target.describe(),
+            self.chat_id,
+            self.rule,
+            self.formatted_time()
+        );
+        if let Some(snippet) = &self.snippet {
+            text.push_str(&format!("\nТекст: {}", snippet));
+        }
+        if let Some(evidence) = &self.evidence {
+            text.push_str(&format!("\nДоказательство: {}", evidence.describe()));
+        }
+        match self.status {
+            RecordStatus::Active => {}
+            RecordStatus::Undone => text.push_str("\n\n↩️ Отменено"),
+            RecordStatus::FalsePositive => text.push_str("\n\n🟡 Ложное срабатывание"),
+        }
+        if let Some(admin) = &self.resolved_by {
+            text.push_str(&format!(" ({})", admin));
+        }
+        text
+    }
+
+    pub fn undo_keyboard(&self) -> Option<InlineKeyboardMarkup> {
+        if self.status != RecordStatus::Active {
+            return None;
+        }
+        let buttons: Vec<(UndoKind, &str)> = match self.action {
+            ModAction::Delete => vec![
+                (UndoKind::FalsePositive, "🟡 Ложное срабатывание"),
+                (UndoKind::Whitelist, "✅ В белый список"),
+            ],
+            ModAction::WhitelistAdd => vec![(UndoKind::Unwhitelist, "➖ Убрать из белого списка")],
+            ModAction::WhitelistRemove => vec![(UndoKind::Whitelist, "✅ Вернуть в белый список")],
+            ModAction::Mute => vec![(UndoKind::Unmute, "🔊 Снять ограничения")],
+            ModAction::Ban | ModAction::Kick => vec![(UndoKind::Unban, "♻️ Разбанить")],
+        };
+        Some(InlineKeyboardMarkup::new([buttons
+            .into_iter()
+            .map(|(kind, label)| {
+                InlineKeyboardButton::callback(
+                    label,
+                    UndoRequest {
+                        kind,
+                        record_id: self.id,
+                    }
+                    .encode(),
+                )
+            })
+            .collect::<Vec<_>>()]))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UndoKind {
+    FalsePositive,
+    Whitelist,
+    Unwhitelist,
+    Unmute,
+    Unban,
+}
+
+impl UndoKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            UndoKind::FalsePositive => "fp",
+            UndoKind::Whitelist => "wl",
+            UndoKind::Unwhitelist => "unwl",
+            UndoKind::Unmute => "unmute",
+            UndoKind::Unban => "unban",
+        }
+    }
+
+    fn parse(text: &str) -> Option<Self> {
+        match text {
+            "fp" => Some(UndoKind::FalsePositive),
+            "wl" => Some(UndoKind::Whitelist),
+            "unwl" => Some(UndoKind::Unwhitelist),
+            "unmute" => Some(UndoKind::Unmute),
+            "unban" => Some(UndoKind::Unban),
+            _ => None,
+        }
+    }
+}
+
+// Данные кнопки отмены: `undo:<вид>:<id записи>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UndoRequest {
+    pub kind: UndoKind,
+    pub record_id: u64,
+}
+
+impl UndoRequest {
+    pub fn encode(&self) -> String {
+        format!("undo:{}:{}", self.kind.as_str(), self.record_id)
+    }
+
+    pub fn parse(data: &str) -> Option<Self> {
+        let mut parts = data.split(':');
+        if parts.next()? != "undo" {
+            return None;
+        }
+        let kind = UndoKind::parse(parts.next()?)?;
+        let record_id = parts.next()?.parse().ok()?;
+        Some(Self { kind, record_id })
+    }
+}
+
+// Данные кнопки добавления исключения: `exc:<id записи>`
+pub fn exception_keyboard(record_id: u64) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new([[InlineKeyboardButton::callback(
+        "➕ Добавить исключение",
+        format!("exc:{}", record_id),
+    )]])
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallbackAction {
+    Undo(UndoRequest),
+    AddException(u64),
+    Report(ReportCallback),
+    Appeal(AppealCallback),
+}
+
+impl CallbackAction {
+    pub fn parse(data: &str) -> Option<Self> {
+        if let Some(record_id) = data.strip_prefix("exc:") {
+            return record_id.parse().ok().map(CallbackAction::AddException);
+        }
+        if let Some(callback) = AppealCallback::parse(data) {
+            return Some(CallbackAction::Appeal(callback));
+        }
+        if let Some(callback) = ReportCallback::parse(data) {
+            return Some(CallbackAction::Report(callback));
+        }
+        UndoRequest::parse(data).map(CallbackAction::Undo)
+    }
+}
+
+pub struct ModLog {
+    records: Mutex<VecDeque<ModRecord>>,
+    path: String,
+}
+
+impl ModLog {
+    pub fn load(path: &str) -> Self {
+        info!("Loading moderation log from {}", path);
+        let mut records = VecDeque::new();
+
+        if Path::new(path).exists() {
+            match File::open(path) {
+                Ok(file) => {
+                    for line in BufReader::new(file).lines().map_while(|l| l.ok()) {
+                        match serde_json::from_str::<ModRecord>(&line) {
+                            Ok(record) => {
+                                records.push_back(record);
+                                if records.len() > MAX_RECORDS {
+                                    records.pop_front();
+                                }
+                            }
+                            Err(e) => warn!("Skipping invalid moderation log line: {}", e),
+                        }
+                    }
+                }
+                Err(e) => error!("Failed to load moderation log: {}", e),
+            }
+        }
+
+        info!("Loaded {} moderation records", records.len());
+        Self {
+            records: Mutex::new(records),
+            path: path.to_string(),
+        }
+    }
+
+    pub async fn add(&self, mut record: ModRecord) -> Result<ModRecord> {
+        let mut records = self.records.lock().await;
+        record.id = records.back().map(|r| r.id + 1).unwrap_or(1);
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(&record)?)?;
+
+        records.push_back(record.clone());
+        if records.len() > MAX_RECORDS {
+            records.pop_front();
+        }
+        Ok(record)
+    }
+
+    pub async fn get(&self, id: u64) -> Option<ModRecord> {
+        let records = self.records.lock().await;
+        records.iter().find(|r| r.id == id).cloned()
+    }
+
+    pub async fn find_by_log_message(&self, message_id: i32) -> Option<ModRecord> {
+        let records = self.records.lock().await;
+        records
+            .iter()
+            .rev()
+            .find(|r| r.log_message_id == Some(message_id))
+            .cloned()
+    }
+
+    // Последнее действующее ограничение пользователя, которое можно обжаловать
+    pub async fn latest_appealable(&self, user_id: u64) -> Option<ModRecord> {
+        let records = self.records.lock().await;
+        records
+            .iter()
+            .rev()
+            .find(|r| {
+                r.status == RecordStatus::Active
+                    && matches!(
+                        r.action,
+                        ModAction::Delete | ModAction::Mute | ModAction::Ban | ModAction::Kick
+                    )
+                    && matches!(r.target, Target::User { id, .. } if id == user_id)
+            })
+            .cloned()
+    }
+
+    // Записи, чьи копии в чате доказательств старше `cutoff`
+    pub async fn with_expired_evidence(&self, cutoff: i64) -> Vec<ModRecord> {
+        let records = self.records.lock().await;
+        records
+            .iter()
+            .filter(|r| {
+                r.time < cutoff && r.evidence.as_ref().is_some_and(|e| e.message_id.is_some())
+            })
+            .cloned()
+            .collect()
+    }
+
+    pub async fn update<F>(&self, id: u64, change: F) -> Result<Option<ModRecord>>
+    where
+        F: FnOnce(&mut ModRecord),
+    {
+        let mut records = self.records.lock().await;
+        let Some(record) = records.iter_mut().find(|r| r.id == id) else {
+            return Ok(None);
+        };
+        change(record);
+        let updated = record.clone();
+        self.save(&records)?;
+        Ok(Some(updated))
+    }
+
+    fn save(&self, records: &VecDeque<ModRecord>) -> Result<()> {
+        let tmp_path = format!("{}.tmp", self.path);
+        {
+            let mut file = File::create(&tmp_path)?;
+            for record in records {
+                writeln!(file, "{}", serde_json::to_string(record)?)?;
+            }
+            file.sync_all()?;
+        }
+        std::fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+// Удаления сообщений неподтверждённых копятся и уходят в чат журнала одной
+// сводкой: во время рейда отдельные записи завалили бы чат администраторов
+#[derive(Default)]
+pub struct DeletionDigest {
+    records: Mutex<Vec<ModRecord>>,
+}
+
+impl DeletionDigest {
+    pub async fn add(&self, record: ModRecord) {
+        self.records.lock().await.push(record);
+    }
+
+    // Текст сводки по накопленным записям; None — удалений не было
+    pub async fn take_summary(&self) -> Option<String> {
+        let records = std::mem::take(&mut *self.records.lock().await);
+        let (first, last) = (records.first()?, records.last()?);
+        let mut targets: Vec<&Target> = Vec::new();
+        for record in &records {
+            if !targets
+                .iter()
+                .any(|t| t.describe() == record.target.describe())
+            {
+                targets.push(&record.target);
+            }
+        }
+
+        let mut text = format!(
+            "🗑 Удалены сообщения неподтверждённых: {} от {} участн.\nЗаписи: #{}–#{}",
+            records.len(),
+            targets.len(),
+            first.id,
+            last.id
+        );
+        for target in targets.iter().take(DIGEST_USERS) {
+            text.push_str(&format!("\n• {}", target.describe()));
+        }
+        if targets.len() > DIGEST_USERS {
+            text.push_str(&format!("\n…и ещё {}", targets.len() - DIGEST_USERS));
+        }
+        Some(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deletion(id: u64, user_id: u64) -> ModRecord {
+        let target = Target::User {
+            id: user_id,
+            name: format!("User{}", user_id),
+        };
+        let mut record = ModRecord::new(ModAction::Delete, -100, target, "unverified");
+        record.id = id;
+        record
+    }
+
+    #[tokio::test]
+    async fn digest_summarizes_and_resets() {
+        let digest = DeletionDigest::default();
+        assert_eq!(digest.take_summary().await, None);
+
+        digest.add(deletion(7, 1)).await;
+        digest.add(deletion(8, 2)).await;
+        digest.add(deletion(9, 1)).await;
+
+        assert_eq!(
+            digest.take_summary().await.unwrap(),
+            "🗑 Удалены сообщения неподтверждённых: 3 от 2 участн.\nЗаписи: #7–#9\n• User1 (1)\n• User2 (2)"
+        );
+        assert_eq!(digest.take_summary().await, None);
+    }
+}
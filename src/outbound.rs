@@ -0,0 +1,226 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use log::{debug, warn};
+use tokio::sync::oneshot;
+
+// Очерёдность исходящих запросов, когда их больше, чем разрешено одновременно
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    // Удаление спама и действия против нарушителей
+    Moderation = 0,
+    // Журнал и уведомления администраторов, всё остальное
+    Notification = 1,
+    // Подсказки и предупреждения участникам; при перегрузке отбрасываются
+    Prompt = 2,
+}
+
+const PRIORITIES: usize = 3;
+
+// Подсказка участнику не отправлена: очередь переполнена
+#[derive(Debug)]
+pub struct QueueSaturated;
+
+impl fmt::Display for QueueSaturated {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "outbound queue is saturated, low-priority request dropped"
+        )
+    }
+}
+
+impl std::error::Error for QueueSaturated {}
+
+#[derive(Default)]
+struct GateState {
+    in_flight: usize,
+    waiting: [VecDeque<oneshot::Sender<()>>; PRIORITIES],
+}
+
+impl GateState {
+    fn waiting_count(&self) -> usize {
+        self.waiting.iter().map(VecDeque::len).sum()
+    }
+}
+
+// Ограничивает число одновременных запросов к Bot API и выдаёт освободившиеся
+// места по приоритету. Ограничения Telegram по частоте соблюдает Throttle за ней.
+pub struct OutboundQueue {
+    state: Arc<Mutex<GateState>>,
+    max_in_flight: usize,
+    // Сколько запросов может ждать, прежде чем подсказки начнут отбрасываться
+    max_waiting: usize,
+}
+
+impl OutboundQueue {
+    pub fn new(max_in_flight: usize, max_waiting: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(GateState::default())),
+            max_in_flight,
+            max_waiting,
+        }
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.state.is_poisoned()
+    }
+
+    pub async fn acquire(&self, priority: Priority) -> Result<Permit, QueueSaturated> {
+        let receiver = {
+            let mut state = self.state.lock().unwrap();
+            let ahead: usize = state.waiting[..=priority as usize]
+                .iter()
+                .map(VecDeque::len)
+                .sum();
+            if state.in_flight < self.max_in_flight && ahead == 0 {
+                state.in_flight += 1;
+                return Ok(self.permit());
+            }
+            if priority == Priority::Prompt && state.waiting_count() >= self.max_waiting {
+                warn!(
+                    "Outbound queue saturated ({} waiting), dropping prompt",
+                    state.waiting_count()
+                );
+                return Err(QueueSaturated);
+            }
+            let (sender, receiver) = oneshot::channel();
+            state.waiting[priority as usize].push_back(sender);
+            debug!(
+                "Queued {:?} request, {} waiting",
+                priority,
+                state.waiting_count()
+            );
+            receiver
+        };
+        // Место передаёт нам завершившийся запрос, счётчик уже учтён
+        let mut waiter = Waiter {
+            receiver: Some(receiver),
+            state: self.state.clone(),
+        };
+        let result = waiter.receiver.as_mut().unwrap().await;
+        waiter.receiver = None;
+        result.map(|()| self.permit()).map_err(|_| QueueSaturated)
+    }
+
+    fn permit(&self) -> Permit {
+        Permit {
+            state: self.state.clone(),
+        }
+    }
+}
+
+// Ожидание места. Если ожидающего отменили уже после того, как ему передали
+// место, место возвращается в очередь.
+struct Waiter {
+    receiver: Option<oneshot::Receiver<()>>,
+    state: Arc<Mutex<GateState>>,
+}
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        if let Some(mut receiver) = self.receiver.take() {
+            receiver.close();
+            if receiver.try_recv().is_ok() {
+                drop(Permit {
+                    state: self.state.clone(),
+                });
+            }
+        }
+    }
+}
+
+// Место в очереди; при освобождении переходит к самому приоритетному ожидающему
+pub struct Permit {
+    state: Arc<Mutex<GateState>>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        for queue in state.waiting.iter_mut() {
+            while let Some(waiter) = queue.pop_front() {
+                // Ожидающий мог уже отмениться — тогда место достаётся следующему
+                if waiter.send(()).is_ok() {
+                    return;
+                }
+            }
+        }
+        state.in_flight -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn freed_slot_goes_to_highest_priority() {
+        let queue = Arc::new(OutboundQueue::new(1, 10));
+        let busy = queue.acquire(Priority::Notification).await.unwrap();
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut tasks = Vec::new();
+        for priority in [
+            Priority::Prompt,
+            Priority::Notification,
+            Priority::Moderation,
+        ] {
+            let queue = queue.clone();
+            let order = order.clone();
+            tasks.push(tokio::spawn(async move {
+                let _permit = queue.acquire(priority).await.unwrap();
+                order.lock().unwrap().push(priority);
+            }));
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        drop(busy);
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(
+            *order.lock().unwrap(),
+            vec![
+                Priority::Moderation,
+                Priority::Notification,
+                Priority::Prompt
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn prompts_are_dropped_when_saturated() {
+        let queue = Arc::new(OutboundQueue::new(1, 1));
+        let _busy = queue.acquire(Priority::Moderation).await.unwrap();
+        let waiting = {
+            let queue = queue.clone();
+            tokio::spawn(async move { queue.acquire(Priority::Moderation).await.is_ok() })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        assert!(queue.acquire(Priority::Prompt).await.is_err());
+        waiting.abort();
+    }
+
+    #[tokio::test]
+    async fn cancelled_waiter_does_not_leak_slot() {
+        let queue = Arc::new(OutboundQueue::new(1, 10));
+        let busy = queue.acquire(Priority::Notification).await.unwrap();
+        let cancelled = {
+            let queue = queue.clone();
+            tokio::spawn(async move { queue.acquire(Priority::Notification).await.is_ok() })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        cancelled.abort();
+        let _ = cancelled.await;
+
+        drop(busy);
+        let permit =
+            tokio::time::timeout(Duration::from_secs(1), queue.acquire(Priority::Prompt)).await;
+        assert!(matches!(permit, Ok(Ok(_))));
+    }
+}
@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use log::{error, info, warn};
+use tokio::sync::Mutex;
+
+use crate::logging;
+use crate::Result;
+
+#[derive(Clone)]
+pub struct ForbiddenPatterns {
+    starts_with: Vec<String>,
+    contains: Vec<String>,
+    // Строки с `!`: совпадения внутри такой фразы не считаются нарушением
+    exceptions: Vec<String>,
+}
+
+impl ForbiddenPatterns {
+    pub fn load(path: &str) -> Self {
+        info!("Loading forbidden patterns from {}", path);
+        let mut lines = Vec::new();
+        if Path::new(path).exists() {
+            if let Ok(file) = File::open(path) {
+                lines = BufReader::new(file).lines().map_while(|l| l.ok()).collect();
+            }
+        }
+        Self::parse(&lines)
+    }
+
+    fn parse<S: AsRef<str>>(lines: &[S]) -> Self {
+        let mut starts_with = Vec::new();
+        let mut contains = Vec::new();
+        let mut exceptions = Vec::new();
+        for line in lines {
+            let line = line.as_ref().trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(exception) = line.strip_prefix('!') {
+                exceptions.push(exception.trim().to_lowercase());
+            } else if let Some(pattern) = line.strip_prefix('*') {
+                contains.push(pattern.trim().to_lowercase());
+            } else {
+                starts_with.push(line.trim().to_lowercase());
+            }
+        }
+
+        info!(
+            "Loaded {} starts_with, {} contains patterns and {} exceptions",
+            starts_with.len(),
+            contains.len(),
+            exceptions.len()
+        );
+        Self {
+            starts_with,
+            contains,
+            exceptions,
+        }
+    }
+
+    // Возвращает сработавший паттерн в том виде, как он записан в файле.
+    // Сначала ищутся совпадения запрещающих правил, затем каждое совпадение
+    // проверяется исключениями: если оно целиком лежит внутри фразы-исключения,
+    // оно не считается. Сообщение запрещено, если осталось хоть одно совпадение.
+    pub fn find_match(&self, text: &str) -> Option<String> {
+        let text = text.trim().to_lowercase();
+        let allowed = self.allowed_spans(&text);
+
+        for pattern in &self.starts_with {
+            if text.starts_with(pattern.as_str()) && !is_covered(&allowed, 0, pattern.len()) {
+                return Some(pattern.clone());
+            }
+        }
+        for pattern in &self.contains {
+            if pattern.is_empty() {
+                continue;
+            }
+            let hit = text
+                .match_indices(pattern.as_str())
+                .any(|(start, _)| !is_covered(&allowed, start, pattern.len()));
+            if hit {
+                return Some(format!("*{}", pattern));
+            }
+        }
+        None
+    }
+
+    // Участки текста, занятые фразами-исключениями
+    fn allowed_spans(&self, text: &str) -> Vec<(usize, usize)> {
+        self.exceptions
+            .iter()
+            .filter(|e| !e.is_empty())
+            .flat_map(|e| {
+                text.match_indices(e.as_str())
+                    .map(|(start, m)| (start, start + m.len()))
+            })
+            .collect()
+    }
+
+    // Все правила в том виде, как они записаны в файле
+    pub fn rules(&self) -> Vec<String> {
+        self.starts_with
+            .iter()
+            .cloned()
+            .chain(self.contains.iter().map(|p| format!("*{}", p)))
+            .chain(self.exceptions.iter().map(|e| format!("!{}", e)))
+            .collect()
+    }
+
+    pub fn has_rule(&self, rule: &str) -> bool {
+        match rule.strip_prefix('*') {
+            Some(pattern) => self.contains.iter().any(|p| p == pattern),
+            None => self.starts_with.iter().any(|p| p == rule),
+        }
+    }
+
+    pub fn add_exception(path: &str, text: &str) -> Result<()> {
+        let exception = text.trim().to_lowercase().replace('\n', " ");
+        if exception.is_empty() {
+            return Err("exception text is empty".into());
+        }
+        info!(
+            "Adding pattern exception '{}' to {}",
+            logging::redact(Some(&exception)),
+            path
+        );
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "!{}", exception)?;
+        Ok(())
+    }
+}
+
+fn is_covered(spans: &[(usize, usize)], start: usize, len: usize) -> bool {
+    spans
+        .iter()
+        .any(|&(from, to)| from <= start && start + len <= to)
+}
+
+// Счётчики ложных срабатываний по паттернам, `pattern_stats.json`
+pub struct PatternStats {
+    false_positives: Mutex<HashMap<String, u32>>,
+    path: String,
+}
+
+impl PatternStats {
+    pub fn load(path: &str) -> Self {
+        let false_positives = match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                error!("Failed to parse pattern stats {}: {}", path, e);
+                HashMap::new()
+            }),
+            Err(_) => {
+                warn!("Pattern stats file {} does not exist, starting empty", path);
+                HashMap::new()
+            }
+        };
+        Self {
+            false_positives: Mutex::new(false_positives),
+            path: path.to_string(),
+        }
+    }
+
+    pub async fn record_false_positive(&self, rule: &str) -> Result<u32> {
+        let mut stats = self.false_positives.lock().await;
+        let count = stats.entry(rule.to_string()).or_insert(0);
+        *count += 1;
+        let count = *count;
+
+        let tmp_path = format!("{}.tmp", self.path);
+        std::fs::write(&tmp_path, serde_json::to_string_pretty(&*stats)?)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        Ok(count)
+    }
+
+    pub async fn false_positives(&self, rule: &str) -> u32 {
+        let stats = self.false_positives.lock().await;
+        stats.get(rule).copied().unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Span = (usize, usize);
+
+    #[test]
+    fn finds_matches_outside_exceptions() {
+        let cases: &[(&[&str], &str, Option<&str>)] = &[
+            // Примеры из README
+            (
+                &["https://t.me "],
+                "https://t.me/join ",
+                Some("https://t.me"),
+            ),
+            (&["https://t.me "], "Посмотри сюда: https://t.me ", None),
+            (&["*реклама"], "Реклама казино", Some("*реклама")),
+            (&["*реклама"], "Это реклама", Some("*реклама")),
+            (&["*реклама"], "Рекламма", None),
+            (
+                &["*казино", "!казино рояль фильм"],
+                "Смотрели казино рояль фильм?",
+                None,
+            ),
+            (
+                &["*казино", "!казино рояль фильм"],
+                "Казино рояль фильм, а ещё лучшее онлайн-казино",
+                Some("*казино"),
+            ),
+            (
+                &["*http", "!https://example.org"],
+                "Правила: https://example.org/rules",
+                None,
+            ),
+            (
+                &["*http", "!https://example.org"],
+                "https://example.org и https://spam.com",
+                Some("*http"),
+            ),
+            // Исключение закрывает совпадение с начала сообщения
+            (&["free", "!free software"], "Free software forever", None),
+            // Исключение задевает совпадение лишь частично
+            (
+                &["*casino bonus", "!big casino"],
+                "big casino bonus",
+                Some("*casino bonus"),
+            ),
+            // Из нескольких совпадений исключение закрывает только одно
+            (
+                &["*spam", "!no spam"],
+                "no spam here, just spam",
+                Some("*spam"),
+            ),
+            (&["*spam", "!no spam"], "no spam here", None),
+            // Пустые правила ничего не запрещают и не разрешают
+            (&[], "anything", None),
+            (&["*", "!", "   "], "anything", None),
+            (&["*spam", "!"], "spam", Some("*spam")),
+        ];
+        for (rules, text, expected) in cases {
+            let patterns = ForbiddenPatterns::parse(rules);
+            assert_eq!(
+                patterns.find_match(text).as_deref(),
+                *expected,
+                "rules {:?}, text {:?}",
+                rules,
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn match_is_covered_only_inside_one_span() {
+        let cases: &[(&[Span], usize, usize, bool)] = &[
+            (&[], 0, 3, false),
+            (&[(0, 10)], 0, 10, true),
+            (&[(0, 10)], 2, 5, true),
+            (&[(0, 10)], 5, 6, false),
+            (&[(4, 10)], 2, 4, false),
+            // Соседние исключения не склеиваются
+            (&[(0, 5), (5, 10)], 3, 4, false),
+        ];
+        for (spans, start, len, expected) in cases {
+            assert_eq!(
+                is_covered(spans, *start, *len),
+                *expected,
+                "spans {:?}, match {}+{}",
+                spans,
+                start,
+                len
+            );
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use log::{debug, error, info, warn};
+use teloxide::types::UserId;
+use tokio::sync::Mutex;
+
+use crate::Result;
+
+// Неподтверждённые пользователи и время, когда бот впервые их увидел.
+// Хранится в файле строками `<id> <first_seen>`, чтобы переживать перезапуск.
+pub struct PendingUsers {
+    users: Mutex<HashMap<UserId, i64>>,
+    path: String,
+}
+
+impl PendingUsers {
+    pub fn load(path: &str) -> Self {
+        info!("Loading pending users from {}", path);
+        let mut users = HashMap::new();
+
+        if Path::new(path).exists() {
+            match File::open(path) {
+                Ok(file) => {
+                    for line in BufReader::new(file).lines().map_while(|l| l.ok()) {
+                        let mut fields = line.split_whitespace();
+                        let id = fields.next().and_then(|f| f.parse::<u64>().ok());
+                        let first_seen = fields.next().and_then(|f| f.parse::<i64>().ok());
+                        if let (Some(id), Some(first_seen)) = (id, first_seen) {
+                            users.entry(UserId(id)).or_insert(first_seen);
+                        }
+                    }
+                }
+                Err(e) => error!("Failed to load pending users: {}", e),
+            }
+        } else {
+            warn!("Pending users file {} does not exist, starting empty", path);
+        }
+
+        info!("Loaded {} pending users", users.len());
+        Self {
+            users: Mutex::new(users),
+            path: path.to_string(),
+        }
+    }
+
+    pub async fn contains(&self, user_id: UserId) -> bool {
+        self.users.lock().await.contains_key(&user_id)
+    }
+
+    // Запоминает первое появление пользователя; повторные вызовы ничего не меняют
+    pub async fn track(&self, user_id: UserId, now: i64) -> Result<()> {
+        let mut users = self.users.lock().await;
+        if users.contains_key(&user_id) {
+            return Ok(());
+        }
+        debug!("Tracking unverified user {} since {}", user_id, now);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{} {}", user_id.0, now)?;
+        users.insert(user_id, now);
+        Ok(())
+    }
+
+    pub async fn remove(&self, user_id: UserId) -> Result<()> {
+        let mut users = self.users.lock().await;
+        if users.remove(&user_id).is_some() {
+            debug!("User {} is no longer pending", user_id);
+            self.save(&users)?;
+        }
+        Ok(())
+    }
+
+    pub async fn remove_many(&self, user_ids: &[UserId]) -> Result<()> {
+        let mut users = self.users.lock().await;
+        let before = users.len();
+        for user_id in user_ids {
+            users.remove(user_id);
+        }
+        if users.len() != before {
+            self.save(&users)?;
+        }
+        Ok(())
+    }
+
+    // Пользователи, которые не подтвердились за `deadline_secs`
+    pub async fn overdue(&self, now: i64, deadline_secs: i64) -> Vec<UserId> {
+        let users = self.users.lock().await;
+        users
+            .iter()
+            .filter(|(_, first_seen)| now - **first_seen >= deadline_secs)
+            .map(|(user_id, _)| *user_id)
+            .collect()
+    }
+
+    fn save(&self, users: &HashMap<UserId, i64>) -> Result<()> {
+        let tmp_path = format!("{}.tmp", self.path);
+        {
+            let mut file = File::create(&tmp_path)?;
+            for (user_id, first_seen) in users {
+                writeln!(file, "{} {}", user_id.0, first_seen)?;
+            }
+            file.sync_all()?;
+        }
+        std::fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
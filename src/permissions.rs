@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{LazyLock, Mutex, PoisonError};
+
+use teloxide::types::{ChatId, ChatMember, ChatMemberKind};
+
+// Права администратора, без которых часть функций бота не работает
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Right {
+    Delete,
+    Restrict,
+    Ban,
+    Invite,
+}
+
+impl Right {
+    pub const ALL: [Right; 4] = [Right::Delete, Right::Restrict, Right::Ban, Right::Invite];
+
+    // Поле Bot API; ограничения и баны Telegram выдаёт одним правом
+    pub fn api_name(self) -> &'static str {
+        match self {
+            Right::Delete => "can_delete_messages",
+            Right::Restrict | Right::Ban => "can_restrict_members",
+            Right::Invite => "can_invite_users",
+        }
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Right::Delete => "удаление сообщений",
+            Right::Restrict => "ограничение участников",
+            Right::Ban => "бан участников",
+            Right::Invite => "приглашение участников",
+        }
+    }
+
+    // Что перестаёт работать без этого права
+    pub fn disables(self) -> &'static str {
+        match self {
+            Right::Delete => "спам и сообщения неподтверждённых не удаляются",
+            Right::Restrict => {
+                "режим restrict заменён удалением, блокировка группы при рейде отключена"
+            }
+            Right::Ban => "баны, глобальные баны и выгон неподтверждённых отключены",
+            Right::Invite => "бот не сможет создавать ссылки-приглашения",
+        }
+    }
+
+    fn granted(self, member: &ChatMember) -> bool {
+        match self {
+            Right::Delete => member.kind.can_delete_messages(),
+            Right::Restrict | Right::Ban => member.kind.can_restrict_members(),
+            Right::Invite => match &member.kind {
+                ChatMemberKind::Owner(_) => true,
+                ChatMemberKind::Administrator(admin) => admin.can_invite_users,
+                _ => false,
+            },
+        }
+    }
+}
+
+// Каких прав не хватает боту с таким статусом в группе
+pub fn missing_in(member: &ChatMember) -> Vec<Right> {
+    Right::ALL
+        .into_iter()
+        .filter(|right| !right.granted(member))
+        .collect()
+}
+
+// Недостающие права бота по последней проверке каждой группы. Группы, которые
+// ещё не проверялись (и чужие чаты), считаются полноправными: запрос сам
+// вернёт ошибку, если прав нет.
+static MISSING: LazyLock<Mutex<HashMap<ChatId, Vec<Right>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// Возвращает результат предыдущей проверки
+pub fn record(chat_id: ChatId, missing: Vec<Right>) -> Option<Vec<Right>> {
+    MISSING
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(chat_id, missing)
+}
+
+// None — группа ещё не проверялась
+pub fn missing(chat_id: ChatId) -> Option<Vec<Right>> {
+    MISSING
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&chat_id)
+        .cloned()
+}
+
+pub fn allows(chat_id: ChatId, right: Right) -> bool {
+    missing(chat_id).is_none_or(|missing| !missing.contains(&right))
+}
+
+// Действие, для которого у бота нет прав: его не отправляем, чтобы не тратить
+// повторы и не засорять лог одинаковыми ошибками
+#[derive(Debug)]
+pub struct MissingRight {
+    pub chat_id: ChatId,
+    pub right: Right,
+}
+
+impl fmt::Display for MissingRight {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "bot lacks {} in chat {}",
+            self.right.api_name(),
+            self.chat_id
+        )
+    }
+}
+
+impl std::error::Error for MissingRight {}
+
+pub fn require(chat_id: ChatId, right: Right) -> Result<(), MissingRight> {
+    if allows(chat_id, right) {
+        Ok(())
+    } else {
+        Err(MissingRight { chat_id, right })
+    }
+}
+
+pub fn api_names(rights: &[Right]) -> String {
+    let mut names: Vec<&str> = rights.iter().map(|r| r.api_name()).collect();
+    names.dedup();
+    names.join(", ")
+}
+
+// Сообщение в чат администраторов о недостающих правах
+pub fn describe_missing(rights: &[Right]) -> String {
+    let mut text = String::from("⚠️ Боту не хватает прав администратора в группе:");
+    for right in rights {
+        text.push_str(&format!("\n• {} — {}", right.title(), right.disables()));
+    }
+    text.push_str(
+        "\nВыдайте права в настройках администраторов группы, проверка повторится автоматически.",
+    );
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn member(value: serde_json::Value) -> ChatMember {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn bot() -> serde_json::Value {
+        json!({ "id": 123456, "is_bot": true, "first_name": "Bot" })
+    }
+
+    #[test]
+    fn finds_missing_rights() {
+        let owner = member(json!({ "status": "creator", "user": bot(), "is_anonymous": false }));
+        assert!(missing_in(&owner).is_empty());
+
+        let admin = member(json!({
+            "status": "administrator", "user": bot(), "can_be_edited": false,
+            "is_anonymous": false, "can_manage_chat": true, "can_delete_messages": true,
+            "can_manage_video_chats": false, "can_restrict_members": false,
+            "can_promote_members": false, "can_change_info": false, "can_invite_users": true,
+            "can_post_stories": false, "can_edit_stories": false, "can_delete_stories": false
+        }));
+        assert_eq!(missing_in(&admin), vec![Right::Restrict, Right::Ban]);
+        assert_eq!(api_names(&missing_in(&admin)), "can_restrict_members");
+
+        let regular = member(json!({ "status": "member", "user": bot() }));
+        assert_eq!(missing_in(&regular), Right::ALL.to_vec());
+    }
+
+    #[test]
+    fn unchecked_groups_are_allowed() {
+        let group = ChatId(-100777);
+        assert!(allows(group, Right::Delete));
+        assert_eq!(record(group, vec![Right::Delete]), None);
+        assert!(!allows(group, Right::Delete));
+        assert!(allows(group, Right::Ban));
+        assert_eq!(
+            require(group, Right::Delete).unwrap_err().to_string(),
+            "bot lacks can_delete_messages in chat -100777"
+        );
+        assert_eq!(record(group, Vec::new()), Some(vec![Right::Delete]));
+        assert!(require(group, Right::Delete).is_ok());
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::time::Duration;
+
+use futures::stream::{self, BoxStream};
+use futures::StreamExt;
+use log::{error, info};
+use teloxide::prelude::*;
+use teloxide::stop::{mk_stop_token, StopFlag, StopToken};
+use teloxide::types::{AllowedUpdate, Update};
+use teloxide::update_listeners::{AsUpdateStream, UpdateListener};
+use teloxide::RequestError;
+
+use crate::{health, ThrottledBot};
+
+// Как долго Telegram держит пустой getUpdates; request_timeout_secs должен быть больше
+const POLL_TIMEOUT_SECS: u32 = 10;
+
+// Long polling, как у teloxide, но каждый ответ getUpdates, в том числе пустой,
+// отмечается для проверки живости: без этого тихую группу не отличить от зависшего опроса
+pub struct Polling {
+    bot: ThrottledBot,
+    allowed_updates: Option<Vec<AllowedUpdate>>,
+    token: StopToken,
+    flag: Option<StopFlag>,
+}
+
+impl Polling {
+    // Как и polling_default, снимает вебхук, оставшийся от прошлого запуска
+    pub async fn new(bot: ThrottledBot) -> Self {
+        match bot.get_webhook_info().await {
+            Ok(info) if info.url.is_some() => {
+                if let Err(e) = bot.delete_webhook().await {
+                    error!("Failed to delete webhook: {}", e);
+                }
+            }
+            Ok(_) => {}
+            Err(e) => error!("Failed to get webhook info: {}", e),
+        }
+        let (token, flag) = mk_stop_token();
+        Self {
+            bot,
+            allowed_updates: None,
+            token,
+            flag: Some(flag),
+        }
+    }
+}
+
+impl UpdateListener for Polling {
+    type Err = RequestError;
+
+    fn stop_token(&mut self) -> StopToken {
+        self.token.clone()
+    }
+
+    fn hint_allowed_updates(&mut self, hint: &mut dyn Iterator<Item = AllowedUpdate>) {
+        self.allowed_updates = Some(hint.collect());
+    }
+}
+
+impl<'a> AsUpdateStream<'a> for Polling {
+    type StreamErr = RequestError;
+    type Stream = BoxStream<'a, Result<Update, RequestError>>;
+
+    fn as_stream(&'a mut self) -> Self::Stream {
+        let flag = self.flag.take().unwrap_or_else(|| {
+            let (token, flag) = mk_stop_token();
+            self.token = token;
+            flag
+        });
+        let cursor = Cursor {
+            bot: self.bot.clone(),
+            allowed_updates: self.allowed_updates.clone(),
+            flag: Box::pin(flag),
+            offset: 0,
+            buffer: VecDeque::new(),
+            errors: 0,
+            backoff: None,
+            stopped: false,
+        };
+        stream::unfold(cursor, |mut cursor| async move {
+            let item = cursor.next().await?;
+            Some((item, cursor))
+        })
+        .boxed()
+    }
+}
+
+struct Cursor {
+    bot: ThrottledBot,
+    // Передаются только в первом запросе, дальше Telegram их помнит
+    allowed_updates: Option<Vec<AllowedUpdate>>,
+    flag: Pin<Box<StopFlag>>,
+    offset: i32,
+    buffer: VecDeque<Update>,
+    // Ошибок подряд, для экспоненциальной паузы
+    errors: u32,
+    backoff: Option<Duration>,
+    stopped: bool,
+}
+
+impl Cursor {
+    async fn next(&mut self) -> Option<Result<Update, RequestError>> {
+        loop {
+            if let Some(update) = self.buffer.pop_front() {
+                return Some(Ok(update));
+            }
+            if self.stopped {
+                return None;
+            }
+            if let Some(delay) = self.backoff.take() {
+                tokio::select! {
+                    _ = self.flag.as_mut() => {
+                        self.stop().await;
+                        continue;
+                    }
+                    _ = tokio::time::sleep(delay) => {}
+                }
+            }
+
+            let mut request = self
+                .bot
+                .get_updates()
+                .offset(self.offset)
+                .timeout(POLL_TIMEOUT_SECS);
+            if let Some(allowed_updates) = self.allowed_updates.take() {
+                request = request.allowed_updates(allowed_updates);
+            }
+            let result = tokio::select! {
+                _ = self.flag.as_mut() => {
+                    self.stop().await;
+                    continue;
+                }
+                result = request.send() => result,
+            };
+            match result {
+                Ok(updates) => {
+                    health::poll_succeeded();
+                    self.errors = 0;
+                    if let Some(last) = updates.last() {
+                        self.offset = last.id.as_offset();
+                    }
+                    self.buffer.extend(updates);
+                }
+                Err(e) => {
+                    health::poll_failed(&e.to_string());
+                    let delay = match &e {
+                        RequestError::RetryAfter(seconds) => {
+                            self.errors = 0;
+                            seconds.duration()
+                        }
+                        _ => {
+                            let delay = Duration::from_secs(1 << self.errors.min(6));
+                            self.errors = self.errors.saturating_add(1);
+                            delay
+                        }
+                    };
+                    info!("Retrying getUpdates in {} s", delay.as_secs());
+                    self.backoff = Some(delay);
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+
+    // Подтверждаем полученные обновления, чтобы после перезапуска они не пришли снова
+    async fn stop(&mut self) {
+        self.stopped = true;
+        if self.offset == 0 {
+            return;
+        }
+        if let Err(e) = self
+            .bot
+            .get_updates()
+            .offset(self.offset)
+            .limit(1)
+            .timeout(0)
+            .await
+        {
+            error!("Failed to confirm received updates: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_api::FakeBotApi;
+
+    #[tokio::test]
+    async fn yields_updates_and_confirms_them_on_stop() {
+        let api = FakeBotApi::start().await;
+        api.respond(
+            "getWebhookInfo",
+            json!({ "url": "", "has_custom_certificate": false, "pending_update_count": 0 }),
+        )
+        .await;
+        api.respond(
+            "getUpdates",
+            json!([{
+                "update_id": 41,
+                "message": {
+                    "message_id": 1,
+                    "date": 0,
+                    "chat": { "id": 1, "type": "private", "first_name": "User" },
+                    "text": "hi"
+                }
+            }]),
+        )
+        .await;
+
+        let mut polling = Polling::new(api.bot()).await;
+        let stop = polling.stop_token();
+        let mut updates = polling.as_stream();
+        let update = updates.next().await.unwrap().unwrap();
+        assert_eq!(update.id.0, 41);
+        stop.stop();
+        while updates.next().await.is_some() {}
+
+        let requests = api.requests("getUpdates").await;
+        assert_eq!(requests[0]["timeout"], json!(POLL_TIMEOUT_SECS));
+        let last = requests.last().unwrap();
+        assert_eq!(last["offset"], json!(42));
+        assert_eq!(last["limit"], json!(1));
+        assert!(api.requests("deleteWebhook").await.is_empty());
+    }
+}
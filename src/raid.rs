@@ -0,0 +1,189 @@
+use std::collections::{HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use teloxide::types::{ChatPermissions, UserId};
+use tokio::sync::Mutex;
+
+use crate::config::RaidConfig;
+
+#[derive(Debug, Clone)]
+pub struct RaidSettings {
+    pub enabled: bool,
+    pub window: Duration,
+    // Сколько входов или сообщений неподтверждённых за окно считается рейдом
+    pub join_threshold: usize,
+    pub message_threshold: usize,
+    pub cooldown: Duration,
+    // Менять ли права группы по умолчанию на время блокировки
+    pub lock_group_permissions: bool,
+}
+
+impl RaidSettings {
+    pub fn from_config(config: &RaidConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            window: Duration::from_secs(config.window_secs),
+            join_threshold: config.join_threshold,
+            message_threshold: config.message_threshold,
+            cooldown: Duration::from_secs(config.cooldown_secs),
+            lock_group_permissions: config.lock_group_permissions,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RaidTrigger {
+    Joins,
+    Messages,
+    Manual,
+}
+
+impl RaidTrigger {
+    pub fn description(&self) -> &'static str {
+        match self {
+            RaidTrigger::Joins => "массовый вход участников",
+            RaidTrigger::Messages => "поток сообщений от неподтверждённых",
+            RaidTrigger::Manual => "включено администратором",
+        }
+    }
+}
+
+struct Lockdown {
+    // None — до ручного снятия
+    until: Option<Instant>,
+}
+
+pub struct RaidGuard {
+    pub settings: RaidSettings,
+    joins: Mutex<VecDeque<Instant>>,
+    messages: Mutex<VecDeque<Instant>>,
+    lockdown: Mutex<Option<Lockdown>>,
+    // Права группы до блокировки, чтобы вернуть их после
+    saved_permissions: Mutex<Option<ChatPermissions>>,
+    // Кого ограничили только из-за блокировки: с них ограничения снимаются вместе с ней
+    restricted: Mutex<HashSet<UserId>>,
+}
+
+impl RaidGuard {
+    pub fn new(settings: RaidSettings) -> Self {
+        Self {
+            settings,
+            joins: Mutex::new(VecDeque::new()),
+            messages: Mutex::new(VecDeque::new()),
+            lockdown: Mutex::new(None),
+            saved_permissions: Mutex::new(None),
+            restricted: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub async fn is_active(&self) -> bool {
+        self.lockdown.lock().await.is_some()
+    }
+
+    // Возвращает триггер, если этим событием начинается новая блокировка
+    pub async fn record_join(&self) -> Option<RaidTrigger> {
+        let count = Self::record(&self.joins, self.settings.window).await;
+        self.check(count, self.settings.join_threshold, RaidTrigger::Joins)
+            .await
+    }
+
+    pub async fn record_unverified_message(&self) -> Option<RaidTrigger> {
+        let count = Self::record(&self.messages, self.settings.window).await;
+        self.check(
+            count,
+            self.settings.message_threshold,
+            RaidTrigger::Messages,
+        )
+        .await
+    }
+
+    async fn record(events: &Mutex<VecDeque<Instant>>, window: Duration) -> usize {
+        let now = Instant::now();
+        let mut events = events.lock().await;
+        while events
+            .front()
+            .is_some_and(|t| now.duration_since(*t) > window)
+        {
+            events.pop_front();
+        }
+        events.push_back(now);
+        events.len()
+    }
+
+    async fn check(
+        &self,
+        count: usize,
+        threshold: usize,
+        trigger: RaidTrigger,
+    ) -> Option<RaidTrigger> {
+        if !self.settings.enabled || count < threshold {
+            return None;
+        }
+
+        let mut lockdown = self.lockdown.lock().await;
+        let until = Some(Instant::now() + self.settings.cooldown);
+        match lockdown.as_mut() {
+            // Рейд продолжается — продлеваем автоматическую блокировку
+            Some(active) => {
+                if active.until.is_some() {
+                    active.until = until;
+                }
+                None
+            }
+            None => {
+                warn!(
+                    "Raid detected: {} events in {:?} ({:?})",
+                    count, self.settings.window, trigger
+                );
+                *lockdown = Some(Lockdown { until });
+                Some(trigger)
+            }
+        }
+    }
+
+    pub async fn start_manual(&self) -> bool {
+        let mut lockdown = self.lockdown.lock().await;
+        if lockdown.is_some() {
+            return false;
+        }
+        info!("Lockdown enabled manually");
+        *lockdown = Some(Lockdown { until: None });
+        true
+    }
+
+    pub async fn end(&self) -> bool {
+        let mut lockdown = self.lockdown.lock().await;
+        let was_active = lockdown.take().is_some();
+        if was_active {
+            info!("Lockdown ended");
+            self.joins.lock().await.clear();
+            self.messages.lock().await.clear();
+        }
+        was_active
+    }
+
+    pub async fn is_expired(&self) -> bool {
+        let lockdown = self.lockdown.lock().await;
+        matches!(
+            lockdown.as_ref(),
+            Some(Lockdown { until: Some(until) }) if Instant::now() >= *until
+        )
+    }
+
+    pub async fn save_permissions(&self, permissions: ChatPermissions) {
+        *self.saved_permissions.lock().await = Some(permissions);
+    }
+
+    pub async fn take_saved_permissions(&self) -> Option<ChatPermissions> {
+        self.saved_permissions.lock().await.take()
+    }
+
+    pub async fn record_restricted(&self, user_id: UserId) {
+        self.restricted.lock().await.insert(user_id);
+    }
+
+    pub async fn take_restricted(&self) -> Vec<UserId> {
+        self.restricted.lock().await.drain().collect()
+    }
+}
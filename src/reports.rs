@@ -0,0 +1,231 @@
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use log::info;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, Message, User, UserId};
+use tokio::sync::Mutex;
+
+use crate::config::ReportsConfig;
+
+// Жалобы старше суток забываются
+const REPORT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Clone)]
+pub struct ReportSettings {
+    // Сколько разных доверенных участников должны пожаловаться для автоудаления; 0 — не удалять
+    pub auto_delete_threshold: usize,
+}
+
+impl ReportSettings {
+    pub fn from_config(config: &ReportsConfig) -> Self {
+        Self {
+            auto_delete_threshold: config.auto_delete_threshold,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportAction {
+    Delete,
+    Mute,
+    Ban,
+    Dismiss,
+}
+
+impl ReportAction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ReportAction::Delete => "delete",
+            ReportAction::Mute => "mute",
+            ReportAction::Ban => "ban",
+            ReportAction::Dismiss => "dismiss",
+        }
+    }
+
+    fn parse(text: &str) -> Option<Self> {
+        match text {
+            "delete" => Some(ReportAction::Delete),
+            "mute" => Some(ReportAction::Mute),
+            "ban" => Some(ReportAction::Ban),
+            "dismiss" => Some(ReportAction::Dismiss),
+            _ => None,
+        }
+    }
+}
+
+// Данные кнопки под жалобой: `report:<действие>:<id сообщения>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReportCallback {
+    pub action: ReportAction,
+    pub message_id: i32,
+}
+
+impl ReportCallback {
+    pub fn encode(&self) -> String {
+        format!("report:{}:{}", self.action.as_str(), self.message_id)
+    }
+
+    pub fn parse(data: &str) -> Option<Self> {
+        let mut parts = data.split(':');
+        if parts.next()? != "report" {
+            return None;
+        }
+        let action = ReportAction::parse(parts.next()?)?;
+        let message_id = parts.next()?.parse().ok()?;
+        Some(Self { action, message_id })
+    }
+}
+
+pub fn report_keyboard(message_id: i32) -> InlineKeyboardMarkup {
+    let button = |label: &str, action| {
+        InlineKeyboardButton::callback(label, ReportCallback { action, message_id }.encode())
+    };
+    InlineKeyboardMarkup::new([
+        vec![
+            button("🗑 Удалить", ReportAction::Delete),
+            button("🔇 Ограничить", ReportAction::Mute),
+            button("⛔ Забанить", ReportAction::Ban),
+        ],
+        vec![button("✖️ Отклонить", ReportAction::Dismiss)],
+    ])
+}
+
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub message: Message,
+    // Имена пожаловавшихся в порядке жалоб
+    pub reporters: Vec<String>,
+    reporter_ids: HashSet<UserId>,
+    trusted_reporters: HashSet<UserId>,
+    // Сообщение с жалобой в чате администраторов
+    pub log_message_id: Option<i32>,
+    // Сообщение уже удалено, хотя остальное действие по жалобе не удалось
+    pub deleted: bool,
+    // Действие по жалобе выполняется: вторая кнопка не должна его повторить
+    in_progress: bool,
+    created: Instant,
+}
+
+impl Report {
+    pub fn trusted_count(&self) -> usize {
+        self.trusted_reporters.len()
+    }
+
+    pub fn format(&self) -> String {
+        let author = match (&self.message.sender_chat, &self.message.from) {
+            (Some(chat), _) => format!("канал {} ({})", chat.title().unwrap_or(""), chat.id),
+            (None, Some(user)) => format!("{} ({})", user.full_name(), user.id),
+            (None, None) => "неизвестно".to_string(),
+        };
+        let mut text = format!(
+            "🚩 Жалоба на сообщение\nАвтор: {}\nЖалоб: {} (доверенных: {})\nОт: {}",
+            author,
+            self.reporters.len(),
+            self.trusted_count(),
+            self.reporters.join(", ")
+        );
+        if let Some(url) = self.message.url() {
+            text.push_str(&format!("\nСсылка: {}", url));
+        }
+        if let Some(content) = self.message.text().or_else(|| self.message.caption()) {
+            let snippet: String = content.chars().take(200).collect();
+            text.push_str(&format!("\nТекст: {}", snippet));
+        }
+        text
+    }
+}
+
+pub struct ReportOutcome {
+    pub report: Report,
+    // Первая жалоба этого участника на сообщение
+    pub is_new_reporter: bool,
+    // Набралось доверенных жалоб для автоудаления
+    pub threshold_reached: bool,
+}
+
+pub struct Reports {
+    pub settings: ReportSettings,
+    reports: Mutex<HashMap<i32, Report>>,
+}
+
+impl Reports {
+    pub fn new(settings: ReportSettings) -> Self {
+        Self {
+            settings,
+            reports: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn add(&self, message: &Message, reporter: &User, trusted: bool) -> ReportOutcome {
+        let mut reports = self.reports.lock().await;
+        reports.retain(|_, r| r.created.elapsed() < REPORT_TTL);
+
+        let report = reports.entry(message.id.0).or_insert_with(|| Report {
+            message: message.clone(),
+            reporters: Vec::new(),
+            reporter_ids: HashSet::new(),
+            trusted_reporters: HashSet::new(),
+            log_message_id: None,
+            deleted: false,
+            in_progress: false,
+            created: Instant::now(),
+        });
+        let is_new_reporter = report.reporter_ids.insert(reporter.id);
+        if is_new_reporter {
+            report.reporters.push(reporter.full_name());
+            if trusted {
+                report.trusted_reporters.insert(reporter.id);
+            }
+            info!(
+                "User {} reported message {} ({} reports)",
+                reporter.id,
+                message.id,
+                report.reporters.len()
+            );
+        }
+
+        let threshold = self.settings.auto_delete_threshold;
+        let threshold_reached =
+            is_new_reporter && trusted && threshold > 0 && report.trusted_count() == threshold;
+        ReportOutcome {
+            report: report.clone(),
+            is_new_reporter,
+            threshold_reached,
+        }
+    }
+
+    pub async fn set_log_message(&self, message_id: i32, log_message_id: i32) {
+        if let Some(report) = self.reports.lock().await.get_mut(&message_id) {
+            report.log_message_id = Some(log_message_id);
+        }
+    }
+
+    // Берёт жалобу в работу. Она остаётся в списке, пока действие не выполнено:
+    // при ошибке release возвращает её администраторам
+    pub async fn claim(&self, message_id: i32) -> Option<Report> {
+        let mut reports = self.reports.lock().await;
+        let report = reports.get_mut(&message_id)?;
+        if report.in_progress {
+            return None;
+        }
+        report.in_progress = true;
+        Some(report.clone())
+    }
+
+    pub async fn release(&self, message_id: i32) {
+        if let Some(report) = self.reports.lock().await.get_mut(&message_id) {
+            report.in_progress = false;
+        }
+    }
+
+    pub async fn mark_deleted(&self, message_id: i32) {
+        if let Some(report) = self.reports.lock().await.get_mut(&message_id) {
+            report.deleted = true;
+        }
+    }
+
+    // Жалоба закрыта: сообщение удалено или жалоба отклонена
+    pub async fn take(&self, message_id: i32) -> Option<Report> {
+        self.reports.lock().await.remove(&message_id)
+    }
+}
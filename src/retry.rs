@@ -0,0 +1,358 @@
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+use log::{debug, error, info, warn};
+use teloxide::{ApiError, RequestError};
+
+use crate::config::TelegramConfig;
+use crate::metrics;
+use crate::outbound::{Priority, QueueSaturated};
+use crate::Result;
+
+// Ответы Bot API на сбои на стороне Telegram: такой запрос имеет смысл повторить
+const TRANSIENT_API_ERRORS: [&str; 4] = [
+    "Internal Server Error",
+    "Bad Gateway",
+    "Service Unavailable",
+    "Gateway Timeout",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    // Повтор ничего не изменит: нет прав, сообщение уже удалено, неверный запрос
+    Permanent,
+    // Флуд-контроль: Telegram сам говорит, сколько ждать
+    RetryAfter(Duration),
+    // Сеть, таймаут, сбой на стороне Telegram
+    Transient,
+}
+
+pub fn classify(error: &(dyn Error + Send + Sync + 'static)) -> ErrorClass {
+    if error.is::<QueueSaturated>() {
+        return ErrorClass::Permanent;
+    }
+    let Some(error) = error.downcast_ref::<RequestError>() else {
+        // Ошибки не от Bot API (например, файловые) считаем временными, как раньше
+        return ErrorClass::Transient;
+    };
+    match error {
+        RequestError::RetryAfter(seconds) => ErrorClass::RetryAfter(seconds.duration()),
+        RequestError::Network(_) | RequestError::Io(_) | RequestError::InvalidJson { .. } => {
+            ErrorClass::Transient
+        }
+        RequestError::Api(ApiError::Unknown(description))
+            if TRANSIENT_API_ERRORS.iter().any(|e| description.contains(e)) =>
+        {
+            ErrorClass::Transient
+        }
+        RequestError::Api(_) | RequestError::MigrateToChatId(_) => ErrorClass::Permanent,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    // Всего попыток, включая первую
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    // Дольше этого RetryAfter не ждём: запрос считается неудавшимся
+    pub max_retry_after: Duration,
+}
+
+impl RetryPolicy {
+    pub fn from_config(config: &TelegramConfig) -> Self {
+        Self {
+            max_attempts: config.retry_attempts,
+            base_delay: Duration::from_millis(config.retry_base_delay_ms),
+            max_delay: Duration::from_millis(config.retry_max_delay_ms),
+            max_retry_after: Duration::from_secs(config.retry_max_wait_secs),
+        }
+    }
+
+    // Пауза перед следующей попыткой после неудачной попытки `attempt` (с 1);
+    // None — больше не пытаться. `jitter` в диапазоне [0, 1).
+    pub fn delay(&self, attempt: u32, class: ErrorClass, jitter: f64) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        match class {
+            ErrorClass::Permanent => None,
+            ErrorClass::RetryAfter(wait) if wait > self.max_retry_after => None,
+            ErrorClass::RetryAfter(wait) => Some(wait),
+            ErrorClass::Transient => {
+                // Экспоненциальный рост с потолком; случайная половина паузы
+                // разводит повторы параллельных запросов
+                let exponent = (attempt - 1).min(16);
+                let backoff = self
+                    .base_delay
+                    .saturating_mul(1 << exponent)
+                    .min(self.max_delay);
+                Some(backoff.mul_f64(0.5 + jitter / 2.0))
+            }
+        }
+    }
+}
+
+// Каждая попытка заново встаёт в очередь исходящих запросов со своим приоритетом,
+// чтобы пауза между повторами не занимала место
+pub async fn send_with_priority<F, T>(priority: Priority, action: F, action_name: &str) -> Result<T>
+where
+    F: Fn() -> BoxFuture<'static, Result<T>>,
+    T: Send + 'static,
+{
+    let policy = RetryPolicy::from_config(crate::telegram_config());
+    let queue = crate::OUTBOUND_QUEUE.get();
+    retry_with_policy(
+        &policy,
+        move || {
+            let request = action();
+            let action_name = action_name.to_string();
+            Box::pin(async move {
+                let _permit = match queue {
+                    Some(queue) => Some(queue.acquire(priority).await?),
+                    None => None,
+                };
+                // Время в очереди в задержку запроса не входит
+                let started = Instant::now();
+                let result = request.await;
+                metrics::observe_request(&action_name, started.elapsed());
+                result
+            })
+        },
+        action_name,
+    )
+    .await
+}
+
+pub async fn retry_with_policy<F, T>(
+    policy: &RetryPolicy,
+    action: F,
+    action_name: &str,
+) -> Result<T>
+where
+    F: Fn() -> BoxFuture<'static, Result<T>>,
+{
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let e = match action().await {
+            Ok(result) => {
+                info!(
+                    "Successfully completed {} after {} attempts",
+                    action_name, attempts
+                );
+                return Ok(result);
+            }
+            Err(e) => e,
+        };
+
+        // Отброшенная подсказка — не сбой запроса, вызывающий её просто пропускает
+        if e.is::<QueueSaturated>() {
+            debug!("Skipped {}: {}", action_name, e);
+            return Err(e);
+        }
+        let class = classify(e.as_ref());
+        match policy.delay(attempts, class, fastrand::f64()) {
+            Some(delay) => {
+                metrics::retry(action_name);
+                warn!(
+                    "Attempt {} of {} failed for {} ({:?}): {}. Retrying in {:?}",
+                    attempts, policy.max_attempts, action_name, class, e, delay
+                );
+                tokio::time::sleep(delay).await;
+            }
+            None => {
+                metrics::failure(action_name);
+                error!(
+                    "Failed to complete {} after {} attempts ({:?}). Last error: {}",
+                    action_name, attempts, class, e
+                );
+                return Err(e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    use teloxide::types::Seconds;
+
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(3),
+            max_retry_after: Duration::from_secs(60),
+        }
+    }
+
+    fn io_error() -> RequestError {
+        RequestError::Io(std::io::Error::new(
+            std::io::ErrorKind::ConnectionReset,
+            "reset",
+        ))
+    }
+
+    // Запрос, который первые `failures` раз возвращает ошибку из `make_error`
+    fn flaky(
+        calls: Arc<AtomicU32>,
+        failures: u32,
+        make_error: fn() -> RequestError,
+    ) -> impl Fn() -> BoxFuture<'static, Result<u32>> {
+        move || {
+            let calls = calls.clone();
+            Box::pin(async move {
+                let call = calls.fetch_add(1, Ordering::SeqCst) + 1;
+                if call <= failures {
+                    Err(make_error().into())
+                } else {
+                    Ok(call)
+                }
+            })
+        }
+    }
+
+    #[test]
+    fn classifies_request_errors() {
+        let classify_request = |e: RequestError| classify(&e);
+        assert_eq!(
+            classify_request(RequestError::Api(ApiError::MessageToDeleteNotFound)),
+            ErrorClass::Permanent
+        );
+        assert_eq!(
+            classify_request(RequestError::Api(ApiError::NotEnoughRightsToRestrict)),
+            ErrorClass::Permanent
+        );
+        assert_eq!(
+            classify_request(RequestError::RetryAfter(Seconds::from_seconds(7))),
+            ErrorClass::RetryAfter(Duration::from_secs(7))
+        );
+        assert_eq!(classify_request(io_error()), ErrorClass::Transient);
+        assert_eq!(
+            classify_request(RequestError::Api(ApiError::Unknown(
+                "Bad Gateway".to_string()
+            ))),
+            ErrorClass::Transient
+        );
+        assert_eq!(
+            classify_request(RequestError::Api(ApiError::Unknown(
+                "Bad Request: something new".to_string()
+            ))),
+            ErrorClass::Permanent
+        );
+    }
+
+    #[test]
+    fn dropped_prompt_is_not_retried() {
+        assert_eq!(classify(&QueueSaturated), ErrorClass::Permanent);
+    }
+
+    #[test]
+    fn backoff_grows_exponentially_with_cap_and_jitter() {
+        let policy = policy();
+        let transient = ErrorClass::Transient;
+        assert_eq!(
+            policy.delay(1, transient, 1.0),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(
+            policy.delay(2, transient, 1.0),
+            Some(Duration::from_millis(1000))
+        );
+        assert_eq!(
+            policy.delay(3, transient, 0.0),
+            Some(Duration::from_millis(1000))
+        );
+        let capped = RetryPolicy {
+            max_attempts: 10,
+            ..policy
+        };
+        assert_eq!(
+            capped.delay(8, transient, 1.0),
+            Some(Duration::from_secs(3))
+        );
+        assert_eq!(capped.delay(10, transient, 1.0), None);
+    }
+
+    #[test]
+    fn retry_after_is_honoured_up_to_limit() {
+        let policy = policy();
+        let wait = |secs| ErrorClass::RetryAfter(Duration::from_secs(secs));
+        assert_eq!(
+            policy.delay(1, wait(12), 0.3),
+            Some(Duration::from_secs(12))
+        );
+        assert_eq!(policy.delay(1, wait(61), 0.3), None);
+        assert_eq!(policy.delay(1, ErrorClass::Permanent, 0.3), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn permanent_error_is_not_retried() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let result = retry_with_policy(
+            &policy(),
+            flaky(calls.clone(), 10, || {
+                RequestError::Api(ApiError::MessageToDeleteNotFound)
+            }),
+            "delete",
+        )
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dropped_prompt_is_returned_as_is() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = calls.clone();
+        let result: Result<()> = retry_with_policy(
+            &policy(),
+            move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Box::pin(async { Err(QueueSaturated.into()) })
+            },
+            "send prompt",
+        )
+        .await;
+        assert!(result.unwrap_err().is::<QueueSaturated>());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn network_errors_are_retried_until_success() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let result = retry_with_policy(&policy(), flaky(calls.clone(), 2, io_error), "send").await;
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_up_after_max_attempts() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let result = retry_with_policy(&policy(), flaky(calls.clone(), 10, io_error), "send").await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn waits_exactly_retry_after() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let started = tokio::time::Instant::now();
+        let result = retry_with_policy(
+            &policy(),
+            flaky(calls.clone(), 1, || {
+                RequestError::RetryAfter(Seconds::from_seconds(5))
+            }),
+            "send",
+        )
+        .await;
+        assert_eq!(result.unwrap(), 2);
+        assert_eq!(started.elapsed(), Duration::from_secs(5));
+    }
+}
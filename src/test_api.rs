@@ -0,0 +1,136 @@
+use serde_json::{json, Value};
+use teloxide::adaptors::throttle::Limits;
+use teloxide::prelude::*;
+use wiremock::matchers::{body_partial_json, method, path_regex};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use teloxide::adaptors::Throttle;
+
+// Поддельный Bot API для тестов: отвечает заготовленными ответами и запоминает запросы
+pub struct FakeBotApi {
+    server: MockServer,
+}
+
+impl FakeBotApi {
+    pub async fn start() -> Self {
+        Self {
+            server: MockServer::start().await,
+        }
+    }
+
+    pub fn bot(&self) -> Throttle<Bot> {
+        Bot::new("123456:TEST")
+            .set_api_url(self.server.uri().parse().unwrap())
+            .throttle(Limits::default())
+    }
+
+    // Успешный ответ на метод (имя без учёта регистра: sendMessage, deleteMessage, ...)
+    pub async fn respond(&self, api_method: &str, result: Value) {
+        self.mount(api_method, json!({ "ok": true, "result": result }))
+            .await;
+    }
+
+    pub async fn fail(&self, api_method: &str, error_code: u16, description: &str) {
+        self.mount(
+            api_method,
+            json!({ "ok": false, "error_code": error_code, "description": description }),
+        )
+        .await;
+    }
+
+    // Ошибка только для запросов в один чат; перекрывает ответы, заданные через `respond`
+    #[allow(dead_code)] // нужна только тестам обработчиков
+    pub async fn fail_in_chat(
+        &self,
+        api_method: &str,
+        chat_id: i64,
+        error_code: u16,
+        description: &str,
+    ) {
+        Mock::given(method("POST"))
+            .and(path_regex(format!("(?i)/{}$", api_method)))
+            .and(body_partial_json(json!({ "chat_id": chat_id })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(
+                json!({ "ok": false, "error_code": error_code, "description": description }),
+            ))
+            .with_priority(1)
+            .mount(&self.server)
+            .await;
+    }
+
+    async fn mount(&self, api_method: &str, body: Value) {
+        Mock::given(method("POST"))
+            .and(path_regex(format!("(?i)/{}$", api_method)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .mount(&self.server)
+            .await;
+    }
+
+    // Тела запросов к методу в порядке поступления
+    pub async fn requests(&self, api_method: &str) -> Vec<Value> {
+        let suffix = format!("/{}", api_method.to_lowercase());
+        self.server
+            .received_requests()
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|r| r.url.path().to_lowercase().ends_with(&suffix))
+            .map(|r| {
+                serde_json::from_slice(&r.body).unwrap_or_else(|_| {
+                    let content_type = r
+                        .headers
+                        .get("content-type")
+                        .and_then(|v| v.to_str().ok())
+                        .unwrap_or_default();
+                    multipart_fields(content_type, &String::from_utf8_lossy(&r.body))
+                })
+            })
+            .collect()
+    }
+}
+
+// Текстовые поля multipart-запроса (так teloxide отправляет запросы с файлами)
+fn multipart_fields(content_type: &str, body: &str) -> Value {
+    let Some((_, boundary)) = content_type.split_once("boundary=") else {
+        return Value::Null;
+    };
+    let mut fields = serde_json::Map::new();
+    for part in body.split(&format!("--{}", boundary)) {
+        let Some((headers, content)) = part.split_once("\r\n\r\n") else {
+            continue;
+        };
+        let name = headers
+            .split("name=\"")
+            .nth(1)
+            .and_then(|rest| rest.split('"').next());
+        if let (Some(name), false) = (name, headers.contains("filename=")) {
+            let content = content.strip_suffix("\r\n").unwrap_or(content);
+            fields.insert(name.to_string(), Value::String(content.to_string()));
+        }
+    }
+    Value::Object(fields)
+}
+
+// Сообщение бота в ответ на sendMessage
+pub fn sent_message(chat_id: i64, message_id: i32, text: &str) -> Value {
+    json!({
+        "message_id": message_id,
+        "date": 0,
+        "chat": { "id": chat_id, "type": "supergroup", "title": "Test group" },
+        "from": bot_user(),
+        "text": text,
+    })
+}
+
+// Ответ getMe
+pub fn bot_user() -> Value {
+    json!({
+        "id": 123456,
+        "is_bot": true,
+        "first_name": "Bot",
+        "username": "test_bot",
+        "can_join_groups": true,
+        "can_read_all_group_messages": false,
+        "supports_inline_queries": false,
+    })
+}
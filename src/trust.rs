@@ -0,0 +1,199 @@
+use std::fmt;
+
+use teloxide::types::{Message, MessageEntityKind};
+
+use crate::config::TrustConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TrustLevel {
+    New,
+    Regular,
+    Trusted,
+}
+
+impl TrustLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrustLevel::New => "new",
+            TrustLevel::Regular => "regular",
+            TrustLevel::Trusted => "trusted",
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "new" => Some(TrustLevel::New),
+            "regular" => Some(TrustLevel::Regular),
+            "trusted" => Some(TrustLevel::Trusted),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TrustLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WhitelistEntry {
+    pub username: String,
+    pub level: TrustLevel,
+    pub clean_messages: u32,
+    // Время подтверждения, unix timestamp
+    pub confirmed_at: i64,
+}
+
+impl WhitelistEntry {
+    pub fn new(username: &str, confirmed_at: i64) -> Self {
+        Self {
+            username: username.to_string(),
+            level: TrustLevel::New,
+            clean_messages: 0,
+            confirmed_at,
+        }
+    }
+
+    // Формат строки: `<id> <username> | <level> <clean_messages> <confirmed_at>`.
+    // Старые строки без `|` считаются постоянными участниками.
+    pub fn parse(rest: &str) -> Self {
+        let (username, meta) = match rest.rsplit_once('|') {
+            Some((username, meta)) => (username.trim(), Some(meta)),
+            None => (rest.trim(), None),
+        };
+
+        let mut entry = Self {
+            username: username.to_string(),
+            level: TrustLevel::Regular,
+            clean_messages: 0,
+            confirmed_at: 0,
+        };
+
+        if let Some(meta) = meta {
+            let mut fields = meta.split_whitespace();
+            if let Some(level) = fields.next().and_then(TrustLevel::parse) {
+                entry.level = level;
+            }
+            entry.clean_messages = fields.next().and_then(|f| f.parse().ok()).unwrap_or(0);
+            entry.confirmed_at = fields.next().and_then(|f| f.parse().ok()).unwrap_or(0);
+        }
+        entry
+    }
+
+    pub fn format(&self) -> String {
+        format!(
+            "{} | {} {} {}",
+            self.username, self.level, self.clean_messages, self.confirmed_at
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TrustSettings {
+    pub regular_min_messages: u32,
+    pub regular_min_days: i64,
+    pub flood_window_secs: u64,
+    pub new_flood_limit: usize,
+    pub regular_flood_limit: usize,
+}
+
+impl TrustSettings {
+    pub fn from_config(config: &TrustConfig) -> Self {
+        Self {
+            regular_min_messages: config.regular_min_messages,
+            regular_min_days: config.regular_min_days,
+            flood_window_secs: config.flood_window_secs,
+            new_flood_limit: config.new_flood_limit,
+            regular_flood_limit: config.regular_flood_limit,
+        }
+    }
+
+    pub fn is_ready_for_regular(&self, entry: &WhitelistEntry, now: i64) -> bool {
+        entry.level == TrustLevel::New
+            && entry.clean_messages >= self.regular_min_messages
+            && now - entry.confirmed_at >= self.regular_min_days * 24 * 60 * 60
+    }
+
+    pub fn flood_limit(&self, level: TrustLevel) -> Option<usize> {
+        match level {
+            TrustLevel::New => Some(self.new_flood_limit),
+            TrustLevel::Regular => Some(self.regular_flood_limit),
+            TrustLevel::Trusted => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Violation {
+    Link,
+    Media,
+    Forward,
+    Flood,
+}
+
+impl Violation {
+    pub fn rule(&self) -> &'static str {
+        match self {
+            Violation::Link => "level_links",
+            Violation::Media => "level_media",
+            Violation::Forward => "level_forwards",
+            Violation::Flood => "flood",
+        }
+    }
+
+    // Ключ описания нарушения в каталоге сообщений
+    pub fn message_key(&self) -> &'static str {
+        match self {
+            Violation::Link => "violation.link",
+            Violation::Media => "violation.media",
+            Violation::Forward => "violation.forward",
+            Violation::Flood => "violation.flood",
+        }
+    }
+}
+
+// Ограничения по содержимому действуют только для новых участников
+pub fn content_violation(level: TrustLevel, msg: &Message) -> Option<Violation> {
+    if level != TrustLevel::New {
+        return None;
+    }
+    if msg.forward_origin().is_some() {
+        return Some(Violation::Forward);
+    }
+    if has_media(msg) {
+        return Some(Violation::Media);
+    }
+    if has_links(msg) {
+        return Some(Violation::Link);
+    }
+    None
+}
+
+fn has_media(msg: &Message) -> bool {
+    msg.photo().is_some()
+        || msg.video().is_some()
+        || msg.document().is_some()
+        || msg.animation().is_some()
+        || msg.sticker().is_some()
+        || msg.audio().is_some()
+        || msg.voice().is_some()
+        || msg.video_note().is_some()
+}
+
+fn has_links(msg: &Message) -> bool {
+    let entities = msg
+        .entities()
+        .into_iter()
+        .chain(msg.caption_entities())
+        .flatten();
+    for entity in entities {
+        if matches!(
+            entity.kind,
+            MessageEntityKind::Url | MessageEntityKind::TextLink { .. }
+        ) {
+            return true;
+        }
+    }
+    false
+}
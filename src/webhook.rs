@@ -0,0 +1,200 @@
+use std::error::Error;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use axum_server::tls_rustls::RustlsConfig;
+use log::{error, info};
+use teloxide::dispatching::{DefaultKey, Dispatcher};
+use teloxide::error_handlers::LoggingErrorHandler;
+use teloxide::types::InputFile;
+use teloxide::update_listeners::webhooks::{self, Options};
+use teloxide::update_listeners::UpdateListener;
+use url::Url;
+
+use crate::config::WebhookConfig;
+use crate::{Result, ThrottledBot};
+
+// Сколько ждать незавершённые запросы Telegram при остановке HTTPS-сервера
+const TLS_SHUTDOWN_GRACE: Duration = Duration::from_secs(10);
+
+type BotDispatcher = Dispatcher<ThrottledBot, Box<dyn Error + Send + Sync>, DefaultKey>;
+
+// Принимает обновления через вебхук, пока диспетчер не остановят. Вебхук
+// регистрируется после того, как сервер занял адрес, и снимается перед выходом.
+pub async fn dispatch(
+    dispatcher: &mut BotDispatcher,
+    bot: ThrottledBot,
+    config: &WebhookConfig,
+) -> Result<()> {
+    // Настройки уже проверены при загрузке конфигурации
+    let address: SocketAddr = config.listen.parse()?;
+    let url = Url::parse(config.url.as_deref().unwrap_or_default())?;
+    let listener = std::net::TcpListener::bind(address)
+        .map_err(|e| format!("failed to listen on {}: {}", address, e))?;
+    listener.set_nonblocking(true)?;
+    let tls = match (&config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key)) => {
+            // Из провайдеров rustls собран только ring; повторная установка не нужна
+            let _ = rustls::crypto::ring::default_provider().install_default();
+            let tls = RustlsConfig::from_pem_file(cert, key)
+                .await
+                .map_err(|e| format!("failed to load TLS certificate {}: {}", cert, e))?;
+            Some(tls)
+        }
+        _ => None,
+    };
+
+    let mut options = Options::new(address, url.clone());
+    if let Some(token) = &config.secret_token {
+        options = options.secret_token(token.clone());
+    }
+    if let (true, Some(cert)) = (config.upload_certificate, &config.tls_cert) {
+        options = options.certificate(InputFile::file(cert));
+    }
+    let (mut update_listener, stopped, router) = webhooks::axum_to_router(bot, options).await?;
+    let stop_token = update_listener.stop_token();
+    info!(
+        "Webhook registered at {}, listening on {} ({})",
+        url,
+        address,
+        if tls.is_some() { "HTTPS" } else { "HTTP" }
+    );
+
+    // `stopped` завершается после остановки диспетчера и снятия вебхука
+    let server = tokio::spawn(async move {
+        let result = match tls {
+            Some(tls) => {
+                let handle = axum_server::Handle::new();
+                let shutdown = handle.clone();
+                tokio::spawn(async move {
+                    stopped.await;
+                    shutdown.graceful_shutdown(Some(TLS_SHUTDOWN_GRACE));
+                });
+                axum_server::from_tcp_rustls(listener, tls)
+                    .handle(handle)
+                    .serve(router.into_make_service())
+                    .await
+            }
+            None => match tokio::net::TcpListener::from_std(listener) {
+                Ok(listener) => {
+                    axum::serve(listener, router)
+                        .with_graceful_shutdown(stopped)
+                        .await
+                }
+                Err(e) => Err(e),
+            },
+        };
+        if let Err(e) = result {
+            // Без сервера обновления не придут: останавливаем и диспетчер
+            error!("Webhook server failed: {}", e);
+            stop_token.stop();
+        }
+    });
+
+    dispatcher
+        .dispatch_with_listener(
+            update_listener,
+            LoggingErrorHandler::with_custom_text("An error from the webhook listener"),
+        )
+        .await;
+    server.await?;
+    info!("Webhook removed, server stopped");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use serde_json::json;
+    use teloxide::dptree;
+    use teloxide::types::Update;
+
+    use super::*;
+    use crate::test_api::{bot_user, FakeBotApi};
+
+    fn free_port() -> u16 {
+        std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    // Диспетчер занимает один рабочий поток, сервер вебхука работает на втором
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn registers_accepts_signed_updates_and_removes_webhook() {
+        let api = FakeBotApi::start().await;
+        api.respond("setWebhook", json!(true)).await;
+        api.respond("deleteWebhook", json!(true)).await;
+        api.respond("getMe", bot_user()).await;
+        let port = free_port();
+        let config = WebhookConfig {
+            enabled: true,
+            listen: format!("127.0.0.1:{}", port),
+            url: Some("https://bot.example.org/telegram/hook".to_string()),
+            secret_token: Some("test-secret".to_string()),
+            ..WebhookConfig::default()
+        };
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let handler = {
+            let received = received.clone();
+            dptree::entry().endpoint(move |update: Update| {
+                let received = received.clone();
+                async move {
+                    received.lock().unwrap().push(update.id.0);
+                    Ok(())
+                }
+            })
+        };
+        let mut dispatcher = Dispatcher::builder(api.bot(), handler).build();
+        let shutdown = dispatcher.shutdown_token();
+        let bot = api.bot();
+        let running = tokio::spawn(async move { dispatch(&mut dispatcher, bot, &config).await });
+
+        let registered = loop {
+            let requests = api.requests("setWebhook").await;
+            if !requests.is_empty() {
+                break requests;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        assert_eq!(
+            registered[0]["url"],
+            json!("https://bot.example.org/telegram/hook")
+        );
+        assert_eq!(registered[0]["secret_token"], json!("test-secret"));
+
+        let client = teloxide::net::default_reqwest_settings().build().unwrap();
+        let endpoint = format!("http://127.0.0.1:{}/telegram/hook", port);
+        let update = json!({
+            "update_id": 7,
+            "message": {
+                "message_id": 1,
+                "date": 0,
+                "chat": { "id": 1, "type": "private", "first_name": "User" },
+                "text": "hi"
+            }
+        });
+        let unsigned = client.post(&endpoint).json(&update).send().await.unwrap();
+        assert_eq!(unsigned.status().as_u16(), 401);
+        let signed = client
+            .post(&endpoint)
+            .header("X-Telegram-Bot-Api-Secret-Token", "test-secret")
+            .json(&update)
+            .send()
+            .await
+            .unwrap();
+        assert!(signed.status().is_success());
+
+        while received.lock().unwrap().is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        shutdown.shutdown().unwrap().await;
+        running.await.unwrap().unwrap();
+
+        assert_eq!(received.lock().unwrap().as_slice(), &[7]);
+        assert_eq!(api.requests("deleteWebhook").await.len(), 1);
+    }
+}
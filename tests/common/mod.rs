@@ -0,0 +1,211 @@
+#![allow(dead_code)]
+
+#[path = "../../src/test_api.rs"]
+mod test_api;
+
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicI32, AtomicU32, Ordering};
+use std::sync::Arc;
+
+use nstgbr::config::Config;
+use nstgbr::Groups;
+use serde_json::{json, Value};
+use teloxide::dptree;
+use teloxide::types::Update;
+
+pub use test_api::{sent_message, FakeBotApi};
+
+pub const GROUP: i64 = -1001234567890;
+pub const SECRET: &str = "open-sesame-42";
+pub const BOT_USERNAME: &str = "test_bot";
+pub const ADMIN_LOG: i64 = -1009876543210;
+
+type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+static NEXT_DIR: AtomicU32 = AtomicU32::new(0);
+static NEXT_ID: AtomicI32 = AtomicI32::new(1);
+
+// Бот с состоянием групп, поддельным Bot API и файлами во временном каталоге
+pub struct TestBot {
+    pub api: FakeBotApi,
+    pub dir: PathBuf,
+    groups: Arc<Groups>,
+}
+
+impl TestBot {
+    // `files` — содержимое файлов в каталоге бота до запуска (whitelist.txt, ...)
+    pub async fn start(files: &[(&str, &str)], configure: impl FnOnce(&mut Config)) -> Self {
+        let dir = std::env::temp_dir().join(format!(
+            "nstgbr-test-{}-{}",
+            std::process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, content) in files {
+            std::fs::write(dir.join(name), content).unwrap();
+        }
+
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        let mut config = Config::default();
+        config.bot.group_chat_id = GROUP;
+        config.bot.secret_code = Some(SECRET.to_string());
+        config.files.whitelist = path("whitelist.txt");
+        config.files.forbidden_patterns = path("forbidden_patterns.txt");
+        config.files.pending_users = path("pending_users.txt");
+        config.files.modlog = path("modlog.jsonl");
+        config.files.pattern_stats = path("pattern_stats.json");
+        // Ошибки API в тестах проверяются без повторов и пауз
+        config.telegram.retry_attempts = 1;
+        config.telegram.deletion_queue_file = path("deletion_queue.txt");
+        configure(&mut config);
+        nstgbr::init_telegram(&config.telegram);
+
+        let api = FakeBotApi::start().await;
+        api.respond("getChat", group_chat()).await;
+        let groups = nstgbr::load_groups(&api.bot(), &config, BOT_USERNAME.to_string()).await;
+        Self { api, dir, groups }
+    }
+
+    pub async fn dispatch(&self, update: Value) -> HandlerResult {
+        // UpdateKind разбирается только из текста: ключи из Value он не заимствует
+        let update: Update = serde_json::from_str(&update.to_string()).expect("valid update");
+        match nstgbr::schema()
+            .dispatch(dptree::deps![self.api.bot(), update, self.groups.clone()])
+            .await
+        {
+            ControlFlow::Break(result) => result,
+            ControlFlow::Continue(_) => panic!("update was not handled"),
+        }
+    }
+
+    pub fn file(&self, name: &str) -> String {
+        std::fs::read_to_string(self.dir.join(name)).unwrap_or_default()
+    }
+}
+
+impl Drop for TestBot {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+pub fn group_chat() -> Value {
+    json!({
+        "id": GROUP,
+        "type": "supergroup",
+        "title": "Test group",
+        "accent_color_id": 0,
+        "max_reaction_count": 0,
+        "permissions": { "can_send_messages": true }
+    })
+}
+
+pub fn user(id: u64) -> Value {
+    json!({ "id": id, "is_bot": false, "first_name": format!("User{}", id), "language_code": "ru" })
+}
+
+pub fn chat_member(id: u64, status: &str) -> Value {
+    json!({ "user": user(id), "status": status })
+}
+
+pub fn owner_member(id: u64) -> Value {
+    json!({ "user": user(id), "status": "creator", "is_anonymous": false })
+}
+
+fn message_update(chat: Value, from: u64, fields: Value) -> Value {
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    let mut message = json!({
+        "message_id": id,
+        "date": 0,
+        "chat": chat,
+        "from": user(from),
+    });
+    for (key, value) in fields.as_object().unwrap() {
+        message[key] = value.clone();
+    }
+    json!({ "update_id": id, "message": message })
+}
+
+pub fn group_message(from: u64, text: &str) -> Value {
+    message_update(
+        json!({ "id": GROUP, "type": "supergroup", "title": "Test group" }),
+        from,
+        json!({ "text": text }),
+    )
+}
+
+// Фото без подписи
+pub fn group_photo(from: u64) -> Value {
+    message_update(
+        json!({ "id": GROUP, "type": "supergroup", "title": "Test group" }),
+        from,
+        json!({ "photo": [{
+            "file_id": "photo", "file_unique_id": "photo", "width": 1, "height": 1, "file_size": 1
+        }] }),
+    )
+}
+
+pub fn private_message(from: u64, text: &str) -> Value {
+    message_update(
+        json!({ "id": from, "type": "private", "first_name": format!("User{}", from) }),
+        from,
+        json!({ "text": text }),
+    )
+}
+
+pub fn member_joined(id: u64) -> Value {
+    message_update(
+        json!({ "id": GROUP, "type": "supergroup", "title": "Test group" }),
+        id,
+        json!({ "new_chat_members": [user(id)] }),
+    )
+}
+
+// Сообщение в группе от имени канала
+pub fn channel_message(channel: i64, text: &str) -> Value {
+    message_update(
+        json!({ "id": GROUP, "type": "supergroup", "title": "Test group" }),
+        136817688,
+        json!({
+            "sender_chat": { "id": channel, "type": "channel", "title": format!("Channel{}", channel) },
+            "text": text
+        }),
+    )
+}
+
+// Фото с подписью от имени канала
+pub fn channel_photo(channel: i64, caption: &str) -> Value {
+    message_update(
+        json!({ "id": GROUP, "type": "supergroup", "title": "Test group" }),
+        136817688,
+        json!({
+            "sender_chat": { "id": channel, "type": "channel", "title": format!("Channel{}", channel) },
+            "photo": [{
+                "file_id": "photo", "file_unique_id": "photo", "width": 1, "height": 1, "file_size": 1
+            }],
+            "caption": caption
+        }),
+    )
+}
+
+// Нажатие кнопки под сообщением бота в чате администраторов
+pub fn callback_query(from: u64, data: &str) -> Value {
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    json!({
+        "update_id": id,
+        "callback_query": {
+            "id": id.to_string(),
+            "from": user(from),
+            "chat_instance": "test",
+            "data": data,
+            "message": {
+                "message_id": 1,
+                "date": 0,
+                "chat": { "id": ADMIN_LOG, "type": "supergroup", "title": "Admin log" },
+                "text": "record"
+            }
+        }
+    })
+}
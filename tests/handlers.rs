@@ -0,0 +1,602 @@
+// Обработчики обновлений против поддельного Bot API, без сети
+mod common;
+
+use serde_json::json;
+
+use common::*;
+
+const NEWCOMER: u64 = 100;
+const MEMBER: u64 = 200;
+const CHANNEL: i64 = -1005550000;
+
+#[tokio::test]
+async fn joined_member_is_tracked_as_pending() {
+    let bot = TestBot::start(&[], |_| {}).await;
+
+    bot.dispatch(member_joined(NEWCOMER)).await.unwrap();
+
+    assert!(bot.file("pending_users.txt").starts_with("100 "));
+    assert!(bot.api.requests("sendMessage").await.is_empty());
+}
+
+#[tokio::test]
+async fn unverified_message_is_deleted_and_prompted() {
+    let bot = TestBot::start(&[], |_| {}).await;
+    bot.api.respond("deleteMessage", json!(true)).await;
+    bot.api
+        .respond("sendMessage", sent_message(GROUP, 900, "prompt"))
+        .await;
+
+    bot.dispatch(group_message(NEWCOMER, "привет всем"))
+        .await
+        .unwrap();
+
+    let deleted = bot.api.requests("deleteMessage").await;
+    assert_eq!(deleted.len(), 1);
+    assert_eq!(deleted[0]["chat_id"], json!(GROUP));
+    let sent = bot.api.requests("sendMessage").await;
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0]["chat_id"], json!(GROUP));
+    assert!(bot.file("modlog.jsonl").contains("unverified"));
+}
+
+#[tokio::test]
+async fn unverified_photo_is_deleted_and_prompted() {
+    let bot = TestBot::start(&[], |_| {}).await;
+    bot.api.respond("deleteMessage", json!(true)).await;
+    bot.api
+        .respond("sendMessage", sent_message(GROUP, 911, "prompt"))
+        .await;
+
+    bot.dispatch(group_photo(NEWCOMER)).await.unwrap();
+
+    assert_eq!(bot.api.requests("deleteMessage").await.len(), 1);
+    assert_eq!(bot.api.requests("sendMessage").await.len(), 1);
+}
+
+#[tokio::test]
+async fn failed_prompt_does_not_block_the_next_one() {
+    let bot = TestBot::start(&[], |_| {}).await;
+    bot.api.respond("deleteMessage", json!(true)).await;
+    bot.api
+        .fail("sendMessage", 400, "Bad Request: not enough rights")
+        .await;
+
+    let result = bot.dispatch(group_message(NEWCOMER, "привет")).await;
+    assert!(result.is_err());
+    let result = bot
+        .dispatch(group_message(NEWCOMER, "привет ещё раз"))
+        .await;
+    assert!(result.is_err());
+
+    assert_eq!(bot.api.requests("sendMessage").await.len(), 2);
+}
+
+#[tokio::test]
+async fn unverified_user_is_restricted_and_sent_to_private_chat() {
+    let bot = TestBot::start(&[], |config| {
+        config.verification.mode = "restrict".to_string();
+    })
+    .await;
+    bot.api.respond("deleteMessage", json!(true)).await;
+    bot.api.respond("restrictChatMember", json!(true)).await;
+    bot.api
+        .respond("sendMessage", sent_message(GROUP, 901, "prompt"))
+        .await;
+
+    bot.dispatch(group_message(NEWCOMER, "привет всем"))
+        .await
+        .unwrap();
+
+    let restricted = bot.api.requests("restrictChatMember").await;
+    assert_eq!(restricted.len(), 1);
+    assert_eq!(restricted[0]["user_id"], json!(NEWCOMER));
+    assert_eq!(
+        restricted[0]["permissions"]["can_send_messages"],
+        json!(null)
+    );
+    let sent = bot.api.requests("sendMessage").await;
+    assert_eq!(
+        sent[0]["reply_markup"]["inline_keyboard"][0][0]["url"],
+        json!(format!("https://t.me/{}?start=confirm", BOT_USERNAME))
+    );
+}
+
+#[tokio::test]
+async fn confirm_link_whitelists_member_and_lifts_restrictions() {
+    let bot = TestBot::start(&[], |config| {
+        config.verification.mode = "restrict".to_string();
+    })
+    .await;
+    bot.api.respond("deleteMessage", json!(true)).await;
+    bot.api
+        .respond("getChatMember", chat_member(NEWCOMER, "member"))
+        .await;
+    bot.api.respond("restrictChatMember", json!(true)).await;
+    bot.api
+        .respond("sendMessage", sent_message(NEWCOMER as i64, 902, "ok"))
+        .await;
+
+    bot.dispatch(private_message(NEWCOMER, "/start confirm"))
+        .await
+        .unwrap();
+
+    assert!(bot.file("whitelist.txt").starts_with("100 "));
+    let lifted = bot.api.requests("restrictChatMember").await;
+    assert_eq!(lifted.len(), 1);
+    assert_eq!(lifted[0]["chat_id"], json!(GROUP));
+    assert_eq!(lifted[0]["permissions"]["can_send_messages"], json!(true));
+    let sent = bot.api.requests("sendMessage").await;
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0]["chat_id"], json!(NEWCOMER));
+}
+
+#[tokio::test]
+async fn confirm_from_non_member_is_refused() {
+    let bot = TestBot::start(&[], |_| {}).await;
+    bot.api.respond("deleteMessage", json!(true)).await;
+    bot.api
+        .respond("getChatMember", chat_member(NEWCOMER, "left"))
+        .await;
+    bot.api
+        .respond("sendMessage", sent_message(NEWCOMER as i64, 903, "no"))
+        .await;
+
+    bot.dispatch(private_message(NEWCOMER, "/confirm"))
+        .await
+        .unwrap();
+
+    assert!(bot.file("whitelist.txt").is_empty());
+    assert_eq!(bot.api.requests("sendMessage").await.len(), 1);
+}
+
+#[tokio::test]
+async fn secret_code_whitelists_user_and_removes_code() {
+    let bot = TestBot::start(&[], |_| {}).await;
+    bot.api.respond("deleteMessage", json!(true)).await;
+
+    bot.dispatch(group_message(NEWCOMER, SECRET)).await.unwrap();
+
+    assert!(bot.file("whitelist.txt").starts_with("100 "));
+    assert_eq!(bot.api.requests("deleteMessage").await.len(), 1);
+    assert!(bot.api.requests("sendMessage").await.is_empty());
+}
+
+#[tokio::test]
+async fn wrong_secret_code_is_treated_as_unverified_message() {
+    let bot = TestBot::start(&[], |_| {}).await;
+    bot.api.respond("deleteMessage", json!(true)).await;
+    bot.api
+        .respond("sendMessage", sent_message(GROUP, 904, "prompt"))
+        .await;
+
+    bot.dispatch(group_message(NEWCOMER, "open-sesame"))
+        .await
+        .unwrap();
+
+    assert!(bot.file("whitelist.txt").is_empty());
+    assert_eq!(bot.api.requests("sendMessage").await.len(), 1);
+}
+
+#[tokio::test]
+async fn forbidden_pattern_is_deleted_with_warning() {
+    let bot = TestBot::start(
+        &[
+            ("whitelist.txt", "200 member | regular 0 0\n"),
+            ("forbidden_patterns.txt", "*casino\n"),
+        ],
+        |_| {},
+    )
+    .await;
+    bot.api.respond("deleteMessage", json!(true)).await;
+    bot.api
+        .respond("sendMessage", sent_message(GROUP, 905, "warning"))
+        .await;
+
+    bot.dispatch(group_message(MEMBER, "Лучшее CASINO в городе"))
+        .await
+        .unwrap();
+
+    assert_eq!(bot.api.requests("deleteMessage").await.len(), 1);
+    let sent = bot.api.requests("sendMessage").await;
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0]["chat_id"], json!(GROUP));
+    assert!(bot.file("modlog.jsonl").contains("casino"));
+}
+
+#[tokio::test]
+async fn clean_message_from_member_is_left_alone() {
+    let bot = TestBot::start(
+        &[
+            ("whitelist.txt", "200 member | regular 0 0\n"),
+            ("forbidden_patterns.txt", "*casino\n"),
+        ],
+        |_| {},
+    )
+    .await;
+
+    bot.dispatch(group_message(MEMBER, "добрый вечер"))
+        .await
+        .unwrap();
+
+    assert!(bot.api.requests("deleteMessage").await.is_empty());
+    assert!(bot.api.requests("sendMessage").await.is_empty());
+}
+
+#[tokio::test]
+async fn failed_deletion_still_prompts_without_record() {
+    let bot = TestBot::start(&[], |_| {}).await;
+    bot.api
+        .fail(
+            "deleteMessage",
+            400,
+            "Bad Request: message can't be deleted",
+        )
+        .await;
+    bot.api
+        .respond("sendMessage", sent_message(GROUP, 906, "prompt"))
+        .await;
+
+    bot.dispatch(group_message(NEWCOMER, "привет всем"))
+        .await
+        .unwrap();
+
+    assert_eq!(bot.api.requests("sendMessage").await.len(), 1);
+    assert!(bot.file("modlog.jsonl").is_empty());
+}
+
+#[tokio::test]
+async fn membership_check_error_is_reported_to_user() {
+    let bot = TestBot::start(&[], |_| {}).await;
+    bot.api.respond("deleteMessage", json!(true)).await;
+    bot.api
+        .fail("getChatMember", 400, "Bad Request: chat not found")
+        .await;
+    bot.api
+        .respond("sendMessage", sent_message(NEWCOMER as i64, 907, "error"))
+        .await;
+
+    bot.dispatch(private_message(NEWCOMER, "/confirm"))
+        .await
+        .unwrap();
+
+    assert!(bot.file("whitelist.txt").is_empty());
+    assert_eq!(bot.api.requests("sendMessage").await.len(), 1);
+}
+
+#[tokio::test]
+async fn blocked_bot_error_is_returned_from_handler() {
+    let bot = TestBot::start(&[], |_| {}).await;
+    bot.api
+        .fail("sendMessage", 403, "Forbidden: bot was blocked by the user")
+        .await;
+
+    let result = bot.dispatch(private_message(NEWCOMER, "/start")).await;
+
+    assert!(result.is_err());
+    assert_eq!(bot.api.requests("sendMessage").await.len(), 1);
+}
+
+#[tokio::test]
+async fn deleted_channel_post_lets_admin_whitelist_the_channel() {
+    let bot = TestBot::start(&[], |config| {
+        config.bot.admin_log_chat_id = Some(ADMIN_LOG);
+    })
+    .await;
+    bot.api.respond("deleteMessage", json!(true)).await;
+    bot.api
+        .respond("sendMessage", sent_message(ADMIN_LOG, 910, "record"))
+        .await;
+    bot.api
+        .respond("editMessageText", sent_message(ADMIN_LOG, 910, "record"))
+        .await;
+    bot.api.respond("getChatMember", owner_member(MEMBER)).await;
+    bot.api.respond("answerCallbackQuery", json!(true)).await;
+
+    bot.dispatch(channel_message(CHANNEL, "реклама"))
+        .await
+        .unwrap();
+    let sent = bot.api.requests("sendMessage").await;
+    assert_eq!(
+        sent[0]["reply_markup"]["inline_keyboard"][0][1]["callback_data"],
+        json!("undo:wl:1")
+    );
+
+    bot.dispatch(callback_query(MEMBER, "undo:wl:1"))
+        .await
+        .unwrap();
+    assert!(bot
+        .file("whitelist.txt")
+        .starts_with(&format!("{} Channel{}", CHANNEL, CHANNEL)));
+
+    bot.dispatch(channel_message(CHANNEL, "новости канала"))
+        .await
+        .unwrap();
+    assert_eq!(bot.api.requests("deleteMessage").await.len(), 1);
+}
+
+#[tokio::test]
+async fn forbidden_caption_of_whitelisted_channel_is_deleted() {
+    let bot = TestBot::start(
+        &[
+            (
+                "whitelist.txt",
+                &format!("{} Channel{}\n", CHANNEL, CHANNEL),
+            ),
+            ("forbidden_patterns.txt", "*casino\n"),
+        ],
+        |_| {},
+    )
+    .await;
+    bot.api.respond("deleteMessage", json!(true)).await;
+    bot.api
+        .respond("sendMessage", sent_message(GROUP, 911, "warning"))
+        .await;
+
+    bot.dispatch(channel_photo(CHANNEL, "Лучшее casino"))
+        .await
+        .unwrap();
+
+    assert_eq!(bot.api.requests("deleteMessage").await.len(), 1);
+    assert!(bot.file("modlog.jsonl").contains("casino"));
+}
+
+#[tokio::test]
+async fn clean_messages_of_newcomer_do_not_rewrite_whitelist() {
+    let whitelist = "300 newbie | new 0 0\n200 member | regular 0 0\n";
+    let bot = TestBot::start(&[("whitelist.txt", whitelist)], |_| {}).await;
+
+    bot.dispatch(group_message(300, "всем привет"))
+        .await
+        .unwrap();
+
+    assert!(bot.api.requests("deleteMessage").await.is_empty());
+    assert_eq!(bot.file("whitelist.txt"), whitelist);
+}
+
+#[tokio::test]
+async fn ending_lockdown_lifts_restrictions_it_applied() {
+    let bot = TestBot::start(&[], |_| {}).await;
+    bot.api.respond("deleteMessage", json!(true)).await;
+    bot.api.respond("getChatMember", owner_member(MEMBER)).await;
+    bot.api.respond("restrictChatMember", json!(true)).await;
+    bot.api
+        .respond("sendMessage", sent_message(GROUP, 912, "lockdown"))
+        .await;
+
+    bot.dispatch(group_message(MEMBER, "/lockdown on"))
+        .await
+        .unwrap();
+    bot.dispatch(member_joined(NEWCOMER)).await.unwrap();
+    bot.dispatch(group_message(MEMBER, "/lockdown off"))
+        .await
+        .unwrap();
+
+    // Служебное сообщение о входе тоже ограничивает новичка, снятие — одно
+    let restricted = bot.api.requests("restrictChatMember").await;
+    let (lifted, applied): (Vec<_>, Vec<_>) = restricted
+        .iter()
+        .partition(|r| r["permissions"]["can_send_messages"] == json!(true));
+    assert!(!applied.is_empty());
+    assert!(applied.iter().all(|r| r["user_id"] == json!(NEWCOMER)));
+    assert_eq!(lifted.len(), 1);
+    assert_eq!(lifted[0]["user_id"], json!(NEWCOMER));
+    assert_eq!(
+        restricted.last().unwrap()["permissions"]["can_send_messages"],
+        json!(true)
+    );
+}
+
+#[tokio::test]
+async fn failed_deletion_discards_archived_evidence() {
+    let archive = std::env::temp_dir().join(format!("nstgbr-evidence-{}", std::process::id()));
+    let dir = archive.to_str().unwrap().to_string();
+    let bot = TestBot::start(&[], |config| {
+        config.evidence.mode = "archive".to_string();
+        config.evidence.dir = dir;
+    })
+    .await;
+    bot.api
+        .fail(
+            "deleteMessage",
+            400,
+            "Bad Request: message can't be deleted",
+        )
+        .await;
+    bot.api
+        .respond("sendMessage", sent_message(GROUP, 913, "prompt"))
+        .await;
+
+    bot.dispatch(group_message(NEWCOMER, "привет всем"))
+        .await
+        .unwrap();
+
+    assert_eq!(std::fs::read_dir(&archive).unwrap().count(), 0);
+    std::fs::remove_dir_all(&archive).unwrap();
+}
+
+#[tokio::test]
+async fn failed_report_action_keeps_report_open() {
+    let whitelist = "200 member | regular 0 0\n";
+    let bot = TestBot::start(&[("whitelist.txt", whitelist)], |config| {
+        config.bot.admin_log_chat_id = Some(ADMIN_LOG);
+    })
+    .await;
+    bot.api
+        .fail(
+            "deleteMessage",
+            400,
+            "Bad Request: message can't be deleted",
+        )
+        .await;
+    bot.api
+        .respond("sendMessage", sent_message(ADMIN_LOG, 914, "report"))
+        .await;
+    bot.api
+        .respond("editMessageText", sent_message(ADMIN_LOG, 914, "report"))
+        .await;
+    bot.api.respond("answerCallbackQuery", json!(true)).await;
+
+    let reported = channel_message(CHANNEL, "реклама")["message"].clone();
+    let reported_id = reported["message_id"].as_i64().unwrap();
+    let mut report = group_message(MEMBER, "/report");
+    report["message"]["reply_to_message"] = reported;
+    bot.dispatch(report).await.unwrap();
+    bot.api.respond("getChatMember", owner_member(MEMBER)).await;
+
+    bot.dispatch(callback_query(
+        MEMBER,
+        &format!("report:delete:{}", reported_id),
+    ))
+    .await
+    .unwrap();
+    assert!(bot.api.requests("editMessageText").await.is_empty());
+
+    bot.dispatch(callback_query(
+        MEMBER,
+        &format!("report:dismiss:{}", reported_id),
+    ))
+    .await
+    .unwrap();
+    let edited = bot.api.requests("editMessageText").await;
+    assert_eq!(edited.len(), 1);
+    assert!(edited[0]["text"]
+        .as_str()
+        .unwrap()
+        .contains("Жалоба отклонена"));
+}
+
+#[tokio::test]
+async fn approved_appeal_of_deletion_whitelists_user() {
+    let bot = TestBot::start(&[], |config| {
+        config.bot.admin_log_chat_id = Some(ADMIN_LOG);
+    })
+    .await;
+    bot.api.respond("deleteMessage", json!(true)).await;
+    bot.api
+        .respond("sendMessage", sent_message(ADMIN_LOG, 915, "appeal"))
+        .await;
+    bot.api
+        .respond("editMessageText", sent_message(ADMIN_LOG, 915, "appeal"))
+        .await;
+    bot.api.respond("answerCallbackQuery", json!(true)).await;
+
+    bot.dispatch(group_message(NEWCOMER, "привет всем"))
+        .await
+        .unwrap();
+    bot.dispatch(private_message(NEWCOMER, "/appeal"))
+        .await
+        .unwrap();
+    bot.dispatch(private_message(NEWCOMER, "я просто поздоровался"))
+        .await
+        .unwrap();
+    let sent = bot.api.requests("sendMessage").await;
+    assert!(sent.iter().any(
+        |m| m["reply_markup"]["inline_keyboard"][0][0]["callback_data"]
+            == json!("appeal:approve:1")
+    ));
+
+    bot.api.respond("getChatMember", owner_member(MEMBER)).await;
+    bot.dispatch(callback_query(MEMBER, "appeal:approve:1"))
+        .await
+        .unwrap();
+
+    assert!(bot
+        .file("whitelist.txt")
+        .starts_with(&format!("{} ", NEWCOMER)));
+    bot.dispatch(group_message(NEWCOMER, "ещё раз привет"))
+        .await
+        .unwrap();
+    assert_eq!(bot.api.requests("deleteMessage").await.len(), 1);
+}
+
+#[tokio::test]
+async fn failed_appeal_approval_keeps_appeal_open() {
+    let bot = TestBot::start(&[], |config| {
+        config.bot.admin_log_chat_id = Some(ADMIN_LOG);
+    })
+    .await;
+    bot.api.respond("deleteMessage", json!(true)).await;
+    bot.api
+        .respond("sendMessage", sent_message(ADMIN_LOG, 916, "appeal"))
+        .await;
+    bot.api
+        .fail_in_chat("sendMessage", GROUP, 400, "Bad Request: not enough rights")
+        .await;
+    bot.api.respond("answerCallbackQuery", json!(true)).await;
+    bot.api.respond("getChatMember", owner_member(MEMBER)).await;
+
+    let _ = bot.dispatch(group_message(NEWCOMER, "привет всем")).await;
+    bot.dispatch(private_message(NEWCOMER, "/appeal"))
+        .await
+        .unwrap();
+    bot.dispatch(private_message(NEWCOMER, "я просто поздоровался"))
+        .await
+        .unwrap();
+    let notified = bot.api.requests("sendMessage").await.len();
+
+    // Вернуть сообщение в группу не удалось: апелляция и кнопки остаются
+    bot.dispatch(callback_query(MEMBER, "appeal:approve:1"))
+        .await
+        .unwrap();
+    bot.dispatch(callback_query(MEMBER, "appeal:approve:1"))
+        .await
+        .unwrap();
+
+    let sent = bot.api.requests("sendMessage").await;
+    assert_eq!(sent.len(), notified + 2);
+    assert!(sent[notified..]
+        .iter()
+        .all(|m| m["chat_id"] == json!(GROUP)));
+    assert!(bot.api.requests("editMessageText").await.is_empty());
+    assert!(bot.file("whitelist.txt").is_empty());
+}
+
+#[tokio::test]
+async fn commands_are_removed_from_the_group() {
+    let bot = TestBot::start(&[], |_| {}).await;
+    bot.api.respond("deleteMessage", json!(true)).await;
+    bot.api.respond("getChatMember", owner_member(MEMBER)).await;
+    bot.api
+        .respond("sendMessage", sent_message(GROUP, 916, "reply"))
+        .await;
+
+    let mut commands = Vec::new();
+    for text in ["/whois", "/trust", "/lockdown", "/patterns", "/report"] {
+        let update = group_message(MEMBER, text);
+        commands.push(update["message"]["message_id"].clone());
+        bot.dispatch(update).await.unwrap();
+    }
+
+    let deleted: Vec<_> = bot
+        .api
+        .requests("deleteMessage")
+        .await
+        .into_iter()
+        .map(|r| r["message_id"].clone())
+        .collect();
+    assert_eq!(deleted, commands);
+    assert!(bot.api.requests("restrictChatMember").await.is_empty());
+}
+
+#[tokio::test]
+async fn confirm_in_group_from_member_is_removed_and_answered() {
+    let whitelist = "200 member | regular 0 0\n";
+    let bot = TestBot::start(&[("whitelist.txt", whitelist)], |_| {}).await;
+    bot.api.respond("deleteMessage", json!(true)).await;
+    bot.api
+        .respond("sendMessage", sent_message(GROUP, 917, "already"))
+        .await;
+
+    let update = group_message(MEMBER, "/confirm");
+    let command = update["message"]["message_id"].clone();
+    bot.dispatch(update).await.unwrap();
+
+    let deleted = bot.api.requests("deleteMessage").await;
+    assert_eq!(deleted.len(), 1);
+    assert_eq!(deleted[0]["message_id"], command);
+    let sent = bot.api.requests("sendMessage").await;
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0]["chat_id"], json!(GROUP));
+    assert!(bot.api.requests("getChatMember").await.is_empty());
+}